@@ -0,0 +1,39 @@
+#!/usr/bin/env bash
+# The timings in the README's table: each sample program run with a release
+# build on the tree walker and on the VM, best of seven runs (the --time
+# figure, so start-up isn't counted). Pass a count to change the number of
+# runs, and program files to time other programs.
+#
+#     scripts/bench.sh
+#     scripts/bench.sh 3 sample_code/fib.scm
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+runs=${1:-7}
+shift || true
+programs=("$@")
+if [ ${#programs[@]} -eq 0 ]; then
+    programs=(sample_code/fib.scm sample_code/mandelbrot.scm)
+fi
+
+cargo build --release --quiet
+rs_lisp=target/release/rs_lisp
+
+# The best time of $runs runs of rs_lisp with these arguments
+best_of() {
+    local best=""
+    for _ in $(seq "$runs"); do
+        local elapsed
+        elapsed=$("$rs_lisp" --time "$@" 2>&1 >/dev/null | sed -n 's/^Elapsed: \(.*\)s$/\1/p')
+        if [ -z "$best" ] || awk -v a="$elapsed" -v b="$best" 'BEGIN { exit !(a < b) }'; then
+            best=$elapsed
+        fi
+    done
+    echo "${best}s"
+}
+
+echo "| Program | Tree walker | VM |"
+echo "|---|---|---|"
+for program in "${programs[@]}"; do
+    echo "| $program | $(best_of "$program") | $(best_of --vm "$program") |"
+done
@@ -0,0 +1,137 @@
+use crate::primitives::Cell;
+use crate::primitives::LogicalOperator;
+use crate::primitives::NumericOperator;
+use crate::symbolic_expression::SExpression;
+use crate::symbols::symbol_name;
+
+// The instructions for the stack machine in vm.rs. Most of them take their
+// operands off the top of the value stack and push a result; jumps hold the
+// index of the instruction to continue from.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    // Push constants[index]
+    Constant(usize),
+    // Variables with a lexical address: depth, slot and symbol number
+    GetLocal(usize, usize, i32),
+    SetLocal(usize, usize, i32),
+    DefineLocal(usize, i32),
+    // Variables the lexical analysis couldn't place, looked up by symbol number
+    GetGlobal(i32),
+    SetGlobal(i32),
+    DefineGlobal(i32),
+    Pop,
+    Jump(usize),
+    // Pop the value on top and jump if it's false or true
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // A list at the head of a list is only called if it gives a procedure;
+    // otherwise its value is the result and the arguments are skipped.
+    JumpUnlessProcedure(usize),
+    // Call the procedure below the given number of arguments
+    Call(usize),
+    // A call whose result is returned straight away reuses the caller's frame
+    TailCall(usize),
+    Return,
+    // Make a procedure from the lambda template in constants[index]
+    MakeClosure(usize),
+    Arithmetic(NumericOperator, usize),
+    Compare(LogicalOperator, usize),
+    // Print the given number of values, and a new line if true
+    Output(usize, bool),
+    // Print the given number of values as a prompt and read a line
+    Input(usize),
+}
+
+// A compiled top-level form or lambda body
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<SExpression>,
+}
+
+impl Chunk {
+    pub fn add_constant(&mut self, value: SExpression) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    // Returns the index of the instruction so jumps can be patched later
+    pub fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    // Point the jump at 'index' to the next instruction to be emitted
+    pub fn patch_jump(&mut self, index: usize) {
+        let target = self.code.len();
+        self.code[index] = match self.code[index] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfTrue(_) => Op::JumpIfTrue(target),
+            Op::JumpUnlessProcedure(_) => Op::JumpUnlessProcedure(target),
+            other => other,
+        };
+    }
+
+    // One instruction per line with its operands spelled out, for --disassemble
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut lines = vec![format!("== {} ==", name)];
+        for (index, op) in self.code.iter().enumerate() {
+            let operands = match *op {
+                Op::Constant(c) => format!("{:<4} ; {}", c, self.print_constant(c)),
+                Op::GetLocal(depth, slot, symbol) | Op::SetLocal(depth, slot, symbol) => {
+                    format!("{} {:<2} ; {}", depth, slot, symbol_name(symbol))
+                }
+                Op::DefineLocal(slot, symbol) => format!("{:<4} ; {}", slot, symbol_name(symbol)),
+                Op::GetGlobal(symbol) | Op::SetGlobal(symbol) | Op::DefineGlobal(symbol) => {
+                    format!("{:<4} ; {}", symbol, symbol_name(symbol))
+                }
+                Op::Jump(target)
+                | Op::JumpIfFalse(target)
+                | Op::JumpIfTrue(target)
+                | Op::JumpUnlessProcedure(target) => format!("-> {:04}", target),
+                Op::Call(argc) | Op::TailCall(argc) | Op::Input(argc) => argc.to_string(),
+                Op::MakeClosure(c) => format!("{:<4} ; {}", c, self.print_constant(c)),
+                Op::Arithmetic(operator, argc) => format!("{} {}", operator.print(), argc),
+                Op::Compare(operator, argc) => format!("{} {}", operator.print(), argc),
+                Op::Output(argc, newline) => format!("{} {}", argc, newline),
+                Op::Pop | Op::Return => String::new(),
+            };
+            let line = format!("{:04} {:<22}{}", index, op_name(op), operands);
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    fn print_constant(&self, index: usize) -> String {
+        match &self.constants[index] {
+            SExpression::Cell(Cell::Str(s)) => format!("\"{}\"", s),
+            other => other.print(),
+        }
+    }
+} // impl Chunk
+
+fn op_name(op: &Op) -> &'static str {
+    match op {
+        Op::Constant(_) => "CONSTANT",
+        Op::GetLocal(..) => "GET_LOCAL",
+        Op::SetLocal(..) => "SET_LOCAL",
+        Op::DefineLocal(..) => "DEFINE_LOCAL",
+        Op::GetGlobal(_) => "GET_GLOBAL",
+        Op::SetGlobal(_) => "SET_GLOBAL",
+        Op::DefineGlobal(_) => "DEFINE_GLOBAL",
+        Op::Pop => "POP",
+        Op::Jump(_) => "JUMP",
+        Op::JumpIfFalse(_) => "JUMP_IF_FALSE",
+        Op::JumpIfTrue(_) => "JUMP_IF_TRUE",
+        Op::JumpUnlessProcedure(_) => "JUMP_UNLESS_PROCEDURE",
+        Op::Call(_) => "CALL",
+        Op::TailCall(_) => "TAIL_CALL",
+        Op::Return => "RETURN",
+        Op::MakeClosure(_) => "MAKE_CLOSURE",
+        Op::Arithmetic(..) => "ARITHMETIC",
+        Op::Compare(..) => "COMPARE",
+        Op::Output(..) => "OUTPUT",
+        Op::Input(_) => "INPUT",
+    }
+}
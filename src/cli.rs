@@ -0,0 +1,143 @@
+use rs_lisp::{Backend, Redefinition};
+
+pub const USAGE: &str = "\
+Usage: rs_lisp [options] [program file | -] [arguments...]
+
+With no program file the REPL starts, unless standard input is a pipe or a
+file, in which case the program is read from there. - reads it from standard
+input too. Arguments after the program file are passed to it, and (command-line)
+gives them back.
+
+Options:
+  -e expression       run the expression; can be given more than once, and
+                      then every argument that isn't an option is passed on
+  -i                  start the REPL after running the program
+  --print-results     print the value of each top-level form
+  --keep-going        report an error in a top-level form and go on to the
+                      next one, instead of stopping
+  --vm                run on the bytecode VM
+  --disassemble       print the bytecode without running it
+  --time              report the run time on stderr
+  --strict            redefining a top-level name is an error
+  --warn-redefine     redefining a top-level name gives a warning
+  -I directory        also look in directory for files to load
+  --config file       read the REPL settings from file
+  --history file      keep the REPL history in file
+  --init file         run file when the REPL starts
+  -h, --help          show this message";
+
+// Where the program to run comes from
+pub enum Program {
+    // -e expressions, run in order
+    Expressions(Vec<String>),
+    File(String),
+    // - on the command line, or a pipe
+    Stdin,
+    // Nothing to run; just the REPL
+    Nothing,
+}
+
+pub struct Options {
+    pub backend: Backend,
+    pub disassemble: bool,
+    pub time: bool,
+    pub redefinition: Redefinition,
+    pub search_path: Vec<String>,
+    pub config_file: Option<String>,
+    pub history_file: Option<String>,
+    pub init_file: Option<String>,
+    pub interactive: bool,
+    pub print_results: bool,
+    pub keep_going: bool,
+    pub help: bool,
+    pub program: Program,
+    // What (command-line) gives: the program's name, then its arguments
+    pub command_line: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            backend: Backend::TreeWalker,
+            disassemble: false,
+            time: false,
+            redefinition: Redefinition::Allow,
+            search_path: Vec::new(),
+            config_file: None,
+            history_file: None,
+            init_file: None,
+            interactive: false,
+            print_results: false,
+            keep_going: false,
+            help: false,
+            program: Program::Nothing,
+            command_line: vec![String::from("rs_lisp")],
+        }
+    }
+}
+
+// The value after an option like -I
+fn option_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} needs a value", option))
+}
+
+// Options come first. The first argument that isn't one is the program
+// file, and everything after it belongs to the program.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut expressions = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" => expressions.push(option_value(&mut args, &arg)?),
+            "-i" => options.interactive = true,
+            "--print-results" => options.print_results = true,
+            "--keep-going" => options.keep_going = true,
+            "--vm" => options.backend = Backend::Vm,
+            "--disassemble" => options.disassemble = true,
+            "--time" => options.time = true,
+            // A top-level define of something already defined is an error
+            "--strict" => options.redefinition = Redefinition::Error,
+            "--warn-redefine" => options.redefinition = Redefinition::Warn,
+            // Another directory for load and include to search
+            "-I" => options.search_path.push(option_value(&mut args, &arg)?),
+            // REPL settings, instead of the ones in the config file
+            "--config" => options.config_file = Some(option_value(&mut args, &arg)?),
+            "--history" => options.history_file = Some(option_value(&mut args, &arg)?),
+            "--init" => options.init_file = Some(option_value(&mut args, &arg)?),
+            "-h" | "--help" => options.help = true,
+            "--" => {
+                positional.extend(args.by_ref());
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("Unknown option {}", arg));
+            }
+            _ => {
+                positional.push(arg);
+                positional.extend(args.by_ref());
+            }
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.program = if !expressions.is_empty() {
+        options.command_line = vec![String::from("-e")];
+        Program::Expressions(expressions)
+    } else {
+        match positional.next() {
+            Some(file) => {
+                options.command_line = vec![file.clone()];
+                if file == "-" {
+                    Program::Stdin
+                } else {
+                    Program::File(file)
+                }
+            }
+            None => Program::Nothing,
+        }
+    };
+    options.command_line.extend(positional);
+    Ok(options)
+}
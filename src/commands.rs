@@ -0,0 +1,230 @@
+use rs_lisp::primitives::SpecialForm;
+use rs_lisp::{parse, Cell, Error, Interpreter, SExpression};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+const HELP: &str = "\
+,env                 list the definitions made so far
+,load <file>         run a program file in this session
+,time <expression>   evaluate and say how long it took
+,trace [on|off]      print each call to a function, or stop
+,describe <expr>     the type of a value, and its definition if it has one
+,clear               start over with nothing defined
+,save <file>         write what's been typed this session to a file
+,quit [code]         leave, saving the history
+,help                this list";
+
+// A REPL session: the interpreter, and what's been typed into it that
+// worked, so it can be saved or a definition shown again.
+pub struct Session {
+    interp: Interpreter,
+    entries: Vec<String>,
+    // The text of the define that gave each name its value
+    definitions: HashMap<String, String>,
+}
+
+impl Session {
+    pub fn new(interp: Interpreter) -> Self {
+        Session {
+            interp,
+            entries: Vec::new(),
+            definitions: HashMap::new(),
+        }
+    }
+
+    pub fn interp(&self) -> &Interpreter {
+        &self.interp
+    }
+
+    // Run the forms typed so far and print each one's value. An error stops
+    // the rest of the forms from running. Returns the exit code if the
+    // program called exit.
+    pub fn interpret(&mut self, program: &str) -> Option<i32> {
+        match self.interp.eval_forms(program) {
+            Ok(results) => {
+                for result in results {
+                    println!("=>  {}", result.print());
+                }
+                self.remember(program);
+                None
+            }
+            Err(error) => report(error),
+        }
+    }
+
+    // Run the init file quietly, before the first prompt
+    pub fn run_init_file(&mut self, file: &Path) -> Option<i32> {
+        match self.interp.eval_file_forms(file) {
+            Ok(_) => None,
+            Err(error) => {
+                println!("In init file {}:", file.display());
+                report(error)
+            }
+        }
+    }
+
+    fn remember(&mut self, program: &str) {
+        self.entries.push(program.trim_end().to_string());
+        for form in parse(program).unwrap_or_default() {
+            if let Some(name) = defined_name(&form) {
+                self.definitions.insert(name, form.write());
+            }
+        }
+    }
+
+    // Carry out a comma command. Returns the exit code when it's time to go.
+    pub fn command(&mut self, line: &str) -> Option<i32> {
+        let line = line.trim().trim_start_matches(',');
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        match (name, argument) {
+            ("env", "") => self.env(),
+            ("load", file) if !file.is_empty() => return self.load(file),
+            ("time", program) if !program.is_empty() => {
+                let started = Instant::now();
+                let exit = self.interpret(program);
+                println!("Elapsed: {:.6}s", started.elapsed().as_secs_f64());
+                return exit;
+            }
+            ("trace", setting) => match setting {
+                "" => self.interp.set_tracing(!self.interp.tracing()),
+                "on" => self.interp.set_tracing(true),
+                "off" => self.interp.set_tracing(false),
+                _ => println!("Usage: ,trace [on|off]"),
+            },
+            ("describe", program) if !program.is_empty() => return self.describe(program),
+            ("clear", "") => {
+                self.interp.reset();
+                self.entries.clear();
+                self.definitions.clear();
+                println!("Cleared the session");
+            }
+            ("save", file) if !file.is_empty() => {
+                let mut text = self.entries.join("\n");
+                text.push('\n');
+                match fs::write(file, text) {
+                    Ok(()) => println!("Saved {} entries to {}", self.entries.len(), file),
+                    Err(error) => println!("Can't write {}: {}", file, error),
+                }
+            }
+            ("quit", "") => return Some(0),
+            ("quit", code) => match code.parse() {
+                Ok(code) => return Some(code),
+                Err(_) => println!("Usage: ,quit [code]"),
+            },
+            ("help", _) => println!("{}", HELP),
+            _ => println!("Unknown command ,{}. Type ,help for the list.", line),
+        }
+        if name == "trace" {
+            let state = if self.interp.tracing() { "on" } else { "off" };
+            println!("Tracing is {}", state);
+        }
+        None
+    }
+
+    fn env(&self) {
+        for name in self.interp.global_names() {
+            if let Ok(value) = self.interp.get_global(&name) {
+                println!("{} = {}", name, value.write());
+            }
+        }
+    }
+
+    fn load(&mut self, file: &str) -> Option<i32> {
+        match self.interp.eval_file_forms(file) {
+            Ok(results) => {
+                for result in results {
+                    println!("=>  {}", result.print());
+                }
+                // Saving the session loads the file again
+                self.entries.push(format!(
+                    "(load {})",
+                    SExpression::Cell(Cell::Str(file.to_string())).write()
+                ));
+                None
+            }
+            Err(error) => report(error),
+        }
+    }
+
+    fn describe(&mut self, program: &str) -> Option<i32> {
+        let value = match self.interp.eval_str(program) {
+            Ok(value) => value,
+            Err(error) => return report(error),
+        };
+        let name = program.trim();
+        match self.interp.parameters(name) {
+            Some(parameters) => {
+                let mut signature = vec![name.to_string()];
+                signature.extend(parameters);
+                println!("{}: procedure ({})", name, signature.join(" "))
+            }
+            None => println!("{}: {}", name, type_name(&value)),
+        }
+        match (self.definitions.get(name), self.interp.doc(name)) {
+            (Some(definition), _) => println!("{}", definition),
+            (None, Some(doc)) if !doc.is_empty() => println!("{}", doc),
+            _ => println!("=>  {}", value.write()),
+        }
+        None
+    }
+} // impl Session
+
+// Print an error, unless it's the program asking to exit
+fn report(error: Error) -> Option<i32> {
+    match error {
+        Error::Exit(code) => return Some(code),
+        // Parsing errors
+        Error::Parse(reason) => println!("=>  {}", reason),
+        error => println!("=>  Interpreter Error: {}", error),
+    }
+    None
+}
+
+// The name a top-level (define name ...) or (define (name ...) ...) defines
+fn defined_name(form: &SExpression) -> Option<String> {
+    let items = match form {
+        SExpression::List(list) => list.to_vec(),
+        _ => return None,
+    };
+    match items.as_slice() {
+        [SExpression::Cell(Cell::Special(SpecialForm::Define)), target, ..] => match target {
+            SExpression::Cell(Cell::Symbol(_, name)) => Some(name.clone()),
+            SExpression::List(signature) => match signature.to_vec().first() {
+                Some(SExpression::Cell(Cell::Symbol(_, name))) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn type_name(value: &SExpression) -> &'static str {
+    match value {
+        SExpression::List(_) => "list",
+        SExpression::Null => "null",
+        SExpression::Cell(cell) => match cell {
+            Cell::Int(_) | Cell::Flt(_) => "number",
+            Cell::Str(_) => "string",
+            Cell::Bool(_) => "boolean",
+            Cell::Symbol(..) => "symbol",
+            Cell::Op(_) | Cell::Logical(_) | Cell::Core(_) => "built-in procedure",
+            Cell::Special(_) => "special form",
+            Cell::Lambda(..) | Cell::Template(_) => "procedure",
+            Cell::Native(_) => "native procedure",
+            Cell::Local(..) => "variable",
+            Cell::HashTable(_) => "hash table",
+            Cell::Vector(_) => "vector",
+            Cell::Port(_) => "port",
+            Cell::Eof => "end of file object",
+            Cell::JsonNull => "JSON null",
+            Cell::Environment(_) => "environment",
+            Cell::Condition(_) => "error object",
+        },
+    }
+}
@@ -0,0 +1,421 @@
+use crate::bytecode::{Chunk, Op};
+use crate::context::Context;
+use crate::exceptions::expand_guard;
+use crate::lexical::{analyze_lambda, lambda_body, LambdaTemplate};
+use crate::list::List;
+use crate::load;
+use crate::primitives::Cell;
+use crate::primitives::CoreFunc;
+use crate::primitives::LogicalOperator;
+use crate::primitives::SpecialForm;
+use crate::symbolic_expression::SExpression;
+use std::rc::Rc;
+
+// Compiles s-expressions to bytecode for the VM in vm.rs. The compiler takes
+// either code straight from the parser (top-level forms) or the body of a
+// lambda that went through the lexical analysis, where variables are already
+// Cell::Local addresses and nested lambdas are templates. Every form compiles
+// to code that leaves exactly one value on the stack.
+//
+// Lambda bodies aren't compiled until the VM first calls them, so a lambda that
+// is never called costs nothing and a mistake in its body is reported when it
+// runs, like it would be by the tree walker.
+
+// Files are included, and lambdas analyzed, for the interpreter the context
+// belongs to
+pub fn compile_top_level(exp: &SExpression, context: &Context) -> Result<Chunk, String> {
+    let mut compiler = Compiler::new(context);
+    compiler.compile(exp, true)?;
+    compiler.chunk.emit(Op::Return);
+    Ok(compiler.chunk)
+}
+
+// The compiled body of a lambda, compiled the first time it's asked for and
+// kept with the template after that
+pub fn lambda_code(template: &LambdaTemplate, context: &Context) -> Result<Rc<Chunk>, String> {
+    if let Some(code) = template.code.get() {
+        return Ok(code.clone());
+    }
+    let mut compiler = Compiler::new(context);
+    compiler.compile(&template.body, true)?;
+    compiler.chunk.emit(Op::Return);
+    let code = Rc::new(compiler.chunk);
+    let _ = template.code.set(code.clone());
+    Ok(code)
+}
+
+// A chunk's listing followed by those of the lambdas it makes, and theirs
+pub fn disassemble_all(chunk: &Chunk, name: &str, context: &Context) -> Result<String, String> {
+    let mut listings = vec![chunk.disassemble(name)];
+    for constant in &chunk.constants {
+        if let SExpression::Cell(Cell::Template(template)) = constant {
+            let code = lambda_code(template, context)?;
+            listings.push(disassemble_all(&code, &template.print(), context)?);
+        }
+    }
+    Ok(listings.join("\n\n"))
+}
+
+struct Compiler<'a> {
+    chunk: Chunk,
+    context: &'a Context,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(context: &'a Context) -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            context,
+        }
+    }
+
+    // An expression in tail position gives the value of the whole chunk, so a
+    // call there can replace the current call frame instead of adding one.
+    fn compile(&mut self, exp: &SExpression, tail: bool) -> Result<(), String> {
+        match exp {
+            SExpression::Cell(cell) => self.compile_cell(cell),
+            SExpression::List(list) => self.compile_list(list, tail),
+            SExpression::Null => {
+                self.constant(SExpression::Null);
+                Ok(())
+            }
+        }
+    }
+
+    fn constant(&mut self, value: SExpression) {
+        let index = self.chunk.add_constant(value);
+        self.chunk.emit(Op::Constant(index));
+    }
+
+    fn closure(&mut self, template: Rc<LambdaTemplate>) {
+        let index = self
+            .chunk
+            .add_constant(SExpression::Cell(Cell::Template(template)));
+        self.chunk.emit(Op::MakeClosure(index));
+    }
+
+    fn compile_cell(&mut self, cell: &Cell) -> Result<(), String> {
+        match cell {
+            Cell::Symbol(number, _) => {
+                self.chunk.emit(Op::GetGlobal(*number));
+            }
+            Cell::Local(depth, slot, number) => {
+                self.chunk.emit(Op::GetLocal(*depth, *slot, *number));
+            }
+            Cell::Template(template) => self.closure(template.clone()),
+            _ => self.constant(SExpression::Cell(cell.clone())),
+        }
+        Ok(())
+    }
+
+    fn compile_args(&mut self, args: &List) -> Result<usize, String> {
+        let args = args.to_vec();
+        for arg in &args {
+            self.compile(arg, false)?;
+        }
+        Ok(args.len())
+    }
+
+    fn compile_call(&mut self, argc: usize, tail: bool) {
+        if tail {
+            self.chunk.emit(Op::TailCall(argc));
+        } else {
+            self.chunk.emit(Op::Call(argc));
+        }
+    }
+
+    fn compile_list(&mut self, list: &List, tail: bool) -> Result<(), String> {
+        let args = list.rest();
+        match *list.first() {
+            SExpression::Cell(cell) => match cell {
+                Cell::Special(form) => self.compile_special_form(form, &args, tail),
+                Cell::Core(_) => {
+                    self.constant(SExpression::Cell(cell));
+                    let argc = self.compile_args(&args)?;
+                    self.compile_call(argc, tail);
+                    Ok(())
+                }
+                Cell::Op(operator) => {
+                    if args.is_empty() || args.rest().is_empty() {
+                        return Err(String::from("Operator ")
+                            + operator.print()
+                            + " requires two arguments");
+                    }
+                    let argc = self.compile_args(&args)?;
+                    self.chunk.emit(Op::Arithmetic(operator, argc));
+                    Ok(())
+                }
+                Cell::Logical(operator) => self.compile_logical(operator, &args),
+                Cell::Symbol(_, _) | Cell::Local(_, _, _) | Cell::Template(_) => {
+                    self.compile_cell(&cell)?;
+                    let argc = self.compile_args(&args)?;
+                    self.compile_call(argc, tail);
+                    Ok(())
+                }
+                _ => Err("Evaluation on this cell type  not supported".to_string()),
+            },
+            // As in List::evaluate, a list at the head is only called if it
+            // gives a procedure.
+            SExpression::List(head) => {
+                self.compile_list(&head, false)?;
+                let skip_call = self.chunk.emit(Op::JumpUnlessProcedure(0));
+                let argc = self.compile_args(&args)?;
+                self.compile_call(argc, tail);
+                self.chunk.patch_jump(skip_call);
+                Ok(())
+            }
+            SExpression::Null => {
+                self.constant(SExpression::Null);
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_logical(&mut self, operator: LogicalOperator, args: &List) -> Result<(), String> {
+        if args.is_empty() || args.rest().is_empty() {
+            return Err(String::from("Operator ") + operator.print() + " requires two arguments");
+        }
+        match operator {
+            LogicalOperator::Greater | LogicalOperator::Less | LogicalOperator::Equal => {
+                let argc = self.compile_args(args)?;
+                self.chunk.emit(Op::Compare(operator, argc));
+            }
+            // Jump out as soon as one argument decides the answer
+            LogicalOperator::And | LogicalOperator::Or => {
+                let deciding_value = operator == LogicalOperator::Or;
+                let mut decided = Vec::new();
+                for arg in args.to_vec() {
+                    self.compile(&arg, false)?;
+                    let jump = if deciding_value {
+                        Op::JumpIfTrue(0)
+                    } else {
+                        Op::JumpIfFalse(0)
+                    };
+                    decided.push(self.chunk.emit(jump));
+                }
+                self.constant(SExpression::Cell(Cell::Bool(!deciding_value)));
+                let end = self.chunk.emit(Op::Jump(0));
+                for jump in decided {
+                    self.chunk.patch_jump(jump);
+                }
+                self.constant(SExpression::Cell(Cell::Bool(deciding_value)));
+                self.chunk.patch_jump(end);
+            }
+            _ => return Err(format!("Operator '{}' not implemented", operator.print())),
+        }
+        Ok(())
+    }
+
+    fn compile_special_form(
+        &mut self,
+        form: SpecialForm,
+        args: &List,
+        tail: bool,
+    ) -> Result<(), String> {
+        match form {
+            SpecialForm::If => self.compile_if(args, tail),
+            SpecialForm::Set => self.compile_set(args),
+            SpecialForm::While => self.compile_while(args),
+            SpecialForm::Begin => self.compile_begin(args, tail),
+            SpecialForm::Quote => {
+                if args.is_empty() || !args.rest().is_empty() {
+                    return Err("quote takes exactly one expression.".to_string());
+                }
+                self.constant(*args.first());
+                Ok(())
+            }
+            SpecialForm::Lambda => {
+                let params = match *args.first() {
+                    SExpression::List(params) => params,
+                    other => {
+                        return Err(format!(
+                            "lambda requires a list of parameters, got {}",
+                            other.print()
+                        ))
+                    }
+                };
+                let template =
+                    analyze_lambda(None, &params, lambda_body(args.rest()), self.context)?;
+                self.closure(template);
+                Ok(())
+            }
+            SpecialForm::Output | SpecialForm::OutputLine => {
+                let argc = self.compile_args(args)?;
+                self.chunk
+                    .emit(Op::Output(argc, form == SpecialForm::OutputLine));
+                Ok(())
+            }
+            SpecialForm::Input => {
+                let argc = self.compile_args(args)?;
+                self.chunk.emit(Op::Input(argc));
+                Ok(())
+            }
+            SpecialForm::Define => self.compile_define(args),
+            // The files are read now, while compiling, and their forms
+            // compiled in place of the include
+            SpecialForm::Include => {
+                let names = args.to_vec();
+                if names.is_empty() {
+                    self.constant(SExpression::Null);
+                }
+                for (index, name) in names.iter().enumerate() {
+                    if index > 0 {
+                        self.chunk.emit(Op::Pop);
+                    }
+                    let path = load::include_path(self.context, name)?;
+                    let last = tail && index == names.len() - 1;
+                    load::with_loading(self.context, &path, || {
+                        let forms = load::read_forms(&path)?;
+                        self.compile_begin(&forms, last)
+                    })??;
+                }
+                Ok(())
+            }
+            SpecialForm::Guard => self.compile(&expand_guard(args)?, tail),
+            // (environment-bindings (current-environment))
+            SpecialForm::Env => {
+                if !args.is_empty() {
+                    return Err("env takes no arguments.".to_string());
+                }
+                self.constant(SExpression::Cell(Cell::Core(CoreFunc::EnvironmentBindings)));
+                self.constant(SExpression::Cell(Cell::Core(CoreFunc::CurrentEnvironment)));
+                self.chunk.emit(Op::Call(0));
+                self.chunk.emit(Op::Call(1));
+                Ok(())
+            }
+            // Libraries are set up by the tree walker, as
+            // (eval '(import ...) (current-environment))
+            SpecialForm::DefineLibrary | SpecialForm::Import => {
+                let mut form = vec![SExpression::Cell(Cell::Special(form))];
+                form.extend(args.to_vec());
+                self.constant(SExpression::Cell(Cell::Core(CoreFunc::Eval)));
+                self.constant(SExpression::List(List::make_from_sexps(form)));
+                self.constant(SExpression::Cell(Cell::Core(CoreFunc::CurrentEnvironment)));
+                self.chunk.emit(Op::Call(0));
+                self.chunk.emit(Op::Call(2));
+                Ok(())
+            }
+            _ => Err(format!("Special form {} not implemented!", form.print())),
+        }
+    }
+
+    fn compile_if(&mut self, clauses: &List, tail: bool) -> Result<(), String> {
+        if clauses.is_empty() {
+            return Err("if expression must have three parts.".to_string());
+        }
+        self.compile(&clauses.first(), false)?;
+        let to_else = self.chunk.emit(Op::JumpIfFalse(0));
+        self.compile(&clauses.rest().first(), tail)?;
+        let to_end = self.chunk.emit(Op::Jump(0));
+        self.chunk.patch_jump(to_else);
+        self.compile(&clauses.rest().rest().first(), tail)?;
+        self.chunk.patch_jump(to_end);
+        Ok(())
+    }
+
+    // The value of a while loop is the value of the last time through the
+    // body, or false if the body never ran.
+    fn compile_while(&mut self, clauses: &List) -> Result<(), String> {
+        if clauses.is_empty() || clauses.rest().is_empty() {
+            return Err("while expression must have two  clauses (test) (body).".to_string());
+        }
+        if !clauses.rest().rest().is_empty() {
+            return Err(
+                "while expression body must not have  more than one s-expression.".to_string(),
+            );
+        }
+        self.constant(SExpression::Cell(Cell::Bool(false)));
+        let loop_start = self.chunk.code.len();
+        self.compile(&clauses.first(), false)?;
+        let exit = self.chunk.emit(Op::JumpIfFalse(0));
+        self.chunk.emit(Op::Pop);
+        self.compile(&clauses.rest().first(), false)?;
+        self.chunk.emit(Op::Jump(loop_start));
+        self.chunk.patch_jump(exit);
+        Ok(())
+    }
+
+    fn compile_begin(&mut self, exprs: &List, tail: bool) -> Result<(), String> {
+        let exprs = exprs.to_vec();
+        if exprs.is_empty() {
+            self.constant(SExpression::Null);
+        }
+        for (index, exp) in exprs.iter().enumerate() {
+            if index > 0 {
+                self.chunk.emit(Op::Pop);
+            }
+            self.compile(exp, tail && index == exprs.len() - 1)?;
+        }
+        Ok(())
+    }
+
+    fn compile_set(&mut self, args: &List) -> Result<(), String> {
+        if args.is_empty() || args.rest().is_empty() {
+            return Err("set! expression must have two arguments.".to_string());
+        }
+        if !args.rest().rest().is_empty() {
+            return Err("set! expression must have only two arguments.".to_string());
+        }
+        let op = match *args.first() {
+            SExpression::Cell(Cell::Symbol(number, _)) => Op::SetGlobal(number),
+            SExpression::Cell(Cell::Local(depth, slot, number)) => {
+                Op::SetLocal(depth, slot, number)
+            }
+            SExpression::Cell(c) => {
+                return Err(format!(
+                    "First argument to set! must be a symbol but was {} instead.",
+                    c.print()
+                ))
+            }
+            _ => return Err("First argument to set! must be a symbol.".to_string()),
+        };
+        self.compile(&args.rest().first(), false)?;
+        self.chunk.emit(op);
+        Ok(())
+    }
+
+    // Like the tree walker, define gives back what it defined: the symbol, or
+    // the signature for (define (f x) ...)
+    fn compile_define(&mut self, args: &List) -> Result<(), String> {
+        let new_symbol = *args.first();
+        let value_for_symbol = *args.rest().first();
+        if !args.rest().rest().is_empty() {
+            return Err(format!("define can only take one expression as the value to assign to the symbol or lambda: {}",
+                &new_symbol.print()));
+        }
+        match new_symbol {
+            SExpression::Cell(Cell::Symbol(number, _)) => {
+                self.compile(&value_for_symbol, false)?;
+                self.chunk.emit(Op::DefineGlobal(number));
+            }
+            SExpression::Cell(Cell::Local(0, slot, number)) => {
+                self.compile(&value_for_symbol, false)?;
+                self.chunk.emit(Op::DefineLocal(slot, number));
+            }
+            SExpression::Cell(ref cell) => {
+                return Err(format!("Cannot re-define {}", &cell.print()))
+            }
+            SExpression::List(ref function_signature) => match *function_signature.first() {
+                SExpression::Cell(Cell::Symbol(number, _)) => {
+                    let template = analyze_lambda(
+                        Some(number),
+                        &function_signature.rest(),
+                        value_for_symbol,
+                        self.context,
+                    )?;
+                    self.closure(template);
+                    self.chunk.emit(Op::DefineGlobal(number));
+                }
+                other => return Err(format!("Invalid function name: {}", &other.print())),
+            },
+            SExpression::Null => {
+                return Err(format!(
+                    "Cannot apply special form treatment to {}",
+                    new_symbol.print()
+                ))
+            }
+        }
+        self.constant(new_symbol);
+        Ok(())
+    }
+} // impl Compiler
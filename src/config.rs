@@ -0,0 +1,130 @@
+use rs_lisp::{parse, AlistReader};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where the REPL keeps its files, following the XDG base directory spec:
+// history under $XDG_DATA_HOME (~/.local/share), and the config and init
+// files under $XDG_CONFIG_HOME (~/.config).
+const APP_DIRECTORY: &str = "rs_lisp";
+const HISTORY_FILE: &str = "history.txt";
+const CONFIG_FILE: &str = "config.scm";
+const INIT_FILE: &str = "init.scm";
+
+// REPL settings. The config file is an association list, with any of these
+// left out to get the default:
+//
+//     ((prompt "λ> ")
+//      (continuation-prompt "   ")
+//      (color #f)
+//      (max-history 500)
+//      (history-file "~/notes/lisp-history")
+//      (init-file "~/lisp/startup.scm"))
+pub struct ReplConfig {
+    pub prompt: String,
+    pub continuation_prompt: String,
+    pub color: bool,
+    pub max_history: usize,
+    // None keeps no history at all, as when there's no home directory
+    pub history_file: Option<PathBuf>,
+    // Scheme code run before the first prompt
+    pub init_file: Option<PathBuf>,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        ReplConfig {
+            prompt: String::from(">> "),
+            continuation_prompt: String::from(".. "),
+            color: true,
+            max_history: 1000,
+            history_file: xdg_directory("XDG_DATA_HOME", ".local/share")
+                .map(|directory| directory.join(HISTORY_FILE)),
+            init_file: xdg_directory("XDG_CONFIG_HOME", ".config")
+                .and_then(|directory| init_file_in(&directory)),
+        }
+    }
+}
+
+impl ReplConfig {
+    // The settings from a config file given on the command line, or else
+    // the one in the config directory if there is one
+    pub fn load(file: Option<&Path>) -> Result<ReplConfig, String> {
+        let file = match file {
+            Some(file) => file.to_path_buf(),
+            None => match default_config_file() {
+                Some(file) if file.exists() => file,
+                _ => return Ok(ReplConfig::default()),
+            },
+        };
+        let text = fs::read_to_string(&file)
+            .map_err(|error| format!("Can't read {}: {}", file.display(), error))?;
+        // The init file is looked for next to the config file, wherever that is
+        let config = ReplConfig {
+            init_file: file.parent().and_then(init_file_in),
+            ..ReplConfig::default()
+        };
+        config
+            .with_settings(&text)
+            .map_err(|error| format!("In {}: {}", file.display(), error))
+    }
+
+    fn with_settings(mut self, text: &str) -> Result<ReplConfig, String> {
+        let settings = match parse(text).map_err(|error| error.to_string())?.as_slice() {
+            [] => return Ok(self),
+            [settings] => AlistReader::new(settings)?,
+            _ => return Err("the settings should be one association list".to_string()),
+        };
+        if let Some(prompt) = settings.optional_field("prompt")? {
+            self.prompt = prompt;
+        }
+        if let Some(prompt) = settings.optional_field("continuation-prompt")? {
+            self.continuation_prompt = prompt;
+        }
+        if let Some(color) = settings.optional_field("color")? {
+            self.color = color;
+        }
+        if let Some(max_history) = settings.optional_field("max-history")? {
+            self.max_history = max_history;
+        }
+        if let Some(file) = settings.optional_field::<String>("history-file")? {
+            self.history_file = Some(expand_home(&file));
+        }
+        if let Some(file) = settings.optional_field::<String>("init-file")? {
+            self.init_file = Some(expand_home(&file));
+        }
+        Ok(self)
+    }
+} // impl ReplConfig
+
+fn init_file_in(directory: &Path) -> Option<PathBuf> {
+    Some(directory.join(INIT_FILE)).filter(|file| file.exists())
+}
+
+fn default_config_file() -> Option<PathBuf> {
+    xdg_directory("XDG_CONFIG_HOME", ".config").map(|directory| directory.join(CONFIG_FILE))
+}
+
+// The app's directory under an XDG base directory, which falls back to a
+// directory in home when the variable isn't set
+fn xdg_directory(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(variable) {
+        Some(base) if !base.is_empty() => PathBuf::from(base),
+        _ => home()?.join(fallback),
+    };
+    Some(base.join(APP_DIRECTORY))
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+// ~/ at the start of a path in the config file means the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
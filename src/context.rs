@@ -0,0 +1,56 @@
+use crate::interpreter::Redefinition;
+use crate::library::Bindings;
+use crate::port::CurrentPorts;
+use crate::symbolic_expression::SExpression;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// What one interpreter keeps besides its definitions: settings, and the state
+// of things like loading that are under way. Every environment made while the
+// interpreter runs points to the same Context, so it goes wherever the code
+// does (into lambdas, eval, load and libraries), and two interpreters never
+// see each other's.
+#[derive(Default)]
+pub struct Context {
+    // What a top-level define of a name that's already defined does
+    pub redefinition: Cell<Redefinition>,
+    // What (command-line) gives: the program's name and its arguments
+    pub command_line: RefCell<Vec<String>>,
+    // Print each call to a lambda, with its arguments, on stderr
+    pub tracing: Cell<bool>,
+    // Set by (exit) while the error it makes unwinds to the top
+    pub exit_code: Cell<Option<i32>>,
+    // The object being raised, and how many handlers were installed when it
+    // was: the ones further in than that don't get to handle it.
+    pub raised: RefCell<Option<(SExpression, usize)>>,
+    // The with-exception-handler handlers, innermost last; None for a guard
+    pub handlers: RefCell<Vec<Option<SExpression>>>,
+    // Where display, read-line and the rest go when they aren't given a port
+    pub ports: CurrentPorts,
+    // Directories load and include look in, after the loading file's own
+    pub search_path: RefCell<Vec<PathBuf>>,
+    // The files being loaded right now, innermost last
+    pub loading: RefCell<Vec<PathBuf>>,
+    // Libraries defined so far, by name as printed: "(geometry shapes)"
+    pub libraries: RefCell<HashMap<String, Rc<Bindings>>>,
+}
+
+impl Context {
+    // Whether an error on its way up is really an exit, which nothing catches
+    pub fn exiting(&self) -> bool {
+        self.exit_code.get().is_some()
+    }
+
+    // The same settings with nothing under way, for starting over
+    pub fn fresh_copy(&self) -> Context {
+        Context {
+            redefinition: self.redefinition.clone(),
+            command_line: self.command_line.clone(),
+            tracing: self.tracing.clone(),
+            search_path: RefCell::new(self.search_path.borrow().clone()),
+            ..Context::default()
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use crate::hash_table::{Equivalence, HashTable};
+use crate::list::List;
+use crate::native::Arity;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use crate::symbols::intern;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::rc::Rc;
+
+// Conversions between Rust values and SExpressions, for passing data in and
+// out of the interpreter without building cells and lists by hand:
+//
+//     let scores = vec![("ann".to_string(), 3), ("bob".to_string(), 5)].to_scheme();
+//     let back: Vec<(String, i64)> = FromScheme::from_scheme(&scores)?;
+//
+// Rust vectors and tuples become lists (a Vec can also be read from a Scheme
+// vector), HashMap<String, T> becomes a hash table with string keys, None
+// becomes Null, and the unit type () becomes Null too so a function with
+// nothing to return can still give a value.
+
+pub trait ToScheme {
+    fn to_scheme(&self) -> SExpression;
+}
+
+// Conversion errors are strings so '?' works inside native functions.
+pub trait FromScheme: Sized {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String>;
+}
+
+fn type_error(wanted: &str, exp: &SExpression) -> String {
+    format!("Expected {}, got {}", wanted, exp.print())
+}
+
+impl ToScheme for SExpression {
+    fn to_scheme(&self) -> SExpression {
+        self.clone()
+    }
+}
+
+impl FromScheme for SExpression {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        Ok(exp.clone())
+    }
+}
+
+impl ToScheme for () {
+    fn to_scheme(&self) -> SExpression {
+        SExpression::Null
+    }
+}
+
+impl FromScheme for () {
+    fn from_scheme(_exp: &SExpression) -> Result<Self, String> {
+        Ok(())
+    }
+}
+
+// Number literals in Scheme code are read as floats, so a whole-numbered float
+// converts to an integer type as long as it fits. Integers are stored as i64,
+// and since to_scheme can't fail, a u64 or usize too big for that wraps around
+// on the way in: u64::MAX becomes -1, and reading it back as a u64 is an error.
+// Serializing one with serde (ser.rs) can fail, and does instead.
+macro_rules! integer_conversions {
+    ($($int:ty),*) => {
+        $(
+            impl ToScheme for $int {
+                fn to_scheme(&self) -> SExpression {
+                    SExpression::Cell(Cell::Int(*self as i64))
+                }
+            }
+
+            impl FromScheme for $int {
+                fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+                    let wanted = concat!("an integer that fits in ", stringify!($int));
+                    let value = match exp {
+                        SExpression::Cell(Cell::Int(value)) => i128::from(*value),
+                        SExpression::Cell(Cell::Flt(value)) if value.fract() == 0.0 => {
+                            *value as i128
+                        }
+                        _ => return Err(type_error(wanted, exp)),
+                    };
+                    <$int>::try_from(value).map_err(|_| type_error(wanted, exp))
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_conversions {
+    ($($float:ty),*) => {
+        $(
+            impl ToScheme for $float {
+                fn to_scheme(&self) -> SExpression {
+                    SExpression::Cell(Cell::Flt(f64::from(*self)))
+                }
+            }
+
+            impl FromScheme for $float {
+                fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+                    match exp {
+                        SExpression::Cell(Cell::Int(value)) => Ok(*value as $float),
+                        SExpression::Cell(Cell::Flt(value)) => Ok(*value as $float),
+                        _ => Err(type_error("a number", exp)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+float_conversions!(f32, f64);
+
+impl ToScheme for bool {
+    fn to_scheme(&self) -> SExpression {
+        SExpression::Cell(Cell::Bool(*self))
+    }
+}
+
+impl FromScheme for bool {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        match exp {
+            SExpression::Cell(Cell::Bool(value)) => Ok(*value),
+            _ => Err(type_error("a boolean", exp)),
+        }
+    }
+}
+
+impl ToScheme for String {
+    fn to_scheme(&self) -> SExpression {
+        SExpression::Cell(Cell::Str(self.clone()))
+    }
+}
+
+impl ToScheme for &str {
+    fn to_scheme(&self) -> SExpression {
+        SExpression::Cell(Cell::Str(self.to_string()))
+    }
+}
+
+impl FromScheme for String {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        match exp {
+            SExpression::Cell(Cell::Str(value)) => Ok(value.clone()),
+            _ => Err(type_error("a string", exp)),
+        }
+    }
+}
+
+impl<T: ToScheme> ToScheme for Vec<T> {
+    fn to_scheme(&self) -> SExpression {
+        let items = self.iter().map(|item| item.to_scheme()).collect();
+        SExpression::List(List::make_from_sexps(items))
+    }
+}
+
+impl<T: ToScheme> ToScheme for [T] {
+    fn to_scheme(&self) -> SExpression {
+        let items = self.iter().map(|item| item.to_scheme()).collect();
+        SExpression::List(List::make_from_sexps(items))
+    }
+}
+
+impl<T: FromScheme> FromScheme for Vec<T> {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        match exp {
+            SExpression::List(list) => list.to_vec().iter().map(T::from_scheme).collect(),
+            SExpression::Cell(Cell::Vector(items)) => {
+                items.borrow().iter().map(T::from_scheme).collect()
+            }
+            _ => Err(type_error("a list or vector", exp)),
+        }
+    }
+}
+
+impl<T: ToScheme> ToScheme for Option<T> {
+    fn to_scheme(&self) -> SExpression {
+        match self {
+            Some(value) => value.to_scheme(),
+            None => SExpression::Null,
+        }
+    }
+}
+
+impl<T: FromScheme> FromScheme for Option<T> {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        match exp {
+            SExpression::Null => Ok(None),
+            _ => Ok(Some(T::from_scheme(exp)?)),
+        }
+    }
+}
+
+impl<T: ToScheme, S: BuildHasher> ToScheme for HashMap<String, T, S> {
+    fn to_scheme(&self) -> SExpression {
+        let mut table = HashTable::new(Equivalence::StringEqual);
+        for (key, value) in self {
+            // Keys are strings, which is all a string=? table checks for
+            let _ = table.set(key.to_scheme(), value.to_scheme());
+        }
+        SExpression::Cell(Cell::HashTable(Rc::new(RefCell::new(table))))
+    }
+}
+
+// From a hash table or an association list, with string or symbol keys
+impl<T: FromScheme, S: BuildHasher + Default> FromScheme for HashMap<String, T, S> {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        let entries = match exp {
+            SExpression::Cell(Cell::HashTable(table)) => table.borrow().entries(),
+            SExpression::List(_) => alist_entries(exp)?,
+            _ => return Err(type_error("a hash table or association list", exp)),
+        };
+        let mut map = HashMap::default();
+        for (key, value) in entries {
+            map.insert(key_name(&key)?, T::from_scheme(&value)?);
+        }
+        Ok(map)
+    }
+}
+
+fn key_name(key: &SExpression) -> Result<String, String> {
+    match key {
+        SExpression::Cell(Cell::Str(name)) | SExpression::Cell(Cell::Symbol(_, name)) => {
+            Ok(name.clone())
+        }
+        _ => Err(type_error("a string or symbol key", key)),
+    }
+}
+
+// An entry (key value) gives value. One made with (cons key some-list) has
+// the list's items after the key, so anything longer gives the rest as a list.
+pub(crate) fn alist_entries(exp: &SExpression) -> Result<Vec<(SExpression, SExpression)>, String> {
+    let entries: Vec<SExpression> = Vec::from_scheme(exp)?;
+    let mut pairs = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let items = match &entry {
+            SExpression::List(items) if !items.is_empty() => items.clone(),
+            _ => return Err(type_error("an association list entry", &entry)),
+        };
+        let rest = items.rest();
+        let value = if !rest.is_empty() && rest.rest().is_empty() {
+            *rest.first()
+        } else {
+            SExpression::List(rest)
+        };
+        pairs.push((*items.first(), value));
+    }
+    Ok(pairs)
+}
+
+macro_rules! tuple_conversions {
+    ($(($($name:ident : $index:tt),+) with $count:expr),*) => {
+        $(
+            impl<$($name: ToScheme),+> ToScheme for ($($name,)+) {
+                fn to_scheme(&self) -> SExpression {
+                    SExpression::List(List::make_from_sexps(vec![$(self.$index.to_scheme()),+]))
+                }
+            }
+
+            impl<$($name: FromScheme),+> FromScheme for ($($name,)+) {
+                fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+                    let items: Vec<SExpression> = Vec::from_scheme(exp)?;
+                    if items.len() != $count {
+                        return Err(type_error(concat!("a list of ", $count, " items"), exp));
+                    }
+                    Ok(($($name::from_scheme(&items[$index])?,)+))
+                }
+            }
+
+            impl<$($name: FromScheme),+> FromArgs for ($($name,)+) {
+                fn arity() -> Arity {
+                    Arity::Exact($count)
+                }
+
+                fn from_args(args: &[SExpression]) -> Result<Self, String> {
+                    Ok(($($name::from_scheme(&args[$index])?,)+))
+                }
+            }
+        )*
+    };
+}
+
+// Arguments to a native function as Rust values: a tuple with one type per
+// argument, for Interpreter::register_typed_fn.
+pub trait FromArgs: Sized {
+    fn arity() -> Arity;
+    fn from_args(args: &[SExpression]) -> Result<Self, String>;
+}
+
+impl FromArgs for () {
+    fn arity() -> Arity {
+        Arity::Exact(0)
+    }
+
+    fn from_args(_args: &[SExpression]) -> Result<Self, String> {
+        Ok(())
+    }
+}
+
+tuple_conversions!(
+    (A: 0) with 1,
+    (A: 0, B: 1) with 2,
+    (A: 0, B: 1, C: 2) with 3,
+    (A: 0, B: 1, C: 2, D: 3) with 4,
+    (A: 0, B: 1, C: 2, D: 3, E: 4) with 5
+);
+
+// Builds the association list for a struct, one (name value) entry per field
+// with the field name as a symbol:
+//
+//     impl ToScheme for Point {
+//         fn to_scheme(&self) -> SExpression {
+//             AlistBuilder::new().field("x", &self.x).field("y", &self.y).build()
+//         }
+//     }
+#[derive(Default)]
+pub struct AlistBuilder {
+    entries: Vec<SExpression>,
+}
+
+impl AlistBuilder {
+    pub fn new() -> Self {
+        AlistBuilder::default()
+    }
+
+    pub fn field<T: ToScheme + ?Sized>(mut self, name: &str, value: &T) -> Self {
+        let key = SExpression::Cell(Cell::Symbol(intern(name), name.to_string()));
+        let entry = List::make_from_sexps(vec![key, value.to_scheme()]);
+        self.entries.push(SExpression::List(entry));
+        self
+    }
+
+    pub fn build(self) -> SExpression {
+        SExpression::List(List::make_from_sexps(self.entries))
+    }
+}
+
+// Reads struct fields back out of an association list:
+//
+//     impl FromScheme for Point {
+//         fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+//             let fields = AlistReader::new(exp)?;
+//             Ok(Point { x: fields.field("x")?, y: fields.field("y")? })
+//         }
+//     }
+pub struct AlistReader {
+    entries: Vec<(String, SExpression)>,
+}
+
+impl AlistReader {
+    pub fn new(exp: &SExpression) -> Result<Self, String> {
+        let entries = alist_entries(exp)?
+            .into_iter()
+            .map(|(key, value)| Ok((key_name(&key)?, value)))
+            .collect::<Result<Vec<(String, SExpression)>, String>>()?;
+        Ok(AlistReader { entries })
+    }
+
+    pub fn field<T: FromScheme>(&self, name: &str) -> Result<T, String> {
+        match self.optional_field(name)? {
+            Some(value) => Ok(value),
+            None => Err(format!("Missing field {}", name)),
+        }
+    }
+
+    // A field that may be left out of the list
+    pub fn optional_field<T: FromScheme>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.entries.iter().find(|(key, _)| key == name) {
+            Some((_, value)) => T::from_scheme(value)
+                .map(Some)
+                .map_err(|error| format!("Field {}: {}", name, error)),
+            None => Ok(None),
+        }
+    }
+}
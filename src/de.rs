@@ -0,0 +1,529 @@
+use crate::convert::alist_entries;
+use crate::embed::parse_program;
+use crate::error::Error;
+use crate::list::List;
+use crate::parser::symbol_cell;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+
+// Reading the s-expression data format back into Rust values; see ser.rs for
+// how values are laid out. The text goes through the same parser as Scheme
+// code, so anything written with to_string reads back with from_str.
+
+pub fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+    let forms = parse_program(text)?.to_vec();
+    match forms.as_slice() {
+        [exp] => from_sexpression(exp),
+        _ => Err(Error::Parse(format!(
+            "Expected one expression, found {}",
+            forms.len()
+        ))),
+    }
+}
+
+pub fn from_sexpression<T: DeserializeOwned>(exp: &SExpression) -> Result<T, Error> {
+    T::deserialize(Deserializer { exp: exp.clone() })
+}
+
+// SExpression asks for a newtype struct with this name when it deserializes,
+// so this format can hand it symbols as symbols rather than strings.
+const SEXPRESSION_TOKEN: &str = "$rs_lisp::SExpression";
+
+fn type_error(wanted: &str, exp: &SExpression) -> Error {
+    Error::Data(format!("Expected {}, got {}", wanted, exp.write()))
+}
+
+// Symbols, and names like car the parser reads as built-ins
+fn name_of(exp: &SExpression) -> Option<String> {
+    match exp {
+        SExpression::Cell(Cell::Symbol(_, name)) => Some(name.clone()),
+        SExpression::Cell(
+            cell @ Cell::Op(_)
+            | cell @ Cell::Logical(_)
+            | cell @ Cell::Special(_)
+            | cell @ Cell::Core(_),
+        ) => Some(cell.print()),
+        _ => None,
+    }
+}
+
+struct Deserializer {
+    exp: SExpression,
+}
+
+impl Deserializer {
+    fn items(&self, wanted: &str) -> Result<Vec<SExpression>, Error> {
+        match &self.exp {
+            SExpression::List(items) => Ok(items.to_vec()),
+            SExpression::Cell(Cell::Vector(items)) => Ok(items.borrow().clone()),
+            _ => Err(type_error(wanted, &self.exp)),
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<(SExpression, SExpression)>, Error> {
+        match &self.exp {
+            SExpression::Cell(Cell::HashTable(table)) => Ok(table.borrow().entries()),
+            _ => alist_entries(&self.exp).map_err(Error::Data),
+        }
+    }
+
+    // A whole-numbered float counts, since that's how the parser reads numbers
+    fn integer(&self) -> Result<i128, Error> {
+        match &self.exp {
+            SExpression::Cell(Cell::Int(value)) => Ok(i128::from(*value)),
+            SExpression::Cell(Cell::Flt(value)) if value.fract() == 0.0 => Ok(*value as i128),
+            _ => Err(type_error("an integer", &self.exp)),
+        }
+    }
+
+    fn signed(&self) -> Result<i64, Error> {
+        i64::try_from(self.integer()?).map_err(|_| type_error("an integer", &self.exp))
+    }
+
+    fn unsigned(&self) -> Result<u64, Error> {
+        u64::try_from(self.integer()?).map_err(|_| type_error("a positive integer", &self.exp))
+    }
+
+    fn string(&self) -> Result<String, Error> {
+        match &self.exp {
+            SExpression::Cell(Cell::Str(value)) => Ok(value.clone()),
+            _ => name_of(&self.exp).ok_or_else(|| type_error("a string", &self.exp)),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.exp {
+            SExpression::Null => visitor.visit_unit(),
+            SExpression::List(items) => visitor.visit_seq(SeqAccess::new(items.to_vec())),
+            SExpression::Cell(cell) => match cell {
+                Cell::Int(value) => visitor.visit_i64(*value),
+                Cell::Flt(value) => visitor.visit_f64(*value),
+                Cell::Str(value) => visitor.visit_string(value.clone()),
+                Cell::Bool(value) => visitor.visit_bool(*value),
+                Cell::Vector(items) => visitor.visit_seq(SeqAccess::new(items.borrow().clone())),
+                Cell::HashTable(_) => visitor.visit_map(MapAccess::new(self.entries()?)),
+                _ => match name_of(&self.exp) {
+                    Some(name) => visitor.visit_string(name),
+                    None => Err(type_error("data", &self.exp)),
+                },
+            },
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.exp {
+            SExpression::Cell(Cell::Bool(value)) => visitor.visit_bool(*value),
+            _ => Err(type_error("a boolean", &self.exp)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.unsigned()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.unsigned()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.unsigned()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.unsigned()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.exp {
+            SExpression::Cell(Cell::Int(value)) => visitor.visit_f64(*value as f64),
+            SExpression::Cell(Cell::Flt(value)) => visitor.visit_f64(*value),
+            _ => Err(type_error("a number", &self.exp)),
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = self.string()?;
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(type_error("a one-character string", &self.exp)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let bytes = self
+            .items("a list of bytes")?
+            .into_iter()
+            .map(|byte| u8::deserialize(Deserializer { exp: byte }))
+            .collect::<Result<Vec<u8>, Error>>()?;
+        visitor.visit_byte_buf(bytes)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.exp {
+            SExpression::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.exp {
+            SExpression::Null => visitor.visit_unit(),
+            SExpression::List(items) if items.is_empty() => visitor.visit_unit(),
+            _ => Err(type_error("#nil", &self.exp)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if name != SEXPRESSION_TOKEN {
+            return visitor.visit_newtype_struct(self);
+        }
+        match name_of(&self.exp) {
+            Some(name) => visitor.visit_enum(name.into_deserializer()),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqAccess::new(self.items("a list")?))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(MapAccess::new(self.entries()?))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    // A bare symbol is a unit variant; a list starting with one holds the
+    // variant's contents after the name.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if let Some(name) = name_of(&self.exp) {
+            return visitor.visit_enum(name.into_deserializer());
+        }
+        let items = self.items("a variant name or a list starting with one")?;
+        match items.split_first().map(|(tag, rest)| (name_of(tag), rest)) {
+            Some((Some(variant), rest)) => visitor.visit_enum(EnumAccess {
+                variant,
+                contents: rest.to_vec(),
+            }),
+            _ => Err(type_error("a list starting with a variant name", &self.exp)),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+} // impl Deserializer
+
+struct SeqAccess {
+    items: std::vec::IntoIter<SExpression>,
+}
+
+impl SeqAccess {
+    fn new(items: Vec<SExpression>) -> Self {
+        SeqAccess {
+            items: items.into_iter(),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some(exp) => seed.deserialize(Deserializer { exp }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess {
+    entries: std::vec::IntoIter<(SExpression, SExpression)>,
+    value: Option<SExpression>,
+}
+
+impl MapAccess {
+    fn new(entries: Vec<(SExpression, SExpression)>) -> Self {
+        MapAccess {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer { exp: key }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(exp) => seed.deserialize(Deserializer { exp }),
+            None => Err(Error::Data("Map value without a key".to_string())),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    contents: Vec<SExpression>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess), Error> {
+        let variant = seed.deserialize(self.variant.clone().into_deserializer())?;
+        Ok((variant, VariantAccess { enum_access: self }))
+    }
+}
+
+struct VariantAccess {
+    enum_access: EnumAccess,
+}
+
+impl VariantAccess {
+    fn contents(self) -> SExpression {
+        SExpression::List(List::make_from_sexps(self.enum_access.contents))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.enum_access.contents.len() {
+            0 => Ok(()),
+            _ => Err(type_error("a variant with no contents", &self.contents())),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let mut contents = self.enum_access.contents;
+        match contents.len() {
+            1 => seed.deserialize(Deserializer {
+                exp: contents.remove(0),
+            }),
+            _ => Err(type_error(
+                "one value after the variant name",
+                &SExpression::List(List::make_from_sexps(contents)),
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqAccess::new(self.enum_access.contents))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let deserializer = Deserializer {
+            exp: self.contents(),
+        };
+        de::Deserializer::deserialize_map(deserializer, visitor)
+    }
+}
+
+// SExpressions can be read from any serde format. From this one, symbols stay
+// symbols; from others a symbol written out earlier comes back as a string.
+impl<'de> Deserialize<'de> for SExpression {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(SEXPRESSION_TOKEN, SExpressionVisitor)
+    }
+}
+
+struct SExpressionVisitor;
+
+impl<'de> Visitor<'de> for SExpressionVisitor {
+    type Value = SExpression;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("s-expression data")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<SExpression, E> {
+        Ok(SExpression::Cell(Cell::Bool(value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<SExpression, E> {
+        Ok(SExpression::Cell(Cell::Int(value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<SExpression, E> {
+        match i64::try_from(value) {
+            Ok(value) => self.visit_i64(value),
+            Err(_) => self.visit_f64(value as f64),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<SExpression, E> {
+        Ok(SExpression::Cell(Cell::Flt(value)))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<SExpression, E> {
+        Ok(SExpression::Cell(Cell::Str(value.to_string())))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<SExpression, E> {
+        Ok(SExpression::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<SExpression, E> {
+        Ok(SExpression::Null)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<SExpression, D::Error> {
+        SExpression::deserialize(deserializer)
+    }
+
+    // Other formats answer the newtype struct request with the value itself
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<SExpression, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<SExpression, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<SExpression>()? {
+            items.push(item);
+        }
+        Ok(SExpression::List(List::make_from_sexps(items)))
+    }
+
+    // Maps become association lists
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<SExpression, A::Error> {
+        let mut entries = Vec::new();
+        while let Some((key, value)) = map.next_entry::<SExpression, SExpression>()? {
+            entries.push(SExpression::List(List::make_from_sexps(vec![key, value])));
+        }
+        Ok(SExpression::List(List::make_from_sexps(entries)))
+    }
+
+    // Only this format sends a symbol, as a unit variant named after it
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<SExpression, A::Error> {
+        use serde::de::VariantAccess;
+        let (name, variant): (String, _) = data.variant()?;
+        variant.unit_variant()?;
+        Ok(SExpression::Cell(symbol_cell(&name)))
+    }
+}
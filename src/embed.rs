@@ -0,0 +1,374 @@
+use crate::compiler::{compile_top_level, disassemble_all};
+use crate::context::Context;
+use crate::convert::{FromArgs, ToScheme};
+use crate::error::Error;
+use crate::exceptions;
+use crate::interpreter::{Environment, Redefinition};
+use crate::list::List;
+use crate::load;
+use crate::native::{Arity, NativeFunction};
+use crate::parser::{FormReader, ParseError, Position};
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use crate::symbols::{intern, symbol_name};
+use crate::vm::Vm;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+// The tree-walking evaluator is the default; the bytecode VM runs the same
+// programs faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    TreeWalker,
+    Vm,
+}
+
+// An interpreter with its own global environment, for running Scheme code
+// from a Rust program:
+//
+//     let mut interp = Interpreter::new();
+//     interp.eval_str("(define (square x) (* x x))")?;
+//     let nine = interp.call("square", vec![SExpression::Cell(Cell::Int(3))])?;
+//
+// Definitions stay around between calls, so code can be loaded once and its
+// functions called many times.
+pub struct Interpreter {
+    env: Environment,
+    backend: Backend,
+    vm: Vm,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter::with_backend(Backend::TreeWalker)
+    }
+
+    pub fn with_backend(backend: Backend) -> Self {
+        Interpreter {
+            env: Environment::new(),
+            backend,
+            vm: Vm::new(),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    // Forget every definition and start over, keeping the settings made
+    // with the set_ and add_ methods
+    pub fn reset(&mut self) {
+        let context = self.env.context().fresh_copy();
+        self.env = Environment::with_context(Rc::new(context));
+        self.vm = Vm::new();
+    }
+
+    // Run a program and give the value of its last top-level form
+    pub fn eval_str(&mut self, program: &str) -> Result<SExpression, Error> {
+        let mut results = self.eval_forms(program)?;
+        Ok(results.pop().unwrap_or(SExpression::Null))
+    }
+
+    // Run a program and give the value of each of its top-level forms. Each
+    // form runs as soon as it's read, so the ones before an error have run.
+    pub fn eval_forms(&mut self, program: &str) -> Result<Vec<SExpression>, Error> {
+        self.collect_forms(program, None)
+    }
+
+    // Run a program a form at a time and hand each form's value, or its
+    // error, to each(), which gives back whether to carry on. Going on after
+    // an error runs the rest of the program, so every form that fails can be
+    // reported; nothing runs after an exit. With a source name, errors say
+    // where in it the form that failed starts.
+    pub fn eval_forms_each<F>(&mut self, program: &str, source: Option<&str>, mut each: F)
+    where
+        F: FnMut(Result<SExpression, Error>) -> bool,
+    {
+        for form in FormReader::new(program) {
+            let result = match form {
+                Ok((form, position)) => match self.eval(form) {
+                    Err(Error::Eval(message)) if source.is_some() => Err(Error::Eval(format!(
+                        "{}: {}",
+                        location(source, position),
+                        message
+                    ))),
+                    result => result,
+                },
+                Err((ParseError::Reason(reason), position)) => Err(Error::Parse(format!(
+                    "{}: {}",
+                    location(source, position),
+                    reason
+                ))),
+            };
+            let exit = matches!(result, Err(Error::Exit(_)));
+            if !each(result) || exit {
+                return;
+            }
+        }
+    }
+
+    fn collect_forms(
+        &mut self,
+        program: &str,
+        source: Option<&str>,
+    ) -> Result<Vec<SExpression>, Error> {
+        let mut results = Vec::new();
+        let mut failure = None;
+        self.eval_forms_each(program, source, |result| match result {
+            Ok(value) => {
+                results.push(value);
+                true
+            }
+            Err(error) => {
+                failure = Some(error);
+                false
+            }
+        });
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(results),
+        }
+    }
+
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<SExpression, Error> {
+        let mut results = self.eval_file_forms(path)?;
+        Ok(results.pop().unwrap_or(SExpression::Null))
+    }
+
+    // Run a program file and give the value of each of its top-level forms.
+    // Files it loads are looked for next to it first.
+    pub fn eval_file_forms<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<SExpression>, Error> {
+        let path = path.as_ref();
+        let program = read_program(path)?;
+        let source = path.display().to_string();
+        let context = self.env.context().clone();
+        load::with_loading(&context, path, || {
+            self.collect_forms(&program, Some(&source))
+        })
+        .map_err(Error::Eval)?
+    }
+
+    // eval_forms_each for a program file. The error is for a file that can't
+    // be read or is already being loaded.
+    pub fn eval_file_each<P, F>(&mut self, path: P, each: F) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(Result<SExpression, Error>) -> bool,
+    {
+        let path = path.as_ref();
+        let program = read_program(path)?;
+        let source = path.display().to_string();
+        let context = self.env.context().clone();
+        load::with_loading(&context, path, || {
+            self.eval_forms_each(&program, Some(&source), each)
+        })
+        .map_err(Error::Eval)
+    }
+
+    // What a top-level define does to a name that's already defined:
+    // Redefinition::Allow (the default) gives it the new value, Warn does too
+    // but says so on stderr, and Error refuses.
+    pub fn set_redefinition(&mut self, setting: Redefinition) {
+        self.env.context().redefinition.set(setting);
+    }
+
+    // What (command-line) gives back: the program's name, then its arguments
+    pub fn set_command_line(&mut self, arguments: Vec<String>) {
+        *self.env.context().command_line.borrow_mut() = arguments;
+    }
+
+    // Print each call to a lambda on stderr, or stop
+    pub fn set_tracing(&mut self, on: bool) {
+        self.env.context().tracing.set(on);
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.env.context().tracing.get()
+    }
+
+    // Another directory for load and include to look in, after the
+    // directory of the file doing the loading
+    pub fn add_search_path<P: AsRef<Path>>(&mut self, directory: P) {
+        load::add_search_directory(self.env.context(), directory.as_ref());
+    }
+
+    // Add the directories listed in the RS_LISP_PATH environment variable
+    pub fn add_search_path_from_env(&mut self) {
+        if let Some(paths) = env::var_os(load::SEARCH_PATH_VARIABLE) {
+            for directory in env::split_paths(&paths) {
+                self.add_search_path(directory);
+            }
+        }
+    }
+
+    // Evaluate an expression that's already been built, rather than parsed
+    pub fn eval(&mut self, exp: SExpression) -> Result<SExpression, Error> {
+        let result = match self.backend {
+            Backend::TreeWalker => self.env.evaluate(exp),
+            Backend::Vm => self.vm.evaluate(&exp, &self.env),
+        };
+        result.map_err(|message| eval_error(self.env.context(), message))
+    }
+
+    // Call the procedure a global is bound to. The arguments are values, so
+    // they aren't evaluated again.
+    pub fn call(&mut self, name: &str, args: Vec<SExpression>) -> Result<SExpression, Error> {
+        let procedure = self.get_global(name)?;
+        let result = match self.backend {
+            Backend::TreeWalker => self.env.apply_procedure(procedure, args),
+            Backend::Vm => self.vm.apply(procedure, args, &self.env),
+        };
+        result.map_err(|message| eval_error(self.env.context(), message))
+    }
+
+    pub fn get_global(&self, name: &str) -> Result<SExpression, Error> {
+        self.env
+            .get_definition_by_symbol(intern(name))
+            .map_err(|_| Error::Undefined(name.to_string()))
+    }
+
+    // Define a global, or change its value if it's already defined
+    pub fn set_global(&mut self, name: &str, value: SExpression) {
+        self.env.define_or_replace(intern(name), value);
+    }
+
+    // Make a Rust function callable from Scheme as a global procedure:
+    //
+    //     interp.register_fn("double", 1, |args| match &args[0] {
+    //         SExpression::Cell(Cell::Int(n)) => Ok(SExpression::Cell(Cell::Int(n * 2))),
+    //         other => Err(format!("double needs an integer, got {}", other.print())),
+    //     });
+    //
+    // The arity is checked before the function is called, so args has a
+    // valid length. Returning Err, or panicking, gives a Scheme error.
+    pub fn register_fn<F>(&mut self, name: &str, arity: impl Into<Arity>, function: F)
+    where
+        F: Fn(&[SExpression]) -> Result<SExpression, String> + 'static,
+    {
+        self.register_documented_fn(name, arity, "", function);
+    }
+
+    // The same as register_fn with a description of the function, which
+    // doc() gives back.
+    pub fn register_documented_fn<F>(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        doc: &str,
+        function: F,
+    ) where
+        F: Fn(&[SExpression]) -> Result<SExpression, String> + 'static,
+    {
+        let native = NativeFunction::new(name, arity.into(), doc, function);
+        self.set_global(name, SExpression::Cell(Cell::Native(Rc::new(native))));
+    }
+
+    // Like register_fn for a function that takes and returns Rust values. The
+    // arguments arrive as a tuple, one element per argument, which also sets
+    // the arity:
+    //
+    //     interp.register_typed_fn("hypot", |(a, b): (f64, f64)| Ok((a * a + b * b).sqrt()));
+    pub fn register_typed_fn<A, R, F>(&mut self, name: &str, function: F)
+    where
+        A: FromArgs,
+        R: ToScheme,
+        F: Fn(A) -> Result<R, String> + 'static,
+    {
+        self.register_fn(name, A::arity(), move |args| {
+            Ok(function(A::from_args(args)?)?.to_scheme())
+        });
+    }
+
+    // The names of all the globals, for listing or completing them
+    pub fn global_names(&self) -> Vec<String> {
+        self.env.names()
+    }
+
+    // The parameter names of the lambda a global is bound to
+    pub fn parameters(&self, name: &str) -> Option<Vec<String>> {
+        match self.get_global(name) {
+            Ok(SExpression::Cell(Cell::Lambda(template, _))) => Some(
+                template.slots[..template.param_count]
+                    .iter()
+                    .map(|symbol| symbol_name(*symbol))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    // The doc string of the native function a global is bound to
+    pub fn doc(&self, name: &str) -> Option<String> {
+        match self.get_global(name) {
+            Ok(SExpression::Cell(Cell::Native(function))) => Some(function.doc().to_string()),
+            _ => None,
+        }
+    }
+} // impl Interpreter
+
+// The bytecode for each top-level form of a program, and for the lambdas
+// in them, without running anything
+pub fn disassemble(program: &str) -> Result<String, Error> {
+    let context = Context::default();
+    let mut listings = Vec::new();
+    for (number, form) in parse_program(program)?.to_vec().iter().enumerate() {
+        let chunk = compile_top_level(form, &context).map_err(Error::Eval)?;
+        let name = format!("top-level form {}", number + 1);
+        listings.push(disassemble_all(&chunk, &name, &context).map_err(Error::Eval)?);
+    }
+    Ok(listings.join("\n\n"))
+}
+
+// The top-level forms of a program, without running them
+pub fn parse(program: &str) -> Result<Vec<SExpression>, Error> {
+    Ok(parse_program(program)?.to_vec())
+}
+
+pub fn read_program(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|error| Error::Io(path.display().to_string(), error))
+}
+
+// All the top-level forms in a program, as one list
+pub(crate) fn parse_program(program: &str) -> Result<List, Error> {
+    let mut forms = Vec::new();
+    for form in FormReader::new(program) {
+        match form {
+            Ok((form, _)) => forms.push(form),
+            Err((ParseError::Reason(reason), position)) => {
+                return Err(Error::Parse(format!(
+                    "{}: {}",
+                    location(None, position),
+                    reason
+                )))
+            }
+        }
+    }
+    Ok(List::make_from_sexps(forms))
+}
+
+// Where a form starts, for error messages: file:line:column for a file, or
+// just the line and column
+pub(crate) fn location(source: Option<&str>, position: Position) -> String {
+    match source {
+        Some(source) => format!("{}:{}:{}", source, position.line, position.column),
+        None => format!("line {}, column {}", position.line, position.column),
+    }
+}
+
+// An error from evaluating, unless it was really a call to exit
+fn eval_error(context: &Context, message: String) -> Error {
+    // Nothing caught it, so what was raised is finished with
+    exceptions::clear(context);
+    match context.exit_code.take() {
+        Some(code) => Error::Exit(code),
+        None => Error::Eval(message),
+    }
+}
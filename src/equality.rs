@@ -0,0 +1,95 @@
+use crate::list::List;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use std::rc::Rc;
+
+// The three standard equivalence predicates, from strictest to loosest:
+//
+//  eq?     Are these the same object? Lists, vectors and hash tables compare
+//          by identity.
+//  eqv?    Like eq?, but numbers are compared by value.
+//  equal?  Do they print the same? Lists and vectors are compared element by
+//          element.
+//
+// Numbers, booleans and symbols aren't boxed in this interpreter, so eq? can
+// compare them by value just like eqv? does. Strings have no identity either:
+// they're copied as values whenever they're passed around, so all three
+// predicates compare them by their characters.
+
+pub fn is_eq(left: &SExpression, right: &SExpression) -> bool {
+    is_eqv(left, right)
+}
+
+pub fn is_eqv(left: &SExpression, right: &SExpression) -> bool {
+    match (left, right) {
+        (SExpression::Null, SExpression::Null) => true,
+        (SExpression::List(l), SExpression::List(r)) => l.same_object(r),
+        (SExpression::Cell(l), SExpression::Cell(r)) => cells_eqv(l, r),
+        _ => false,
+    }
+}
+
+pub fn is_equal(left: &SExpression, right: &SExpression) -> bool {
+    match (left, right) {
+        (SExpression::List(l), SExpression::List(r)) => lists_equal(l, r),
+        (SExpression::Cell(Cell::Vector(l)), SExpression::Cell(Cell::Vector(r))) => {
+            let (l, r) = (l.borrow(), r.borrow());
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(i, j)| is_equal(i, j))
+        }
+        _ => is_eqv(left, right),
+    }
+}
+
+fn lists_equal(left: &List, right: &List) -> bool {
+    if left.same_object(right) {
+        return true;
+    }
+    let mut remaining_left = left.clone();
+    let mut remaining_right = right.clone();
+    while !remaining_left.is_empty() && !remaining_right.is_empty() {
+        if !is_equal(&remaining_left.first(), &remaining_right.first()) {
+            return false;
+        }
+        remaining_left = remaining_left.rest();
+        remaining_right = remaining_right.rest();
+    }
+    remaining_left.is_empty() && remaining_right.is_empty()
+}
+
+fn cells_eqv(left: &Cell, right: &Cell) -> bool {
+    match (left, right) {
+        // The reader makes every number literal a float, while counts like
+        // vector-length and numbers from Rust are integers. There's no
+        // exactness to tell them apart by, so 1 and 1.0 are the same number.
+        (Cell::Int(i), Cell::Int(j)) => i == j,
+        (Cell::Flt(i), Cell::Flt(j)) => i.to_bits() == j.to_bits(),
+        (Cell::Int(i), Cell::Flt(f)) | (Cell::Flt(f), Cell::Int(i)) => {
+            (*i as f64).to_bits() == f.to_bits()
+        }
+        (Cell::Str(i), Cell::Str(j)) => i == j,
+        (Cell::Bool(i), Cell::Bool(j)) => i == j,
+        (Cell::Symbol(i, _), Cell::Symbol(j, _)) => i == j,
+        (Cell::Op(i), Cell::Op(j)) => i == j,
+        (Cell::Logical(i), Cell::Logical(j)) => i == j,
+        (Cell::Special(i), Cell::Special(j)) => i == j,
+        (Cell::Core(i), Cell::Core(j)) => i == j,
+        // A procedure is the same procedure if it's the same lambda closed over
+        // the same environment.
+        (Cell::Lambda(template_i, env_i), Cell::Lambda(template_j, env_j)) => {
+            Rc::ptr_eq(template_i, template_j) && env_i.same_environment(env_j)
+        }
+        (Cell::Local(depth_i, slot_i, _), Cell::Local(depth_j, slot_j, _)) => {
+            depth_i == depth_j && slot_i == slot_j
+        }
+        (Cell::Template(i), Cell::Template(j)) => Rc::ptr_eq(i, j),
+        (Cell::HashTable(i), Cell::HashTable(j)) => Rc::ptr_eq(i, j),
+        (Cell::Native(i), Cell::Native(j)) => Rc::ptr_eq(i, j),
+        (Cell::Vector(i), Cell::Vector(j)) => Rc::ptr_eq(i, j),
+        (Cell::Port(i), Cell::Port(j)) => Rc::ptr_eq(i, j),
+        (Cell::Eof, Cell::Eof) => true,
+        (Cell::JsonNull, Cell::JsonNull) => true,
+        (Cell::Environment(i), Cell::Environment(j)) => i.same_environment(j),
+        (Cell::Condition(i), Cell::Condition(j)) => Rc::ptr_eq(i, j),
+        _ => false,
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::io;
+
+// Errors from the embedding API. Inside the interpreter errors are still plain
+// strings; they get sorted into one of these on the way out.
+#[derive(Debug)]
+pub enum Error {
+    // The program text couldn't be parsed
+    Parse(String),
+    // Evaluating the program failed
+    Eval(String),
+    // A program file couldn't be read
+    Io(String, io::Error),
+    // There's no global by this name
+    Undefined(String),
+    // A Rust value couldn't be converted to or from s-expression data
+    Data(String),
+    // The program called (exit) with this code
+    Exit(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(reason) => write!(f, "Parse error: {}", reason),
+            Error::Eval(message) => write!(f, "{}", message),
+            Error::Io(path, error) => write!(f, "Can't read {}: {}", path, error),
+            Error::Undefined(name) => write!(f, "Symbol {} not defined.", name),
+            Error::Data(message) => write!(f, "{}", message),
+            Error::Exit(code) => write!(f, "Exit with code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Data(message.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Data(message.to_string())
+    }
+}
@@ -0,0 +1,265 @@
+use crate::context::Context;
+use crate::lexical::lambda_body;
+use crate::list::List;
+use crate::primitives::{Cell, CoreFunc, SpecialForm};
+use crate::symbolic_expression::SExpression;
+use crate::symbols::intern;
+use std::rc::Rc;
+
+// Exceptions, R7RS style. Errors travel up the Rust stack as Err(String) like
+// every other error here. When Scheme code raises an object, the object waits
+// in the interpreter's Context while its error unwinds, and whatever catches
+// the error takes it from there. An error that came from the interpreter
+// itself has no object waiting, so one is made from its message.
+//
+// with-exception-handler handlers are kept on a stack, also in the Context, so
+// an interpreter run inside a native function has handlers of its own.
+// raise-continuable calls the innermost one where it is and carries on with
+// what it gives back; everything else unwinds to the with-exception-handler
+// first, and its handler is called there. guard is with-exception-catcher
+// with its clauses made into a handler, and goes on the stack too, as None,
+// so that a raise-continuable inside it unwinds to it.
+
+// What (error message irritant ...) raises, and what an error from the
+// interpreter is turned into when it's caught
+pub struct Condition {
+    pub message: String,
+    pub irritants: Vec<SExpression>,
+}
+
+impl Condition {
+    pub fn new(message: String, irritants: Vec<SExpression>) -> Self {
+        Condition { message, irritants }
+    }
+
+    // The message followed by the irritants, as an error that wasn't caught
+    // is reported
+    pub fn describe(&self) -> String {
+        let mut text = self.message.clone();
+        for irritant in &self.irritants {
+            text.push(' ');
+            text.push_str(&irritant.write());
+        }
+        text
+    }
+
+    pub fn print(&self) -> String {
+        format!("#<error {}>", self.describe())
+    }
+}
+
+fn handler_count(context: &Context) -> usize {
+    context.handlers.borrow().len()
+}
+
+// Raise an object: gives the error message to return, for when nothing
+// handles it
+pub fn raise(context: &Context, object: SExpression) -> String {
+    let message = match &object {
+        SExpression::Cell(Cell::Condition(condition)) => condition.describe(),
+        _ => format!("Uncaught exception: {}", object.write()),
+    };
+    let depth = handler_count(context);
+    *context.raised.borrow_mut() = Some((object, depth));
+    message
+}
+
+// Forget an object that was raised and never handled, once its error has
+// got to the top
+pub fn clear(context: &Context) {
+    context.raised.borrow_mut().take();
+}
+
+// What was raised to give this error
+fn take_raised(context: &Context, message: String) -> SExpression {
+    match context.raised.borrow_mut().take() {
+        Some((object, _)) => object,
+        None => SExpression::Cell(Cell::Condition(Rc::new(Condition::new(
+            message,
+            Vec::new(),
+        )))),
+    }
+}
+
+// Takes the handlers off the stack again even if body failed
+struct Uninstall<'a>(&'a Context, usize);
+
+impl Drop for Uninstall<'_> {
+    fn drop(&mut self) {
+        self.0.handlers.borrow_mut().truncate(self.1);
+    }
+}
+
+// Run body with a handler installed: a procedure for with-exception-handler,
+// or None for a guard. An error coming out of body that this handler is the
+// one to handle gives back what was raised; anything else comes back as it
+// was. An exit is never handled.
+pub fn catch(
+    context: &Context,
+    handler: Option<SExpression>,
+    body: impl FnOnce() -> Result<SExpression, String>,
+) -> Result<Result<SExpression, String>, SExpression> {
+    let depth = handler_count(context);
+    context.handlers.borrow_mut().push(handler);
+    let result = {
+        let _uninstall = Uninstall(context, depth);
+        body()
+    };
+    match result {
+        Err(message) if !context.exiting() => {
+            let ours = match &*context.raised.borrow() {
+                Some((_, raised_depth)) => *raised_depth > depth,
+                None => true,
+            };
+            if ours {
+                Err(take_raised(context, message))
+            } else {
+                Ok(Err(message))
+            }
+        }
+        result => Ok(result),
+    }
+}
+
+// The handler raise-continuable calls: the innermost one, unless that's a
+// guard, which has to be unwound to
+pub fn innermost_handler(context: &Context) -> Option<SExpression> {
+    context.handlers.borrow().last().cloned().flatten()
+}
+
+// Run a handler for raise-continuable. It runs with the handlers that were
+// there when its own was installed, so raising in it goes further out.
+pub fn outside_handler(
+    context: &Context,
+    body: impl FnOnce() -> Result<SExpression, String>,
+) -> Result<SExpression, String> {
+    let handler = context.handlers.borrow_mut().pop();
+    let depth = handler_count(context);
+    let result = body();
+    if let Some(handler) = handler {
+        context.handlers.borrow_mut().push(handler);
+    }
+    if let Err(message) = &result {
+        if !context.exiting() {
+            let mut raised = context.raised.borrow_mut();
+            match raised.as_mut() {
+                Some((_, raised_depth)) => *raised_depth = (*raised_depth).min(depth),
+                None => {
+                    let condition = Condition::new(message.clone(), Vec::new());
+                    let object = SExpression::Cell(Cell::Condition(Rc::new(condition)));
+                    *raised = Some((object, depth));
+                }
+            }
+        }
+    }
+    result
+}
+
+fn list(items: Vec<SExpression>) -> SExpression {
+    SExpression::List(List::make_from_sexps(items))
+}
+
+fn special(form: SpecialForm) -> SExpression {
+    SExpression::Cell(Cell::Special(form))
+}
+
+fn is_symbol(value: &SExpression, name: &str) -> bool {
+    matches!(value, SExpression::Cell(Cell::Symbol(_, symbol)) if symbol == name)
+}
+
+// (guard (var clause ...) body ...) is
+//
+//     (with-exception-catcher
+//       (lambda (var) clauses as nested ifs, ending with (raise var))
+//       (lambda () body ...))
+//
+// so the lexical analysis and the compiler see nothing but lambdas and calls.
+// The clauses are like cond's: (test body ...), (test => receiver), (test)
+// and (else body ...).
+pub fn expand_guard(args: &List) -> Result<SExpression, String> {
+    let (variable, clauses) = match *args.first() {
+        SExpression::List(spec) => match *spec.first() {
+            variable @ SExpression::Cell(Cell::Symbol(..)) => (variable, spec.rest().to_vec()),
+            _ => return Err(format!("guard needs a variable name, got {}", spec.print())),
+        },
+        other => {
+            return Err(format!(
+                "guard needs (variable clause ...) first, got {}",
+                other.print()
+            ))
+        }
+    };
+
+    // Nothing matched: raise it again for a handler further out
+    let mut handled = list(vec![
+        SExpression::Cell(Cell::Core(CoreFunc::Raise)),
+        variable.clone(),
+    ]);
+    // The value of the test, for => and clauses with no body
+    let value = SExpression::Cell(Cell::Symbol(
+        intern("%guard-value"),
+        "%guard-value".to_string(),
+    ));
+    for (index, clause) in clauses.iter().enumerate().rev() {
+        let parts = match clause {
+            SExpression::List(parts) if !parts.is_empty() => parts.to_vec(),
+            _ => return Err(format!("Bad guard clause {}", clause.print())),
+        };
+        let body = List::make_from_sexps(parts[1..].to_vec());
+        handled = match parts.as_slice() {
+            [test, ..] if is_symbol(test, "else") => {
+                if index != clauses.len() - 1 {
+                    return Err("else must be the last guard clause".to_string());
+                }
+                lambda_body(body)
+            }
+            // A value worth passing on usually isn't a boolean, so anything
+            // but #f counts here
+            [test, arrow, receiver] if is_symbol(arrow, "=>") => {
+                let passed = list(vec![receiver.clone(), value.clone()]);
+                pass_value(test, &value, passed, handled)
+            }
+            [test] => pass_value(test, &value, value.clone(), handled),
+            [test, ..] => list(vec![
+                special(SpecialForm::If),
+                test.clone(),
+                lambda_body(body),
+                handled,
+            ]),
+            [] => unreachable!(),
+        };
+    }
+
+    let handler = list(vec![
+        special(SpecialForm::Lambda),
+        list(vec![variable]),
+        handled,
+    ]);
+    let mut thunk = vec![special(SpecialForm::Lambda), list(Vec::new())];
+    thunk.extend(args.rest().to_vec());
+    Ok(list(vec![
+        SExpression::Cell(Cell::Core(CoreFunc::WithExceptionCatcher)),
+        handler,
+        list(thunk),
+    ]))
+}
+
+// ((lambda (value) (if (eq? value #f) otherwise then)) test)
+fn pass_value(
+    test: &SExpression,
+    value: &SExpression,
+    then: SExpression,
+    otherwise: SExpression,
+) -> SExpression {
+    let is_false = list(vec![
+        SExpression::Cell(Cell::Core(CoreFunc::IsEq)),
+        value.clone(),
+        SExpression::Cell(Cell::Bool(false)),
+    ]);
+    let lambda = list(vec![
+        special(SpecialForm::Lambda),
+        list(vec![value.clone()]),
+        list(vec![special(SpecialForm::If), is_false, otherwise, then]),
+    ]);
+    list(vec![lambda, test.clone()])
+}
@@ -0,0 +1,232 @@
+use crate::equality::{is_eq, is_equal, is_eqv};
+use crate::list::List;
+use crate::primitives::Cell;
+use crate::primitives::CoreFunc;
+use crate::symbolic_expression::SExpression;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// The predicate a hash table uses to decide if two keys are the same key. It's
+// chosen when the table is made, as in (make-hash-table string=?)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Equivalence {
+    Eq,
+    Eqv,
+    Equal,
+    StringEqual,
+}
+
+impl Equivalence {
+    // The equivalence procedures are passed to make-hash-table as values, so
+    // they arrive here as core function cells.
+    pub fn from_procedure(procedure: &SExpression) -> Result<Equivalence, String> {
+        match procedure {
+            SExpression::Cell(Cell::Core(CoreFunc::IsEq)) => Ok(Equivalence::Eq),
+            SExpression::Cell(Cell::Core(CoreFunc::IsEqv)) => Ok(Equivalence::Eqv),
+            SExpression::Cell(Cell::Core(CoreFunc::IsEqual)) => Ok(Equivalence::Equal),
+            SExpression::Cell(Cell::Core(CoreFunc::StringEqual)) => Ok(Equivalence::StringEqual),
+            _ => Err(format!(
+                "A hash table can only compare keys with eq?, eqv?, equal? or string=?, got {}",
+                procedure.print()
+            )),
+        }
+    }
+
+    pub fn print(self) -> &'static str {
+        match self {
+            Equivalence::Eq => CoreFunc::IsEq.print(),
+            Equivalence::Eqv => CoreFunc::IsEqv.print(),
+            Equivalence::Equal => CoreFunc::IsEqual.print(),
+            Equivalence::StringEqual => CoreFunc::StringEqual.print(),
+        }
+    }
+}
+
+// Keys are hashed into buckets; all keys in a bucket are compared with the
+// table's equivalence. A BTreeMap keeps iteration order stable between runs
+// so printing a table always gives the same text.
+#[derive(Clone)]
+pub struct HashTable {
+    equivalence: Equivalence,
+    buckets: BTreeMap<u64, Vec<(SExpression, SExpression)>>,
+    count: usize,
+}
+
+impl HashTable {
+    pub fn new(equivalence: Equivalence) -> Self {
+        HashTable {
+            equivalence,
+            buckets: BTreeMap::new(),
+            count: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn get(&self, key: &SExpression) -> Result<Option<SExpression>, String> {
+        let hash = self.hash_key(key)?;
+        let found = self.buckets.get(&hash).and_then(|bucket| {
+            bucket
+                .iter()
+                .find(|(k, _)| self.same_key(k, key))
+                .map(|(_, v)| v.clone())
+        });
+        Ok(found)
+    }
+
+    pub fn contains(&self, key: &SExpression) -> Result<bool, String> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    pub fn set(&mut self, key: SExpression, value: SExpression) -> Result<(), String> {
+        let hash = self.hash_key(&key)?;
+        let equivalence = self.equivalence;
+        let bucket = self.buckets.entry(hash).or_default();
+        match bucket
+            .iter_mut()
+            .find(|(k, _)| keys_match(equivalence, k, &key))
+        {
+            Some(entry) => entry.1 = value,
+            None => {
+                bucket.push((key, value));
+                self.count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns true if the key was in the table.
+    pub fn delete(&mut self, key: &SExpression) -> Result<bool, String> {
+        let hash = self.hash_key(key)?;
+        let equivalence = self.equivalence;
+        let mut removed = false;
+        if let Some(bucket) = self.buckets.get_mut(&hash) {
+            if let Some(position) = bucket
+                .iter()
+                .position(|(k, _)| keys_match(equivalence, k, key))
+            {
+                bucket.remove(position);
+                removed = true;
+            }
+            if bucket.is_empty() {
+                self.buckets.remove(&hash);
+            }
+        }
+        if removed {
+            self.count -= 1;
+        }
+        Ok(removed)
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.count = 0;
+    }
+
+    pub fn entries(&self) -> Vec<(SExpression, SExpression)> {
+        self.buckets
+            .values()
+            .flat_map(|bucket| bucket.iter().cloned())
+            .collect()
+    }
+
+    pub fn keys(&self) -> Vec<SExpression> {
+        self.entries().into_iter().map(|(k, _)| k).collect()
+    }
+
+    pub fn values(&self) -> Vec<SExpression> {
+        self.entries().into_iter().map(|(_, v)| v).collect()
+    }
+
+    // Each entry becomes (cons key value)
+    pub fn to_alist(&self) -> List {
+        let pairs = self
+            .entries()
+            .into_iter()
+            .map(|(k, v)| SExpression::List(List::construct_list(k, v)))
+            .collect();
+        List::make_from_sexps(pairs)
+    }
+
+    pub fn print(&self) -> String {
+        let mut printed = vec![self.equivalence.print().to_string()];
+        for (key, value) in self.entries() {
+            printed.push(format!("({} . {})", key.print(), value.print()));
+        }
+        format!("#hash-table({})", printed.join(" "))
+    }
+
+    fn same_key(&self, left: &SExpression, right: &SExpression) -> bool {
+        keys_match(self.equivalence, left, right)
+    }
+
+    fn hash_key(&self, key: &SExpression) -> Result<u64, String> {
+        if self.equivalence == Equivalence::StringEqual
+            && !matches!(key, SExpression::Cell(Cell::Str(_)))
+        {
+            return Err(format!(
+                "Keys of a string=? hash table must be strings, got {}",
+                key.print()
+            ));
+        }
+        let mut hasher = DefaultHasher::new();
+        hash_expression(self.equivalence, key, &mut hasher);
+        Ok(hasher.finish())
+    }
+} // impl HashTable
+
+// Keys that match under the table's equivalence must hash the same, so an
+// equal? table hashes the structure of lists and vectors while eq? and eqv?
+// tables hash the object itself.
+fn hash_expression(equivalence: Equivalence, exp: &SExpression, hasher: &mut DefaultHasher) {
+    match exp {
+        SExpression::Null => 0.hash(hasher),
+        SExpression::List(list) => {
+            if equivalence == Equivalence::Equal {
+                1.hash(hasher);
+                let mut remaining = list.clone();
+                while !remaining.is_empty() {
+                    hash_expression(equivalence, &remaining.first(), hasher);
+                    remaining = remaining.rest();
+                }
+            } else {
+                (1, list.identity()).hash(hasher);
+            }
+        }
+        SExpression::Cell(cell) => match cell {
+            // Integers and floats with the same value are eqv?, so they hash
+            // the same too
+            Cell::Int(value) => (3, (*value as f64).to_bits()).hash(hasher),
+            Cell::Flt(value) => (3, value.to_bits()).hash(hasher),
+            Cell::Str(value) => (4, value).hash(hasher),
+            Cell::Bool(value) => (5, value).hash(hasher),
+            Cell::Symbol(number, _) => (6, number).hash(hasher),
+            Cell::HashTable(table) => (7, Rc::as_ptr(table) as usize).hash(hasher),
+            Cell::Vector(items) => {
+                if equivalence == Equivalence::Equal {
+                    9.hash(hasher);
+                    for item in items.borrow().iter() {
+                        hash_expression(equivalence, item, hasher);
+                    }
+                } else {
+                    (9, Rc::as_ptr(items) as usize).hash(hasher);
+                }
+            }
+            // Procedures and operators are rare as keys; they all share a bucket.
+            _ => 8.hash(hasher),
+        },
+    }
+}
+
+fn keys_match(equivalence: Equivalence, left: &SExpression, right: &SExpression) -> bool {
+    match equivalence {
+        Equivalence::Eq => is_eq(left, right),
+        Equivalence::Eqv => is_eqv(left, right),
+        // Keys were checked to be strings before getting here.
+        Equivalence::Equal | Equivalence::StringEqual => is_equal(left, right),
+    }
+}
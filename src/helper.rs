@@ -0,0 +1,305 @@
+use rs_lisp::primitives::{map_cell_from_string, Cell};
+use rs_lisp::{scan, Interpreter, Lexeme};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, ConditionalEventHandler, Context, Event, EventContext, Helper, RepeatCount};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// Terminal colors for the parts of a line
+const STRING_COLOR: &str = "\x1b[32m";
+const NUMBER_COLOR: &str = "\x1b[33m";
+const KEYWORD_COLOR: &str = "\x1b[35m";
+const BUILTIN_COLOR: &str = "\x1b[36m";
+const COMMENT_COLOR: &str = "\x1b[90m";
+const MATCHING_PAREN_COLOR: &str = "\x1b[1;34m";
+const HINT_COLOR: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+// Whether the input so far stops in the middle of a form: an open paren
+// that isn't closed yet, a string that isn't finished, or a quote with
+// nothing after it. Too many closing parens isn't something more input can
+// fix, so that's left for the parser to complain about.
+pub fn needs_more(text: &str) -> bool {
+    let mut depth = 0;
+    let mut last = Lexeme::Space;
+    for (lexeme, _) in scan(text) {
+        match lexeme {
+            Lexeme::Open | Lexeme::VectorStart => depth += 1,
+            Lexeme::Close => depth -= 1,
+            Lexeme::Str { finished: false } => return true,
+            Lexeme::Space | Lexeme::Comment => continue,
+            _ => {}
+        }
+        last = lexeme;
+    }
+    depth > 0 || last == Lexeme::Quote
+}
+
+// Where the paren of an opening lexeme is: #( has it second
+fn paren_at(lexeme: Lexeme, range: &Range<usize>) -> Option<usize> {
+    match lexeme {
+        Lexeme::Open | Lexeme::VectorStart | Lexeme::Close => Some(range.end - 1),
+        _ => None,
+    }
+}
+
+// The paren that goes with the one under the cursor, or the one just
+// before it
+fn matching_paren(line: &str, pos: usize) -> Option<usize> {
+    let mut open = Vec::new();
+    let mut pairs = HashMap::new();
+    for (lexeme, range) in scan(line) {
+        let paren = paren_at(lexeme, &range);
+        match (lexeme, paren) {
+            (Lexeme::Close, Some(close)) => {
+                if let Some(start) = open.pop() {
+                    pairs.insert(start, close);
+                    pairs.insert(close, start);
+                }
+            }
+            (_, Some(start)) => open.push(start),
+            _ => {}
+        }
+    }
+    pairs
+        .get(&pos)
+        .or_else(|| pos.checked_sub(1).and_then(|before| pairs.get(&before)))
+        .copied()
+}
+
+// The parameters still to come in the call the cursor is in
+pub struct ParameterHint(String);
+
+impl Hint for ParameterHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    // Only shown; there's nothing to fill in
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+// Rustyline has no prompt for the lines after the first, so Enter in the
+// middle of a form starts the new line with as many spaces as the
+// continuation prompt is wide, and the highlighter draws the prompt over
+// them. That way the cursor is where the editor thinks it is. Inside a
+// string the spaces would be part of it, so it's left to the validator to
+// add a plain newline.
+pub struct ContinueForm {
+    padding: String,
+}
+
+impl ContinueForm {
+    pub fn new(continuation_prompt: &str) -> Self {
+        ContinueForm {
+            padding: " ".repeat(continuation_prompt.chars().count()),
+        }
+    }
+}
+
+impl ConditionalEventHandler for ContinueForm {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let line = ctx.line();
+        let in_string = matches!(
+            scan(&line[..ctx.pos()]).last(),
+            Some((Lexeme::Str { finished: false }, _))
+        );
+        if needs_more(line) && !in_string {
+            Some(Cmd::Insert(1, format!("\n{}", self.padding)))
+        } else {
+            None
+        }
+    }
+}
+
+// Completion, hints and highlighting for the REPL. It knows the reserved
+// words, and the globals as of the last time refresh() was called.
+pub struct LispHelper {
+    reserved: HashMap<String, Cell>,
+    globals: Vec<String>,
+    parameters: HashMap<String, Vec<String>>,
+    // What a new line of a form starts with, and what's drawn over it
+    padding: String,
+    continuation_prompt: String,
+    color: bool,
+}
+
+impl LispHelper {
+    pub fn new(interp: &Interpreter, continuation_prompt: &str, color: bool) -> Self {
+        let mut helper = LispHelper {
+            reserved: map_cell_from_string(),
+            globals: Vec::new(),
+            parameters: HashMap::new(),
+            padding: format!("\n{}", ContinueForm::new(continuation_prompt).padding),
+            continuation_prompt: format!("\n{}", continuation_prompt),
+            color,
+        };
+        helper.refresh(interp);
+        helper
+    }
+
+    // Catch up with what's been defined since
+    pub fn refresh(&mut self, interp: &Interpreter) {
+        self.globals = interp.global_names();
+        self.parameters = self
+            .globals
+            .iter()
+            .filter_map(|name| Some((name.clone(), interp.parameters(name)?)))
+            .collect();
+    }
+
+    fn color(&self, word: &str) -> Option<&'static str> {
+        if word.parse::<f64>().is_ok() {
+            return Some(NUMBER_COLOR);
+        }
+        match self.reserved.get(word) {
+            Some(Cell::Special(_)) => Some(KEYWORD_COLOR),
+            Some(_) => Some(BUILTIN_COLOR),
+            None => None,
+        }
+    }
+}
+
+impl Completer for LispHelper {
+    type Candidate = String;
+
+    // Names starting with the word before the cursor
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = scan(&line[..pos])
+            .last()
+            .filter(|(lexeme, _)| *lexeme == Lexeme::Word)
+            .map_or(pos, |(_, range)| range.start);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        let mut names: Vec<String> = self
+            .reserved
+            .keys()
+            .chain(self.globals.iter())
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok((start, names))
+    }
+}
+
+impl Hinter for LispHelper {
+    type Hint = ParameterHint;
+
+    // After "(f " show the parameters f still needs, once a space says the
+    // next argument is coming.
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ParameterHint> {
+        if pos < line.len() {
+            return None;
+        }
+        // The name being called and how many arguments it has so far, for
+        // each list that's still open
+        let mut calls: Vec<(Option<&str>, usize)> = Vec::new();
+        let mut last = Lexeme::Space;
+        for (lexeme, range) in scan(line) {
+            match lexeme {
+                Lexeme::Open | Lexeme::VectorStart => calls.push((None, 0)),
+                // A list that's finished is one argument of the list it's in
+                Lexeme::Close => {
+                    calls.pop();
+                    if let Some(call) = calls.last_mut() {
+                        call.1 += 1;
+                    }
+                }
+                Lexeme::Word | Lexeme::Str { .. } => {
+                    if let Some(call) = calls.last_mut() {
+                        if call.1 == 0 && lexeme == Lexeme::Word {
+                            call.0 = Some(&line[range]);
+                        }
+                        call.1 += 1;
+                    }
+                }
+                _ => {}
+            }
+            last = lexeme;
+        }
+        if last != Lexeme::Space {
+            return None;
+        }
+        let (name, count) = calls.last()?;
+        let parameters = self.parameters.get((*name)?)?;
+        // The name itself was counted as the first part
+        let remaining = parameters.get(count.saturating_sub(1)..)?;
+        if remaining.is_empty() {
+            return None;
+        }
+        Some(ParameterHint(format!("{})", remaining.join(" "))))
+    }
+}
+
+impl Highlighter for LispHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let matching = matching_paren(line, pos);
+        let mut colored = String::with_capacity(line.len() * 2);
+        for (lexeme, range) in scan(line) {
+            let text = &line[range.clone()];
+            let color = match lexeme {
+                Lexeme::Str { .. } => Some(STRING_COLOR),
+                Lexeme::Comment => Some(COMMENT_COLOR),
+                Lexeme::Word => self.color(text),
+                _ if matching.is_some() && matching == paren_at(lexeme, &range) => {
+                    Some(MATCHING_PAREN_COLOR)
+                }
+                _ => None,
+            };
+            match color.filter(|_| self.color) {
+                Some(color) => {
+                    colored.push_str(color);
+                    colored.push_str(text);
+                    colored.push_str(RESET);
+                }
+                // The continuation prompt goes where ContinueForm left room
+                None if lexeme == Lexeme::Space => {
+                    colored.push_str(&text.replace(&self.padding, &self.continuation_prompt))
+                }
+                None => colored.push_str(text),
+            }
+        }
+        Cow::Owned(colored)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !self.color {
+            return Cow::Borrowed(hint);
+        }
+        Cow::Owned(format!("{}{}{}", HINT_COLOR, hint, RESET))
+    }
+
+    // Moving the cursor can change which paren is matched
+    fn highlight_char(&self, line: &str, _pos: usize) -> bool {
+        !line.is_empty()
+    }
+}
+
+// Enter in the middle of a form starts a new line of it rather than
+// evaluating it
+impl Validator for LispHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if needs_more(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for LispHelper {}
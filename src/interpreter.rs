@@ -1,26 +1,132 @@
+use crate::context::Context;
+use crate::equality::{is_eq, is_equal, is_eqv};
+use crate::exceptions::{self, Condition};
+use crate::hash_table::Equivalence;
+use crate::hash_table::HashTable;
+use crate::json;
+use crate::lexical::{analyze_lambda, lambda_body, LambdaTemplate};
+use crate::library;
 use crate::list::List;
+use crate::load;
+use crate::port::{Port, PortRef};
 use crate::primitives::Cell;
 use crate::primitives::CoreFunc;
 use crate::primitives::LogicalOperator;
 use crate::primitives::NumericOperator;
 use crate::primitives::SpecialForm;
+use crate::reader::DatumReader;
 use crate::symbolic_expression::SExpression;
-use anyhow::{Context, Result};
-use std::io;
-use std::io::Write;
+use crate::symbols::symbol_name;
+use anyhow::Result;
 use std::rc::Rc;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 static TRACE: bool = false;
 
-// Built in simple functions
-// Results of 'define' go here
+// One scope's definitions. Variables are stored in a flat vector of slots;
+// functions get a frame per call laid out ahead of time by the lexical
+// analysis in lexical.rs, so their variables are found by slot number.
+// A slot is None until its define has been evaluated.
+struct Frame {
+    // The symbol number for each slot, shared with the lambda the frame was
+    // made for until something is defined that the analysis didn't expect.
+    symbols: Rc<Vec<i32>>,
+    definitions: Vec<Option<SExpression>>,
+    // Frames that aren't function calls (the global scope) can get large, so
+    // their symbols are also indexed by symbol number.
+    definitions_by_symbol: Option<HashMap<i32, usize>>,
+    parent: Option<Rc<RefCell<Frame>>>,
+}
+
+impl Frame {
+    fn slot_of(&self, symbol: i32) -> Option<usize> {
+        match &self.definitions_by_symbol {
+            Some(index) => index.get(&symbol).copied(),
+            None => self.symbols.iter().position(|s| *s == symbol),
+        }
+    }
+
+    fn get_slot(&self, slot: usize, symbol: i32) -> Result<SExpression, String> {
+        match self.definitions.get(slot) {
+            Some(Some(value)) => Ok(value.clone()),
+            // The body defines it, but hasn't got to the define yet
+            Some(None) => Err(format!(
+                "Symbol {} used before its definition.",
+                symbol_name(symbol)
+            )),
+            None => Err(format!("Symbol {} not defined.", symbol_name(symbol))),
+        }
+    }
+
+    fn add_slot(&mut self, symbol: i32, value: SExpression) -> usize {
+        let number = self.definitions.len();
+        Rc::make_mut(&mut self.symbols).push(symbol);
+        self.definitions.push(Some(value));
+        if let Some(index) = self.definitions_by_symbol.as_mut() {
+            index.insert(symbol, number);
+        }
+        number
+    }
+
+    // Inside a body each name can only be defined once, as with letrec*. At
+    // the top level a define can give a name a new value instead, depending
+    // on the interpreter's redefinition setting.
+    fn define_slot(
+        &mut self,
+        slot: usize,
+        symbol: i32,
+        value: SExpression,
+        redefinition: Redefinition,
+    ) -> Result<(), String> {
+        let top_level = self.definitions_by_symbol.is_some();
+        match self.definitions.get_mut(slot) {
+            Some(Some(old_value)) if top_level => {
+                match redefinition {
+                    Redefinition::Error => {
+                        return Err(format!("{} already defined.", symbol_name(symbol)))
+                    }
+                    Redefinition::Warn => {
+                        eprintln!("Warning: redefining {}", symbol_name(symbol))
+                    }
+                    Redefinition::Allow => {}
+                }
+                *old_value = value;
+                Ok(())
+            }
+            Some(Some(_)) => Err(format!(
+                "{} already defined in this body.",
+                symbol_name(symbol)
+            )),
+            Some(empty) => {
+                *empty = Some(value);
+                Ok(())
+            }
+            None => Err(format!("No slot {} for {}.", slot, symbol_name(symbol))),
+        }
+    }
+}
+
+// What a top-level define of a name that already has a value does. Standard
+// Scheme gives it the new value, which is what the REPL needs to fix a
+// function without starting over; scripts can ask for a warning or, in
+// strict mode, an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Redefinition {
+    #[default]
+    Allow,
+    Warn,
+    Error,
+}
+
+// Results of 'define' go here. Environments are cheap handles to a frame; the
+// frame is shared with any lambdas created in it, which keep it alive. Each
+// also points to the Context of the interpreter it belongs to.
 #[derive(Clone)]
-pub struct Environment<'a> {
-    pub definitions_by_symbol: HashMap<String, usize>,
-    definitions: Vec<SExpression>,
-    parent: Option<&'a Environment<'a>>,
+pub struct Environment {
+    frame: Rc<RefCell<Frame>>,
+    context: Rc<Context>,
 }
 
 /*
@@ -52,262 +158,226 @@ to the same data. It feels a bit less direct or performant but certainly safer.
 
 The other solution would be to use Rc<>  in both containers.
 
+Frames now go one step further: the definitions live in a Vec and the body of a
+lambda refers to them by index directly, worked out once by the lexical analysis
+in lexical.rs. Only the global frame still needs the HashMap of indexes.
+
 */
 
-pub fn extend_environment<'a>(env: &'a mut Environment) -> Environment<'a> {
-    let no_definitions: HashMap<String, usize> = HashMap::new();
-    let empty_symbol_table = Vec::new();
-    Environment {
-        parent: Some(env),
-        definitions_by_symbol: no_definitions,
-        definitions: empty_symbol_table,
-    }
+pub fn extend_environment(env: &Environment) -> Environment {
+    let extended = env.new_top_level();
+    extended.frame.borrow_mut().parent = Some(env.frame.clone());
+    extended
 }
 
-impl Environment<'_> {
+impl Environment {
     // A formatted list of all defined symbols in the environment (not including parent)
     pub fn print(&self) -> String {
+        let frame = self.frame.borrow();
         let mut symbols = Vec::new();
-        for (symbol, number) in &self.definitions_by_symbol {
-            let expr = self.definitions[*number].clone();
-            symbols.push(format!("{} : {} {}", &symbol, number, &expr.print()));
+        for (number, symbol) in frame.symbols.iter().enumerate() {
+            if let Some(Some(expr)) = frame.definitions.get(number) {
+                symbols.push(format!(
+                    "{} : {} {}",
+                    symbol_name(*symbol),
+                    number,
+                    &expr.print()
+                ));
+            }
         }
         symbols.join("\n")
     }
 
+    // The top level of a new interpreter
     pub fn new() -> Self {
-        let no_definitions: HashMap<String, usize> = HashMap::new();
-        let empty_symbol_table = Vec::new();
-        Environment {
-            parent: None,
-            definitions_by_symbol: no_definitions,
-            definitions: empty_symbol_table,
-        }
+        Environment::with_context(Rc::new(Context::default()))
     }
 
-    pub fn make_child<'a>(&'a mut self) -> Environment<'a> {
-        let no_definitions: HashMap<String, usize> = HashMap::new();
+    pub(crate) fn with_context(context: Rc<Context>) -> Self {
+        let no_definitions: HashMap<i32, usize> = HashMap::new();
         let empty_symbol_table = Vec::new();
         Environment {
-            parent: Some(self),
-            definitions_by_symbol: no_definitions,
-            definitions: empty_symbol_table,
+            frame: Rc::new(RefCell::new(Frame {
+                symbols: Rc::new(Vec::new()),
+                definitions: empty_symbol_table,
+                definitions_by_symbol: Some(no_definitions),
+                parent: None,
+            })),
+            context,
         }
     }
 
-    // Shortcut to add symbols to the environment
-    pub fn define(&mut self, name: String, value: SExpression) -> Result<i32, String> {
-        if self.definitions_by_symbol.contains_key(&name) {
-            Err(format!("{} already defined.", &name))
-        } else {
-            let number = self.definitions.len();
-            self.definitions_by_symbol.insert(name, number);
-            self.definitions.push(value);
-            Ok(number as i32)
-        }
+    // Another empty top-level environment in the same interpreter, for
+    // libraries and make-environment
+    pub(crate) fn new_top_level(&self) -> Environment {
+        Environment::with_context(self.context.clone())
     }
 
-    pub fn get_definition_by_symbol(&self, s: String) -> Result<SExpression, String> {
-        match self.definitions_by_symbol.get(&s) {
-            Some(number) => Ok(self.definitions[*number].clone()),
-            _ => {
-                if let Some(outer) = self.parent {
-                    outer.get_definition_by_symbol(s)
-                } else {
-                    Err(format!("Symbol {} not defined.", &s))
-                }
-            }
-        }
-    }
-    // extract  the cell version of an s-expression if it's a number type cell
-    fn checked_number(item: Result<SExpression, String>) -> Result<Cell, String> {
-        match item {
-            Ok(item) => item.as_number(),
-            Err(message) => Err(message),
-        }
+    pub(crate) fn context(&self) -> &Rc<Context> {
+        &self.context
     }
 
-    fn checked_rust_bool(item: Result<SExpression, String>) -> Result<bool, String> {
-        match item {
-            Ok(item) => item.as_rust_bool(),
-            Err(message) => Err(message),
+    // The outermost environment this one is inside, where top-level
+    // definitions go
+    pub(crate) fn global(&self) -> Environment {
+        let mut frame = self.frame.clone();
+        loop {
+            let parent = frame.borrow().parent.clone();
+            match parent {
+                Some(outer) => frame = outer,
+                None => {
+                    return Environment {
+                        frame,
+                        context: self.context.clone(),
+                    }
+                }
+            }
         }
     }
 
-    fn add(&mut self, list: List) -> Result<SExpression, String> {
-        self.add_to(Cell::Int(0), list)
+    // The names defined in this frame, in the order they were defined
+    pub(crate) fn names(&self) -> Vec<String> {
+        let frame = self.frame.borrow();
+        frame
+            .symbols
+            .iter()
+            .enumerate()
+            .filter(|(number, _)| matches!(frame.definitions.get(*number), Some(Some(_))))
+            .map(|(_, symbol)| symbol_name(*symbol))
+            .collect()
     }
 
-    fn add_to(&mut self, left_value: Cell, list: List) -> Result<SExpression, String> {
-        let next_item = self.evaluate(*list.first())?;
-        let right_value = next_item.as_number()?;
-        let partial_sum = match (left_value, right_value) {
-            (Cell::Int(l), Cell::Int(r)) => Cell::Int(l + r),
-            (Cell::Int(l), Cell::Flt(r)) => Cell::Flt(l as f64 + r),
-            (Cell::Flt(l), Cell::Int(r)) => Cell::Flt(l + r as f64),
-            (Cell::Flt(l), Cell::Flt(r)) => Cell::Flt(l + r),
-            _ => {
-                // Type error:
-                panic!("Data type error. Type checking should have caught this earlier.");
+    // (name value) for each definition in this frame, in the order they were made
+    pub(crate) fn bindings(&self) -> SExpression {
+        let frame = self.frame.borrow();
+        let mut bindings = Vec::new();
+        for (number, symbol) in frame.symbols.iter().enumerate() {
+            if let Some(Some(value)) = frame.definitions.get(number) {
+                let name = SExpression::Cell(Cell::Symbol(*symbol, symbol_name(*symbol)));
+                bindings.push(SExpression::List(List::make_from_sexps(vec![
+                    name,
+                    value.clone(),
+                ])));
             }
-        }; // match
-        if list.rest().is_empty() {
-            Ok(SExpression::Cell(partial_sum))
-        } else {
-            self.add_to(partial_sum, list.rest())
         }
+        SExpression::List(List::make_from_sexps(bindings))
     }
 
-    fn subtract(&mut self, list: List) -> Result<SExpression, String> {
-        let first_item = self.evaluate(*list.first())?;
-        let leftmost_number = first_item.as_number()?;
-        self.subtract_from(leftmost_number, list.rest())
-    }
-
-    fn subtract_from(&mut self, left_value: Cell, list: List) -> Result<SExpression, String> {
-        let next_item = self.evaluate(*list.first())?;
-        let right_value = next_item.as_number()?;
-
-        let partial_sum = match (left_value, right_value) {
-            (Cell::Int(l), Cell::Int(r)) => Cell::Int(l - r),
-            (Cell::Int(l), Cell::Flt(r)) => Cell::Flt(l as f64 - r),
-            (Cell::Flt(l), Cell::Int(r)) => Cell::Flt(l - r as f64),
-            (Cell::Flt(l), Cell::Flt(r)) => Cell::Flt(l - r),
-            _ => {
-                // Type error:
-                panic!("Data type error. Type checking should have caught this earlier.");
-            }
-        }; // match
-        if list.rest().is_empty() {
-            Ok(SExpression::Cell(partial_sum))
-        } else {
-            self.subtract_from(partial_sum, list.rest())
+    // The environment for a call to a lambda: its frame holds the arguments and
+    // has empty slots for everything the lambda's body defines. The parent is
+    // the environment the lambda was created in.
+    pub(crate) fn make_call_frame(
+        template: &LambdaTemplate,
+        parent: &Environment,
+        values_from_args: Vec<SExpression>,
+    ) -> Result<Environment, String> {
+        if parent.context.tracing.get() {
+            let name = template.name.map_or(String::from("lambda"), symbol_name);
+            let args: Vec<String> = values_from_args.iter().map(|arg| arg.write()).collect();
+            eprintln!("trace: ({} {})", name, args.join(" "));
+        }
+        if values_from_args.len() < template.param_count {
+            return Err(
+                "Mismatch between number of arguments and function parameters!".to_string(),
+            );
         }
+        let mut definitions = Vec::with_capacity(template.slots.len());
+        definitions.extend(
+            values_from_args
+                .into_iter()
+                .take(template.param_count)
+                .map(Some),
+        );
+        definitions.resize(template.slots.len(), None);
+        Ok(Environment {
+            frame: Rc::new(RefCell::new(Frame {
+                symbols: template.slots.clone(),
+                definitions,
+                definitions_by_symbol: None,
+                parent: Some(parent.frame.clone()),
+            })),
+            context: parent.context.clone(),
+        })
     }
 
-    fn multiply(&mut self, list: List) -> Result<SExpression, String> {
-        self.multiply_by(Cell::Int(1), list)
+    pub fn same_environment(&self, other: &Environment) -> bool {
+        Rc::ptr_eq(&self.frame, &other.frame)
     }
 
-    fn multiply_by(&mut self, left_value: Cell, list: List) -> Result<SExpression, String> {
-        let next_item = self.evaluate(*list.first())?;
-        let right_value = next_item.as_number()?;
-
-        let partial_product = match (left_value, right_value) {
-            (Cell::Int(l), Cell::Int(r)) => Cell::Int(l * r),
-            (Cell::Int(l), Cell::Flt(r)) => Cell::Flt(l as f64 * r),
-            (Cell::Flt(l), Cell::Int(r)) => Cell::Flt(l * r as f64),
-            (Cell::Flt(l), Cell::Flt(r)) => Cell::Flt(l * r),
-            _ => {
-                // Type error:
-                panic!("Data type error. Type checking should have caught this earlier.");
-            }
-        }; // match
-        if list.rest().is_empty() {
-            Ok(SExpression::Cell(partial_product))
-        } else {
-            self.multiply_by(partial_product, list.rest())
+    // Shortcut to add symbols to the environment
+    pub fn define(&mut self, symbol: i32, value: SExpression) -> Result<i32, String> {
+        let mut frame = self.frame.borrow_mut();
+        if let Some(slot) = frame.slot_of(symbol) {
+            frame.define_slot(slot, symbol, value, self.context.redefinition.get())?;
+            return Ok(slot as i32);
         }
+        Ok(frame.add_slot(symbol, value) as i32)
     }
 
-    fn divide(&mut self, list: List) -> Result<SExpression, String> {
-        // If there's only one argument to /
-        if list.rest().is_empty() {
-            self.divide_into(Cell::Int(1), list)
-        } else {
-            let next_item = self.evaluate(*list.first())?;
-            let numerator = next_item.as_number()?;
-            self.divide_into(numerator, list.rest())
-        } // else
-    }
-
-    fn divide_into(&mut self, numerator_value: Cell, list: List) -> Result<SExpression, String> {
-        let next_item = self.evaluate(*list.first())?;
-        let denominator_value = next_item.as_number()?;
-
-        let partial_product = match (numerator_value, denominator_value) {
-            (Cell::Int(n), Cell::Int(d)) => Cell::Int(n / d),
-            (Cell::Int(n), Cell::Flt(d)) => Cell::Flt(n as f64 / d),
-            (Cell::Flt(n), Cell::Int(d)) => Cell::Flt(n / d as f64),
-            (Cell::Flt(n), Cell::Flt(d)) => Cell::Flt(n / d),
-            _ => {
-                // Type error:
-                panic!("Data type error. Type checking should have caught this earlier.");
-            }
-        }; // match
-        if list.rest().is_empty() {
-            Ok(SExpression::Cell(partial_product))
-        } else {
-            self.divide_into(partial_product, list.rest())
+    // Give a symbol a value in this frame whether or not it already has one,
+    // for a host program setting globals.
+    pub(crate) fn define_or_replace(&mut self, symbol: i32, value: SExpression) {
+        let mut frame = self.frame.borrow_mut();
+        match frame.slot_of(symbol) {
+            Some(slot) => frame.definitions[slot] = Some(value),
+            None => {
+                frame.add_slot(symbol, value);
+            }
         }
     }
 
-    fn eval_greater(&mut self, list: List) -> Result<SExpression, String> {
-        if list.rest().is_empty() {
-            return Ok(SExpression::Cell(Cell::Bool(true)));
-        }
-
-        let left = Environment::checked_number(self.evaluate(*list.first()))?;
-        let right = Environment::checked_number(self.evaluate(*list.rest().first()))?;
-
-        let gt = match (left, right) {
-            (Cell::Int(i), Cell::Int(j)) => i > j,
-            (Cell::Int(i), Cell::Flt(j)) => i as f64 > j,
-            (Cell::Flt(i), Cell::Int(j)) => i > j as f64,
-            (Cell::Flt(i), Cell::Flt(j)) => i > j,
-            _ => panic!("Type checking error, needed number type."),
-        };
+    // Define a variable in a slot the lexical analysis reserved for it
+    pub(crate) fn define_local(
+        &self,
+        slot: usize,
+        symbol: i32,
+        value: SExpression,
+    ) -> Result<(), String> {
+        let redefinition = self.context.redefinition.get();
+        self.frame
+            .borrow_mut()
+            .define_slot(slot, symbol, value, redefinition)
+    }
 
-        if gt {
-            self.eval_greater(list.rest())
-        } else {
-            Ok(SExpression::Cell(Cell::Bool(false)))
+    // The slow path, for symbols the lexical analysis couldn't place: search
+    // each enclosing frame by symbol number.
+    pub fn get_definition_by_symbol(&self, symbol: i32) -> Result<SExpression, String> {
+        let mut frame = self.frame.clone();
+        loop {
+            let parent = {
+                let current = frame.borrow();
+                if let Some(slot) = current.slot_of(symbol) {
+                    return current.get_slot(slot, symbol);
+                }
+                current.parent.clone()
+            };
+            match parent {
+                Some(outer) => frame = outer,
+                None => return Err(format!("Symbol {} not defined.", symbol_name(symbol))),
+            }
         }
     }
 
-    fn eval_less(&mut self, list: List) -> Result<SExpression, String> {
-        if list.rest().is_empty() {
-            return Ok(SExpression::Cell(Cell::Bool(true)));
+    // The fast path for variables with a lexical address
+    pub(crate) fn get_definition_by_address(
+        &self,
+        depth: usize,
+        slot: usize,
+        symbol: i32,
+    ) -> Result<SExpression, String> {
+        if depth == 0 {
+            return self.frame.borrow().get_slot(slot, symbol);
         }
-
-        let left = Environment::checked_number(self.evaluate(*list.first()))?;
-        let right = Environment::checked_number(self.evaluate(*list.rest().first()))?;
-
-        let lt = match (left, right) {
-            (Cell::Int(i), Cell::Int(j)) => i < j,
-            (Cell::Int(i), Cell::Flt(j)) => (i as f64) < j,
-            (Cell::Flt(i), Cell::Int(j)) => i < j as f64,
-            (Cell::Flt(i), Cell::Flt(j)) => i < j,
-            _ => panic!("Type checking error, needed number type."),
-        };
-
-        if lt {
-            self.eval_less(list.rest())
-        } else {
-            Ok(SExpression::Cell(Cell::Bool(false)))
+        match self.frame_at(depth) {
+            Some(frame) => frame.borrow().get_slot(slot, symbol),
+            None => Err(format!("Symbol {} not defined.", symbol_name(symbol))),
         }
     }
 
-    fn eval_equal(&mut self, list: List) -> Result<SExpression, String> {
-        if list.rest().is_empty() {
-            return Ok(SExpression::Cell(Cell::Bool(true)));
-        }
-
-        let left = Environment::checked_number(self.evaluate(*list.first()))?;
-        let right = Environment::checked_number(self.evaluate(*list.rest().first()))?;
-
-        let eq = match (left, right) {
-            (Cell::Int(i), Cell::Int(j)) => i == j,
-            (Cell::Int(i), Cell::Flt(j)) => (i as f64) == j,
-            (Cell::Flt(i), Cell::Int(j)) => i == j as f64,
-            (Cell::Flt(i), Cell::Flt(j)) => i == j,
-            _ => panic!("Type checking error, needed number type."),
-        };
-
-        if eq {
-            self.eval_equal(list.rest())
-        } else {
-            Ok(SExpression::Cell(Cell::Bool(false)))
+    fn checked_rust_bool(item: Result<SExpression, String>) -> Result<bool, String> {
+        match item {
+            Ok(item) => item.as_rust_bool(),
+            Err(message) => Err(message),
         }
     }
 
@@ -317,7 +387,7 @@ impl Environment<'_> {
             Ok(SExpression::Cell(Cell::Bool(true)))
         } else {
             if list.rest().is_empty() {
-                Ok(SExpression::Cell(Cell::Bool(true)))
+                Ok(SExpression::Cell(Cell::Bool(false)))
             } else {
                 self.eval_or(list.rest())
             }
@@ -341,9 +411,8 @@ impl Environment<'_> {
     pub fn evaluate(&mut self, exp: SExpression) -> Result<SExpression, String> {
         match exp {
             SExpression::Cell(c) => match c {
-                // The idea is to  use the number instead of the name to do
-                // lookup in a vector of definitions for better performance...
-                // but the hash map gets us started.
+                // The symbol's interned number is the key for lookups, so the
+                // name never needs to be hashed or compared here.
                 //
                 // In the context where this is evaluated the symbol can only
                 // refer to a variable; functions would be the first element
@@ -353,7 +422,14 @@ impl Environment<'_> {
                     if TRACE {
                         println!("Try to evaluate symbol {}", &symbol);
                     }
-                    self.get_definition_by_symbol(symbol)
+                    self.get_definition_by_symbol(number)
+                }
+                Cell::Local(depth, slot, number) => {
+                    self.get_definition_by_address(depth, slot, number)
+                }
+                // An analyzed lambda becomes a procedure closed over this environment
+                Cell::Template(template) => {
+                    Ok(SExpression::Cell(Cell::Lambda(template, self.clone())))
                 }
                 _ => Ok(SExpression::Cell(c)),
             },
@@ -362,25 +438,64 @@ impl Environment<'_> {
         }
     }
 
-    //  Can take the formatted output in the result and direct to another stream
-    fn evaluate_output(&mut self, args: List) -> Result<SExpression, String> {
+    // The output goes to the current output port, which is standard output
+    // unless something like with-output-to-file has changed it.
+    fn evaluate_output(&mut self, args: List, newline: bool) -> Result<SExpression, String> {
         let results = self.eval_each(args)?;
-        let printed_results: String = results
+        self.output_values(&results, newline)
+    }
+
+    // Print values already evaluated for 'output', separated by spaces
+    pub(crate) fn output_values(
+        &self,
+        values: &[SExpression],
+        newline: bool,
+    ) -> Result<SExpression, String> {
+        let printed_results: String = values
             .iter()
             .map(|r| r.print())
             .collect::<Vec<String>>()
             .join(" ");
 
-        print!("{}", &printed_results);
-        io::stdout().flush().unwrap();
+        let line_end = if newline { "\n" } else { "" };
+        self.context
+            .ports
+            .write_output(&(printed_results.clone() + line_end))?;
 
         Ok(SExpression::Cell(Cell::Str(printed_results)))
     }
 
-    fn evaluate_output_line(&mut self, args: List) -> Result<SExpression, String> {
-        let result = self.evaluate_output(args);
-        println!("");
-        result
+    // (include "file" ...) evaluates the forms in the files right here, in
+    // this scope, as if they'd been written in place of the include
+    fn evaluate_include(&mut self, args: List) -> Result<SExpression, String> {
+        let mut result = SExpression::Null;
+        for name in args.to_vec() {
+            let context = self.context.clone();
+            let path = load::include_path(&context, &name)?;
+            result = load::with_loading(&context, &path, || {
+                let forms = load::read_forms(&path)?;
+                self.eval_each_return_last(forms)
+            })??;
+        }
+        Ok(result)
+    }
+
+    // (input) reads a line from the current input port; any arguments are
+    // printed first as a prompt. Gives the eof object at the end of the input.
+    fn evaluate_input(&mut self, args: List) -> Result<SExpression, String> {
+        let prompt = self.eval_each(args)?;
+        self.input_line(&prompt)
+    }
+
+    pub(crate) fn input_line(&self, prompt: &[SExpression]) -> Result<SExpression, String> {
+        if !prompt.is_empty() {
+            self.output_values(prompt, false)?;
+        }
+        let line = self.context.ports.input().borrow_mut().read_line()?;
+        Ok(match line {
+            Some(line) => SExpression::Cell(Cell::Str(line)),
+            None => SExpression::Cell(Cell::Eof),
+        })
     }
 
     pub fn apply_core_func(&mut self, func: CoreFunc, args: List) -> Result<SExpression, String> {
@@ -388,31 +503,34 @@ impl Environment<'_> {
             println!("Apply core function {}", &func.print());
         }
         let values_from_args = self.eval_each(args)?;
+        self.call_core_func(func, values_from_args)
+    }
+
+    // Apply a core function to arguments that have already been evaluated. This
+    // is also how core functions passed around as values get called.
+    pub(crate) fn call_core_func(
+        &mut self,
+        func: CoreFunc,
+        values_from_args: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
         match func {
             CoreFunc::List => Ok(SExpression::List(List::make_from_sexps(values_from_args))),
             CoreFunc::Cons => {
-                if values_from_args.len() > 2 {
-                    return Err("Too many arguments to 'cons'.".to_string());
-                }
+                Environment::check_arg_count(func, &values_from_args, 2, Some(2))?;
                 Ok(SExpression::List(List::construct_list(
                     values_from_args[0].clone(),
                     values_from_args[1].clone(),
                 )))
             }
             CoreFunc::First => {
-                if values_from_args.len() > 1 {
-                    return Err("Too many arguments to 'first'.".to_string());
-                }
+                Environment::check_arg_count(func, &values_from_args, 1, Some(1))?;
                 match values_from_args[0] {
                     SExpression::List(ref list) => Ok(*list.first()),
                     _ => Err("The 'first' function requires a list as its argument.".to_string()),
                 }
             }
             CoreFunc::Rest => {
-                if values_from_args.len() > 1 {
-                    return Err("Too many arguments to 'rest'.".to_string());
-                }
-
+                Environment::check_arg_count(func, &values_from_args, 1, Some(1))?;
                 match values_from_args[0] {
                     SExpression::List(ref list) => Ok(SExpression::List(list.rest())),
                     _ => Err(format!(
@@ -421,10 +539,1154 @@ impl Environment<'_> {
                     )),
                 }
             }
+            CoreFunc::IsEq | CoreFunc::IsEqv | CoreFunc::IsEqual => {
+                Environment::check_arg_count(func, &values_from_args, 2, Some(2))?;
+                let (left, right) = (&values_from_args[0], &values_from_args[1]);
+                let same = match func {
+                    CoreFunc::IsEq => is_eq(left, right),
+                    CoreFunc::IsEqv => is_eqv(left, right),
+                    _ => is_equal(left, right),
+                };
+                Ok(SExpression::Cell(Cell::Bool(same)))
+            }
+            CoreFunc::Member | CoreFunc::Memq | CoreFunc::Memv => {
+                self.apply_member(func, values_from_args)
+            }
+            CoreFunc::Assoc | CoreFunc::Assq | CoreFunc::Assv => {
+                self.apply_assoc(func, values_from_args)
+            }
+            CoreFunc::StringEqual => {
+                let mut strings = Vec::new();
+                for value in &values_from_args {
+                    match value {
+                        SExpression::Cell(Cell::Str(s)) => strings.push(s),
+                        _ => {
+                            return Err(format!(
+                                "The arguments to 'string=?' must be strings, got {}",
+                                value.print()
+                            ))
+                        }
+                    }
+                }
+                let all_equal = strings.windows(2).all(|pair| pair[0] == pair[1]);
+                Ok(SExpression::Cell(Cell::Bool(all_equal)))
+            }
+            CoreFunc::IsHashTable
+            | CoreFunc::MakeHashTable
+            | CoreFunc::AlistToHashTable
+            | CoreFunc::HashTableSet
+            | CoreFunc::HashTableRef
+            | CoreFunc::HashTableRefDefault
+            | CoreFunc::HashTableUpdate
+            | CoreFunc::HashTableUpdateDefault
+            | CoreFunc::HashTableDelete
+            | CoreFunc::HashTableContains
+            | CoreFunc::HashTableSize
+            | CoreFunc::HashTableKeys
+            | CoreFunc::HashTableValues
+            | CoreFunc::HashTableToAlist
+            | CoreFunc::HashTableWalk
+            | CoreFunc::HashTableFold
+            | CoreFunc::HashTableClear
+            | CoreFunc::HashTableCopy => self.apply_hash_table_func(func, values_from_args),
+            CoreFunc::IsVector
+            | CoreFunc::MakeVector
+            | CoreFunc::Vector
+            | CoreFunc::VectorLength
+            | CoreFunc::VectorRef
+            | CoreFunc::VectorSet
+            | CoreFunc::VectorToList
+            | CoreFunc::ListToVector => Environment::apply_vector_func(func, values_from_args),
+            CoreFunc::JsonRead
+            | CoreFunc::JsonWrite
+            | CoreFunc::StringToJson
+            | CoreFunc::JsonToString
+            | CoreFunc::IsJsonNull
+            | CoreFunc::JsonNull => self.apply_json_func(func, values_from_args),
+            CoreFunc::IsInputPort
+            | CoreFunc::IsOutputPort
+            | CoreFunc::IsTextualPort
+            | CoreFunc::IsBinaryPort
+            | CoreFunc::OpenInputFile
+            | CoreFunc::OpenOutputFile
+            | CoreFunc::OpenBinaryInputFile
+            | CoreFunc::OpenBinaryOutputFile
+            | CoreFunc::CallWithInputFile
+            | CoreFunc::CallWithOutputFile
+            | CoreFunc::WithInputFromFile
+            | CoreFunc::WithOutputToFile
+            | CoreFunc::CurrentInputPort
+            | CoreFunc::CurrentOutputPort
+            | CoreFunc::ReadLine
+            | CoreFunc::ReadChar
+            | CoreFunc::PeekChar
+            | CoreFunc::ReadString
+            | CoreFunc::ReadU8
+            | CoreFunc::PeekU8
+            | CoreFunc::WriteString
+            | CoreFunc::WriteU8
+            | CoreFunc::Newline
+            | CoreFunc::Display
+            | CoreFunc::Write
+            | CoreFunc::ClosePort
+            | CoreFunc::CloseInputPort
+            | CoreFunc::CloseOutputPort
+            | CoreFunc::IsEofObject
+            | CoreFunc::EofObject
+            | CoreFunc::OpenInputString
+            | CoreFunc::OpenOutputString
+            | CoreFunc::GetOutputString
+            | CoreFunc::WithOutputToString
+            | CoreFunc::CallWithOutputString
+            | CoreFunc::Read => self.apply_port_func(func, values_from_args),
+            CoreFunc::Eval
+            | CoreFunc::IsEnvironment
+            | CoreFunc::InteractionEnvironment
+            | CoreFunc::SchemeReportEnvironment
+            | CoreFunc::MakeEnvironment
+            | CoreFunc::CurrentEnvironment
+            | CoreFunc::IsEnvironmentBound
+            | CoreFunc::EnvironmentRef
+            | CoreFunc::EnvironmentDefine
+            | CoreFunc::EnvironmentBindings => self.apply_environment_func(func, values_from_args),
+            CoreFunc::Load => self.apply_load(func, values_from_args),
+            CoreFunc::Exit => self.apply_exit(func, values_from_args),
+            CoreFunc::CommandLine => {
+                Environment::check_arg_count(func, &values_from_args, 0, Some(0))?;
+                let arguments = self.context.command_line.borrow().clone();
+                let strings = arguments
+                    .into_iter()
+                    .map(|argument| SExpression::Cell(Cell::Str(argument)))
+                    .collect();
+                Ok(SExpression::List(List::make_from_sexps(strings)))
+            }
+            CoreFunc::Error
+            | CoreFunc::Raise
+            | CoreFunc::RaiseContinuable
+            | CoreFunc::WithExceptionHandler
+            | CoreFunc::WithExceptionCatcher
+            | CoreFunc::IsErrorObject
+            | CoreFunc::ErrorObjectMessage
+            | CoreFunc::ErrorObjectIrritants => self.apply_exception_func(func, values_from_args),
             _ => Err(format!("{} not implemented", func.print())),
         }
     }
 
+    // Compare two values the way member or assoc were asked to: with eq?, eqv?,
+    // equal? or a procedure passed in by the caller.
+    fn values_match(
+        &mut self,
+        func: CoreFunc,
+        compare: Option<&SExpression>,
+        left: &SExpression,
+        right: &SExpression,
+    ) -> Result<bool, String> {
+        match (func, compare) {
+            (_, Some(procedure)) => {
+                let result =
+                    self.apply_procedure(procedure.clone(), vec![left.clone(), right.clone()])?;
+                result.as_rust_bool()
+            }
+            (CoreFunc::Memq, _) | (CoreFunc::Assq, _) => Ok(is_eq(left, right)),
+            (CoreFunc::Memv, _) | (CoreFunc::Assv, _) => Ok(is_eqv(left, right)),
+            _ => Ok(is_equal(left, right)),
+        }
+    }
+
+    fn list_arg(func: CoreFunc, value: &SExpression) -> Result<List, String> {
+        match value {
+            SExpression::List(list) => Ok(list.clone()),
+            _ => Err(format!(
+                "'{}' requires a list, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    // (member x list) returns the first part of the list starting with x, or false
+    fn apply_member(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let max_args = if let CoreFunc::Member = func { 3 } else { 2 };
+        Environment::check_arg_count(func, &values, 2, Some(max_args))?;
+        let mut remaining = Environment::list_arg(func, &values[1])?;
+        while !remaining.is_empty() {
+            if self.values_match(func, values.get(2), &values[0], &remaining.first())? {
+                return Ok(SExpression::List(remaining));
+            }
+            remaining = remaining.rest();
+        }
+        Ok(SExpression::Cell(Cell::Bool(false)))
+    }
+
+    // (assoc key alist) returns the first entry of the association list whose
+    // first item is the key, or false
+    fn apply_assoc(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let max_args = if let CoreFunc::Assoc = func { 3 } else { 2 };
+        Environment::check_arg_count(func, &values, 2, Some(max_args))?;
+        let mut remaining = Environment::list_arg(func, &values[1])?;
+        while !remaining.is_empty() {
+            let entry = *remaining.first();
+            match entry {
+                SExpression::List(ref pair) if !pair.is_empty() => {
+                    if self.values_match(func, values.get(2), &values[0], &pair.first())? {
+                        return Ok(entry);
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "Association list entries must be pairs, got {}",
+                        entry.print()
+                    ))
+                }
+            }
+            remaining = remaining.rest();
+        }
+        Ok(SExpression::Cell(Cell::Bool(false)))
+    }
+
+    // Check the number of arguments passed to a core function; max of None
+    // means any number of extra arguments are allowed.
+    fn check_arg_count(
+        func: CoreFunc,
+        values: &[SExpression],
+        min: usize,
+        max: Option<usize>,
+    ) -> Result<(), String> {
+        if values.len() < min {
+            return Err(format!(
+                "Too few arguments to '{}': needs at least {}, got {}.",
+                func.print(),
+                min,
+                values.len()
+            ));
+        }
+        if let Some(max) = max {
+            if values.len() > max {
+                return Err(format!("Too many arguments to '{}'.", func.print()));
+            }
+        }
+        Ok(())
+    }
+
+    fn hash_table_arg(
+        func: CoreFunc,
+        value: &SExpression,
+    ) -> Result<Rc<RefCell<HashTable>>, String> {
+        match value {
+            SExpression::Cell(Cell::HashTable(table)) => Ok(table.clone()),
+            _ => Err(format!(
+                "The first argument to '{}' must be a hash table, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    fn apply_hash_table_func(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let unspecified = Ok(SExpression::Null);
+        match func {
+            CoreFunc::IsHashTable => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let is_table = matches!(values[0], SExpression::Cell(Cell::HashTable(_)));
+                Ok(SExpression::Cell(Cell::Bool(is_table)))
+            }
+            CoreFunc::MakeHashTable => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let equivalence = match values.first() {
+                    Some(procedure) => Equivalence::from_procedure(procedure)?,
+                    None => Equivalence::Equal,
+                };
+                let table = HashTable::new(equivalence);
+                Ok(SExpression::Cell(Cell::HashTable(Rc::new(RefCell::new(
+                    table,
+                )))))
+            }
+            CoreFunc::AlistToHashTable => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let equivalence = match values.get(1) {
+                    Some(procedure) => Equivalence::from_procedure(procedure)?,
+                    None => Equivalence::Equal,
+                };
+                let mut table = HashTable::new(equivalence);
+                let mut remaining = match &values[0] {
+                    SExpression::List(alist) => alist.clone(),
+                    other => {
+                        return Err(format!(
+                            "'{}' requires an association list, got {}",
+                            func.print(),
+                            other.print()
+                        ))
+                    }
+                };
+                while !remaining.is_empty() {
+                    match *remaining.first() {
+                        SExpression::List(entry) if !entry.is_empty() => {
+                            let value = Environment::alist_entry_value(&entry);
+                            // Earlier entries take precedence, as with assoc.
+                            if !table.contains(&entry.first())? {
+                                table.set(*entry.first(), value)?;
+                            }
+                        }
+                        ref other => {
+                            return Err(format!(
+                                "Association list entries must be pairs, got {}",
+                                other.print()
+                            ))
+                        }
+                    }
+                    remaining = remaining.rest();
+                }
+                Ok(SExpression::Cell(Cell::HashTable(Rc::new(RefCell::new(
+                    table,
+                )))))
+            }
+            CoreFunc::HashTableSet => {
+                Environment::check_arg_count(func, &values, 3, Some(3))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                table
+                    .borrow_mut()
+                    .set(values[1].clone(), values[2].clone())?;
+                unspecified
+            }
+            CoreFunc::HashTableRef => {
+                Environment::check_arg_count(func, &values, 2, Some(3))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let found = table.borrow().get(&values[1])?;
+                match (found, values.get(2)) {
+                    (Some(value), _) => Ok(value),
+                    (None, Some(thunk)) => self.apply_procedure(thunk.clone(), Vec::new()),
+                    (None, None) => Err(format!(
+                        "Key {} not found in hash table.",
+                        values[1].print()
+                    )),
+                }
+            }
+            CoreFunc::HashTableRefDefault => {
+                Environment::check_arg_count(func, &values, 3, Some(3))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let found = table.borrow().get(&values[1])?;
+                Ok(found.unwrap_or_else(|| values[2].clone()))
+            }
+            CoreFunc::HashTableUpdate | CoreFunc::HashTableUpdateDefault => {
+                if let CoreFunc::HashTableUpdate = func {
+                    Environment::check_arg_count(func, &values, 3, Some(4))?;
+                } else {
+                    Environment::check_arg_count(func, &values, 4, Some(4))?;
+                }
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let found = table.borrow().get(&values[1])?;
+                let current = match (found, func, values.get(3)) {
+                    (Some(value), _, _) => value,
+                    (None, CoreFunc::HashTableUpdateDefault, Some(default)) => default.clone(),
+                    (None, _, Some(thunk)) => self.apply_procedure(thunk.clone(), Vec::new())?,
+                    (None, _, None) => {
+                        return Err(format!(
+                            "Key {} not found in hash table.",
+                            values[1].print()
+                        ))
+                    }
+                };
+                // The table isn't borrowed while the procedure runs, so it may
+                // look at or change the table itself.
+                let updated = self.apply_procedure(values[2].clone(), vec![current])?;
+                table.borrow_mut().set(values[1].clone(), updated)?;
+                unspecified
+            }
+            CoreFunc::HashTableDelete => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                table.borrow_mut().delete(&values[1])?;
+                unspecified
+            }
+            CoreFunc::HashTableContains => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let found = table.borrow().contains(&values[1])?;
+                Ok(SExpression::Cell(Cell::Bool(found)))
+            }
+            CoreFunc::HashTableSize => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let size = table.borrow().len();
+                Ok(SExpression::Cell(Cell::Int(size as i64)))
+            }
+            CoreFunc::HashTableKeys => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let keys = table.borrow().keys();
+                Ok(SExpression::List(List::make_from_sexps(keys)))
+            }
+            CoreFunc::HashTableValues => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let table_values = table.borrow().values();
+                Ok(SExpression::List(List::make_from_sexps(table_values)))
+            }
+            CoreFunc::HashTableToAlist => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let alist = table.borrow().to_alist();
+                Ok(SExpression::List(alist))
+            }
+            CoreFunc::HashTableWalk => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let entries = table.borrow().entries();
+                for (key, value) in entries {
+                    self.apply_procedure(values[1].clone(), vec![key, value])?;
+                }
+                unspecified
+            }
+            CoreFunc::HashTableFold => {
+                Environment::check_arg_count(func, &values, 3, Some(3))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let entries = table.borrow().entries();
+                let mut accumulated = values[2].clone();
+                for (key, value) in entries {
+                    accumulated =
+                        self.apply_procedure(values[1].clone(), vec![key, value, accumulated])?;
+                }
+                Ok(accumulated)
+            }
+            CoreFunc::HashTableClear => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                table.borrow_mut().clear();
+                unspecified
+            }
+            CoreFunc::HashTableCopy => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let table = Environment::hash_table_arg(func, &values[0])?;
+                let copy = table.borrow().clone();
+                Ok(SExpression::Cell(Cell::HashTable(Rc::new(RefCell::new(
+                    copy,
+                )))))
+            }
+            _ => Err(format!("{} is not a hash table function", func.print())),
+        }
+    }
+
+    fn vector_arg(
+        func: CoreFunc,
+        value: &SExpression,
+    ) -> Result<Rc<RefCell<Vec<SExpression>>>, String> {
+        match value {
+            SExpression::Cell(Cell::Vector(items)) => Ok(items.clone()),
+            _ => Err(format!(
+                "The first argument to '{}' must be a vector, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    // Number literals are floats, so a whole-numbered float works as an index
+    fn index_arg(func: CoreFunc, value: &SExpression) -> Result<usize, String> {
+        match value {
+            SExpression::Cell(Cell::Int(index)) if *index >= 0 => Ok(*index as usize),
+            SExpression::Cell(Cell::Flt(index)) if *index >= 0.0 && index.fract() == 0.0 => {
+                Ok(*index as usize)
+            }
+            _ => Err(format!(
+                "'{}' needs a non-negative whole number, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    fn apply_vector_func(func: CoreFunc, values: Vec<SExpression>) -> Result<SExpression, String> {
+        let make_vector = |items: Vec<SExpression>| {
+            Ok(SExpression::Cell(Cell::Vector(Rc::new(RefCell::new(
+                items,
+            )))))
+        };
+        match func {
+            CoreFunc::IsVector => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let is_vector = matches!(values[0], SExpression::Cell(Cell::Vector(_)));
+                Ok(SExpression::Cell(Cell::Bool(is_vector)))
+            }
+            CoreFunc::MakeVector => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let size = Environment::index_arg(func, &values[0])?;
+                let fill = values.get(1).cloned().unwrap_or(SExpression::Null);
+                // A huge size shouldn't abort the whole process
+                let mut items = Vec::new();
+                items.try_reserve(size).map_err(|_| {
+                    format!("{}: a vector of {} items is too large", func.print(), size)
+                })?;
+                items.resize(size, fill);
+                make_vector(items)
+            }
+            CoreFunc::Vector => make_vector(values),
+            CoreFunc::VectorLength => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let items = Environment::vector_arg(func, &values[0])?;
+                let length = items.borrow().len();
+                Ok(SExpression::Cell(Cell::Int(length as i64)))
+            }
+            CoreFunc::VectorRef | CoreFunc::VectorSet => {
+                let arg_count = if let CoreFunc::VectorRef = func { 2 } else { 3 };
+                Environment::check_arg_count(func, &values, arg_count, Some(arg_count))?;
+                let items = Environment::vector_arg(func, &values[0])?;
+                let index = Environment::index_arg(func, &values[1])?;
+                let mut items = items.borrow_mut();
+                let length = items.len();
+                let item = items.get_mut(index).ok_or_else(|| {
+                    format!(
+                        "Index {} out of range for a vector of length {}",
+                        index, length
+                    )
+                })?;
+                match values.get(2) {
+                    Some(value) => {
+                        *item = value.clone();
+                        Ok(SExpression::Null)
+                    }
+                    None => Ok(item.clone()),
+                }
+            }
+            CoreFunc::VectorToList => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let items = Environment::vector_arg(func, &values[0])?;
+                let list = List::make_from_sexps(items.borrow().clone());
+                Ok(SExpression::List(list))
+            }
+            CoreFunc::ListToVector => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let list = Environment::list_arg(func, &values[0])?;
+                make_vector(list.to_vec())
+            }
+            _ => Err(format!("{} is not a vector function", func.print())),
+        }
+    }
+
+    // (json->string value) is compact; (json->string value 2) indents nested
+    // items by two spaces a level. (string->json text 'hash-table) reads
+    // objects as hash tables rather than association lists.
+    fn apply_json_func(
+        &self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        match func {
+            // (json-read [port] ['hash-table])
+            CoreFunc::JsonRead => {
+                Environment::check_arg_count(func, &values, 0, Some(2))?;
+                let (port, options) = match values.first() {
+                    Some(SExpression::Cell(Cell::Port(port))) => (port.clone(), &values[1..]),
+                    _ => {
+                        // Without a port the only argument is the object type
+                        Environment::check_arg_count(func, &values, 0, Some(1))?;
+                        (self.context.ports.input(), &values[..])
+                    }
+                };
+                let objects = json::ObjectType::from_arg(options.first())?;
+                let mut port = port.borrow_mut();
+                match json::read(port.reader()?, objects)? {
+                    Some(value) => Ok(value),
+                    None => Ok(SExpression::Cell(Cell::Eof)),
+                }
+            }
+            CoreFunc::StringToJson => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let objects = json::ObjectType::from_arg(values.get(1))?;
+                match &values[0] {
+                    SExpression::Cell(Cell::Str(text)) => json::parse(text, objects),
+                    other => Err(format!(
+                        "'{}' requires a string, got {}",
+                        func.print(),
+                        other.print()
+                    )),
+                }
+            }
+            // (json-write value [port] [indent])
+            CoreFunc::JsonWrite | CoreFunc::JsonToString => {
+                let max_args = if let CoreFunc::JsonWrite = func { 3 } else { 2 };
+                Environment::check_arg_count(func, &values, 1, Some(max_args))?;
+                let (port, options) = match values.get(1) {
+                    Some(SExpression::Cell(Cell::Port(port))) => (port.clone(), &values[2..]),
+                    _ => (self.context.ports.output(), &values[1..]),
+                };
+                let indent = match options.first() {
+                    Some(indent) => Environment::index_arg(func, indent)?,
+                    None => 0,
+                };
+                let text = json::write(&values[0], indent)?;
+                if let CoreFunc::JsonToString = func {
+                    return Ok(SExpression::Cell(Cell::Str(text)));
+                }
+                port.borrow_mut().write_str(&text)?;
+                Ok(SExpression::Null)
+            }
+            CoreFunc::IsJsonNull => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                Ok(SExpression::Cell(Cell::Bool(json::is_null(&values[0]))))
+            }
+            CoreFunc::JsonNull => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                Ok(json::null())
+            }
+            _ => Err(format!("{} is not a JSON function", func.print())),
+        }
+    }
+
+    // The port argument at index, or the current input or output port when
+    // it's left out
+    fn port_arg(
+        &self,
+        func: CoreFunc,
+        values: &[SExpression],
+        index: usize,
+        input: bool,
+    ) -> Result<PortRef, String> {
+        let port = match values.get(index) {
+            Some(SExpression::Cell(Cell::Port(port))) => port.clone(),
+            Some(other) => {
+                return Err(format!(
+                    "'{}' needs a port, got {}",
+                    func.print(),
+                    other.print()
+                ))
+            }
+            None if input => self.context.ports.input(),
+            None => self.context.ports.output(),
+        };
+        if port.borrow().is_input() != input {
+            let wanted = if input { "an input" } else { "an output" };
+            return Err(format!("'{}' needs {} port", func.print(), wanted));
+        }
+        Ok(port)
+    }
+
+    // Characters and strings go through textual ports, bytes through binary ones
+    fn port_kind_arg(
+        &self,
+        func: CoreFunc,
+        values: &[SExpression],
+        index: usize,
+        input: bool,
+        binary: bool,
+    ) -> Result<PortRef, String> {
+        let port = self.port_arg(func, values, index, input)?;
+        if port.borrow().is_binary() != binary {
+            let wanted = if binary { "a binary" } else { "a textual" };
+            return Err(format!("'{}' needs {} port", func.print(), wanted));
+        }
+        Ok(port)
+    }
+
+    fn string_arg(func: CoreFunc, value: &SExpression) -> Result<String, String> {
+        match value {
+            SExpression::Cell(Cell::Str(text)) => Ok(text.clone()),
+            _ => Err(format!(
+                "'{}' requires a string, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    fn apply_port_func(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let unspecified = Ok(SExpression::Null);
+        let eof = SExpression::Cell(Cell::Eof);
+        let port_value = |port: PortRef| Ok(SExpression::Cell(Cell::Port(port)));
+        let truth = |value: bool| Ok(SExpression::Cell(Cell::Bool(value)));
+        match func {
+            CoreFunc::IsInputPort
+            | CoreFunc::IsOutputPort
+            | CoreFunc::IsTextualPort
+            | CoreFunc::IsBinaryPort => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let port = match &values[0] {
+                    SExpression::Cell(Cell::Port(port)) => port.borrow(),
+                    _ => return truth(false),
+                };
+                truth(match func {
+                    CoreFunc::IsInputPort => port.is_input(),
+                    CoreFunc::IsOutputPort => !port.is_input(),
+                    CoreFunc::IsTextualPort => !port.is_binary(),
+                    _ => port.is_binary(),
+                })
+            }
+            CoreFunc::OpenInputFile | CoreFunc::OpenBinaryInputFile => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let path = Environment::string_arg(func, &values[0])?;
+                let binary = func == CoreFunc::OpenBinaryInputFile;
+                port_value(Port::open_input_file(&path, binary)?)
+            }
+            CoreFunc::OpenOutputFile | CoreFunc::OpenBinaryOutputFile => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let path = Environment::string_arg(func, &values[0])?;
+                let binary = func == CoreFunc::OpenBinaryOutputFile;
+                port_value(Port::open_output_file(&path, binary)?)
+            }
+            // (call-with-input-file path proc) passes proc the open port and
+            // closes it when proc returns
+            CoreFunc::CallWithInputFile | CoreFunc::CallWithOutputFile => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let path = Environment::string_arg(func, &values[0])?;
+                let port = if let CoreFunc::CallWithInputFile = func {
+                    Port::open_input_file(&path, false)?
+                } else {
+                    Port::open_output_file(&path, false)?
+                };
+                let result = self.apply_procedure(
+                    values[1].clone(),
+                    vec![SExpression::Cell(Cell::Port(port.clone()))],
+                );
+                port.borrow_mut().close()?;
+                result
+            }
+            // (with-output-to-file path thunk) calls thunk with the file as
+            // the current output port
+            CoreFunc::WithInputFromFile | CoreFunc::WithOutputToFile => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let path = Environment::string_arg(func, &values[0])?;
+                let thunk = values[1].clone();
+                let context = self.context.clone();
+                let (port, result) = if let CoreFunc::WithInputFromFile = func {
+                    let port = Port::open_input_file(&path, false)?;
+                    let result = context
+                        .ports
+                        .with_input(port.clone(), || self.apply_procedure(thunk, Vec::new()));
+                    (port, result)
+                } else {
+                    let port = Port::open_output_file(&path, false)?;
+                    let result = context
+                        .ports
+                        .with_output(port.clone(), || self.apply_procedure(thunk, Vec::new()));
+                    (port, result)
+                };
+                // Closed even when the thunk raised, so what it wrote is flushed
+                port.borrow_mut().close()?;
+                result
+            }
+            CoreFunc::OpenInputString => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let text = Environment::string_arg(func, &values[0])?;
+                port_value(Port::open_input_string(&text))
+            }
+            CoreFunc::OpenOutputString => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                port_value(Port::open_output_string())
+            }
+            CoreFunc::GetOutputString => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let port = self.port_arg(func, &values, 0, false)?;
+                let text = port.borrow().output_string()?;
+                Ok(SExpression::Cell(Cell::Str(text)))
+            }
+            // (with-output-to-string thunk) gives everything thunk printed;
+            // (call-with-output-string proc) everything proc wrote to its port
+            CoreFunc::WithOutputToString | CoreFunc::CallWithOutputString => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let port = Port::open_output_string();
+                if let CoreFunc::WithOutputToString = func {
+                    let context = self.context.clone();
+                    context.ports.with_output(port.clone(), || {
+                        self.apply_procedure(values[0].clone(), Vec::new())
+                    })?;
+                } else {
+                    let args = vec![SExpression::Cell(Cell::Port(port.clone()))];
+                    self.apply_procedure(values[0].clone(), args)?;
+                }
+                let text = port.borrow().output_string()?;
+                Ok(SExpression::Cell(Cell::Str(text)))
+            }
+            CoreFunc::CurrentInputPort => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                port_value(self.context.ports.input())
+            }
+            CoreFunc::CurrentOutputPort => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                port_value(self.context.ports.output())
+            }
+            CoreFunc::ReadLine => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let port = self.port_kind_arg(func, &values, 0, true, false)?;
+                let line = port.borrow_mut().read_line()?;
+                Ok(line.map_or(eof, |line| SExpression::Cell(Cell::Str(line))))
+            }
+            // (read [port]) gives the next datum without evaluating it; a
+            // string reads the first datum in it
+            CoreFunc::Read => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let port = match values.first() {
+                    Some(SExpression::Cell(Cell::Str(text))) => Port::open_input_string(text),
+                    _ => self.port_kind_arg(func, &values, 0, true, false)?,
+                };
+                let mut port = port.borrow_mut();
+                match DatumReader::new(port.reader()?).read()? {
+                    Some(datum) => Ok(datum),
+                    None => Ok(eof),
+                }
+            }
+            // There's no character type, so characters are one-character strings
+            CoreFunc::ReadChar | CoreFunc::PeekChar => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let port = self.port_kind_arg(func, &values, 0, true, false)?;
+                let c = if let CoreFunc::ReadChar = func {
+                    port.borrow_mut().read_char()?
+                } else {
+                    port.borrow_mut().peek_char()?
+                };
+                Ok(c.map_or(eof, |c| SExpression::Cell(Cell::Str(c.to_string()))))
+            }
+            CoreFunc::ReadString => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let count = Environment::index_arg(func, &values[0])?;
+                let port = self.port_kind_arg(func, &values, 1, true, false)?;
+                let text = port.borrow_mut().read_string(count)?;
+                Ok(text.map_or(eof, |text| SExpression::Cell(Cell::Str(text))))
+            }
+            CoreFunc::ReadU8 | CoreFunc::PeekU8 => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let port = self.port_kind_arg(func, &values, 0, true, true)?;
+                let byte = if let CoreFunc::ReadU8 = func {
+                    port.borrow_mut().read_byte()?
+                } else {
+                    port.borrow_mut().peek_byte()?
+                };
+                Ok(byte.map_or(eof, |byte| SExpression::Cell(Cell::Int(i64::from(byte)))))
+            }
+            CoreFunc::WriteString => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let text = Environment::string_arg(func, &values[0])?;
+                let port = self.port_kind_arg(func, &values, 1, false, false)?;
+                port.borrow_mut().write_str(&text)?;
+                unspecified
+            }
+            CoreFunc::WriteU8 => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let byte = match Environment::index_arg(func, &values[0])? {
+                    byte if byte <= 255 => byte as u8,
+                    byte => return Err(format!("'{}' needs a byte, got {}", func.print(), byte)),
+                };
+                let port = self.port_kind_arg(func, &values, 1, false, true)?;
+                port.borrow_mut().write_bytes(&[byte])?;
+                unspecified
+            }
+            CoreFunc::Newline => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let port = self.port_kind_arg(func, &values, 0, false, false)?;
+                port.borrow_mut().write_str("\n")?;
+                unspecified
+            }
+            // display prints strings without quotes, write the way the parser
+            // would read them back
+            CoreFunc::Display | CoreFunc::Write => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let port = self.port_kind_arg(func, &values, 1, false, false)?;
+                let text = if let CoreFunc::Display = func {
+                    values[0].print()
+                } else {
+                    values[0].write()
+                };
+                port.borrow_mut().write_str(&text)?;
+                unspecified
+            }
+            CoreFunc::ClosePort | CoreFunc::CloseInputPort | CoreFunc::CloseOutputPort => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let port = match func {
+                    CoreFunc::CloseInputPort => self.port_arg(func, &values, 0, true)?,
+                    CoreFunc::CloseOutputPort => self.port_arg(func, &values, 0, false)?,
+                    _ => match &values[0] {
+                        SExpression::Cell(Cell::Port(port)) => port.clone(),
+                        other => {
+                            return Err(format!(
+                                "'{}' needs a port, got {}",
+                                func.print(),
+                                other.print()
+                            ))
+                        }
+                    },
+                };
+                port.borrow_mut().close()?;
+                unspecified
+            }
+            CoreFunc::IsEofObject => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                truth(matches!(values[0], SExpression::Cell(Cell::Eof)))
+            }
+            CoreFunc::EofObject => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                Ok(eof)
+            }
+            _ => Err(format!("{} is not a port function", func.print())),
+        }
+    }
+
+    // (load "file" [env]) runs the file's top-level forms in env, or at the
+    // top level, and gives the value of the last one
+    fn apply_load(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        Environment::check_arg_count(func, &values, 1, Some(2))?;
+        let name = Environment::string_arg(func, &values[0])?;
+        let mut env = match values.get(1) {
+            Some(env) => Environment::environment_arg(func, env)?,
+            None => self.global(),
+        };
+        let path = load::resolve(&self.context, &name)?;
+        load::with_loading(&self.context, &path, || load::eval_file(&path, &mut env))?
+    }
+
+    // (exit) and (exit #t) succeed, (exit #f) fails, and (exit n) exits with n.
+    // It unwinds like an error so the program embedding the interpreter
+    // decides what leaving means; the code waits in the context until the
+    // error gets to the top.
+    fn apply_exit(&self, func: CoreFunc, values: Vec<SExpression>) -> Result<SExpression, String> {
+        Environment::check_arg_count(func, &values, 0, Some(1))?;
+        let code = match values.first() {
+            None | Some(SExpression::Cell(Cell::Bool(true))) => 0,
+            Some(SExpression::Cell(Cell::Bool(false))) => 1,
+            Some(SExpression::Cell(Cell::Int(code))) => *code as i32,
+            Some(SExpression::Cell(Cell::Flt(code))) if code.fract() == 0.0 => *code as i32,
+            Some(other) => {
+                return Err(format!(
+                    "'exit' needs a boolean or an integer, got {}",
+                    other.print()
+                ))
+            }
+        };
+        self.context.exit_code.set(Some(code));
+        Err(format!("Exit with code {}", code))
+    }
+
+    fn apply_exception_func(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let truth = |value: bool| Ok(SExpression::Cell(Cell::Bool(value)));
+        match func {
+            // (error message irritant ...)
+            CoreFunc::Error => {
+                Environment::check_arg_count(func, &values, 1, None)?;
+                let message = Environment::string_arg(func, &values[0])?;
+                let condition = Condition::new(message, values[1..].to_vec());
+                Err(exceptions::raise(
+                    &self.context,
+                    SExpression::Cell(Cell::Condition(Rc::new(condition))),
+                ))
+            }
+            CoreFunc::Raise => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                Err(exceptions::raise(&self.context, values[0].clone()))
+            }
+            // The handler's value is what raise-continuable gives back
+            CoreFunc::RaiseContinuable => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let context = self.context.clone();
+                match exceptions::innermost_handler(&context) {
+                    Some(handler) => exceptions::outside_handler(&context, || {
+                        self.apply_procedure(handler, values.clone())
+                    }),
+                    None => Err(exceptions::raise(&context, values[0].clone())),
+                }
+            }
+            // (with-exception-handler handler thunk). Only raise-continuable
+            // can carry on after the handler; for anything else the handler
+            // returning is an error of its own.
+            CoreFunc::WithExceptionHandler => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let (handler, thunk) = (values[0].clone(), values[1].clone());
+                let context = self.context.clone();
+                let caught = exceptions::catch(&context, Some(handler.clone()), || {
+                    self.apply_procedure(thunk, Vec::new())
+                });
+                match caught {
+                    Ok(result) => result,
+                    Err(raised) => {
+                        let description = match &raised {
+                            SExpression::Cell(Cell::Condition(condition)) => condition.describe(),
+                            other => other.write(),
+                        };
+                        self.apply_procedure(handler, vec![raised])?;
+                        let message = format!(
+                            "The exception handler returned after a raise of {}",
+                            description
+                        );
+                        let condition = Condition::new(message, Vec::new());
+                        Err(exceptions::raise(
+                            &self.context,
+                            SExpression::Cell(Cell::Condition(Rc::new(condition))),
+                        ))
+                    }
+                }
+            }
+            // (with-exception-catcher handler thunk) unwinds out of thunk
+            // before calling the handler, whose value it gives. guard is
+            // made of this.
+            CoreFunc::WithExceptionCatcher => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let (handler, thunk) = (values[0].clone(), values[1].clone());
+                let context = self.context.clone();
+                match exceptions::catch(&context, None, || self.apply_procedure(thunk, Vec::new()))
+                {
+                    Ok(result) => result,
+                    Err(raised) => self.apply_procedure(handler, vec![raised]),
+                }
+            }
+            CoreFunc::IsErrorObject => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                truth(matches!(values[0], SExpression::Cell(Cell::Condition(_))))
+            }
+            CoreFunc::ErrorObjectMessage | CoreFunc::ErrorObjectIrritants => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let condition = match &values[0] {
+                    SExpression::Cell(Cell::Condition(condition)) => condition,
+                    other => {
+                        return Err(format!(
+                            "'{}' requires an error object, got {}",
+                            func.print(),
+                            other.print()
+                        ))
+                    }
+                };
+                if func == CoreFunc::ErrorObjectMessage {
+                    Ok(SExpression::Cell(Cell::Str(condition.message.clone())))
+                } else {
+                    Ok(SExpression::List(List::make_from_sexps(
+                        condition.irritants.clone(),
+                    )))
+                }
+            }
+            _ => Err(format!("{} is not an exception function", func.print())),
+        }
+    }
+
+    fn environment_arg(func: CoreFunc, value: &SExpression) -> Result<Environment, String> {
+        match value {
+            SExpression::Cell(Cell::Environment(env)) => Ok(env.clone()),
+            _ => Err(format!(
+                "'{}' requires an environment, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    fn symbol_arg(func: CoreFunc, value: &SExpression) -> Result<i32, String> {
+        match value {
+            SExpression::Cell(Cell::Symbol(number, _)) => Ok(*number),
+            _ => Err(format!(
+                "'{}' requires a symbol, got {}",
+                func.print(),
+                value.print()
+            )),
+        }
+    }
+
+    // Environments are values here, so Scheme code can make its own scopes,
+    // evaluate code in them and look at what's defined.
+    fn apply_environment_func(
+        &mut self,
+        func: CoreFunc,
+        values: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        let env_value = |env: Environment| Ok(SExpression::Cell(Cell::Environment(env)));
+        match func {
+            // (eval expr [env]) evaluates data as code, in the top-level
+            // environment if none is given
+            CoreFunc::Eval => {
+                Environment::check_arg_count(func, &values, 1, Some(2))?;
+                let mut env = match values.get(1) {
+                    Some(env) => Environment::environment_arg(func, env)?,
+                    None => self.global(),
+                };
+                env.evaluate(values[0].clone())
+            }
+            CoreFunc::IsEnvironment => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let is_env = matches!(values[0], SExpression::Cell(Cell::Environment(_)));
+                Ok(SExpression::Cell(Cell::Bool(is_env)))
+            }
+            CoreFunc::InteractionEnvironment => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                env_value(self.global())
+            }
+            // Only the built-ins, with none of the program's definitions. The
+            // version number is accepted and ignored.
+            CoreFunc::SchemeReportEnvironment => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                env_value(self.new_top_level())
+            }
+            // (make-environment [parent]) makes an empty environment, or one
+            // whose lookups fall back to parent
+            CoreFunc::MakeEnvironment => {
+                Environment::check_arg_count(func, &values, 0, Some(1))?;
+                let parent = match values.first() {
+                    Some(parent) => Some(Environment::environment_arg(func, parent)?),
+                    None => None,
+                };
+                env_value(match parent {
+                    Some(parent) => extend_environment(&parent),
+                    None => self.new_top_level(),
+                })
+            }
+            CoreFunc::CurrentEnvironment => {
+                Environment::check_arg_count(func, &values, 0, Some(0))?;
+                env_value(self.clone())
+            }
+            CoreFunc::IsEnvironmentBound | CoreFunc::EnvironmentRef => {
+                Environment::check_arg_count(func, &values, 2, Some(2))?;
+                let env = Environment::environment_arg(func, &values[0])?;
+                let symbol = Environment::symbol_arg(func, &values[1])?;
+                let value = env.get_definition_by_symbol(symbol);
+                if let CoreFunc::EnvironmentRef = func {
+                    return value;
+                }
+                Ok(SExpression::Cell(Cell::Bool(value.is_ok())))
+            }
+            // Defines or redefines the symbol in that environment itself
+            CoreFunc::EnvironmentDefine => {
+                Environment::check_arg_count(func, &values, 3, Some(3))?;
+                let mut env = Environment::environment_arg(func, &values[0])?;
+                let symbol = Environment::symbol_arg(func, &values[1])?;
+                env.define_or_replace(symbol, values[2].clone());
+                Ok(values[1].clone())
+            }
+            CoreFunc::EnvironmentBindings => {
+                Environment::check_arg_count(func, &values, 1, Some(1))?;
+                let env = Environment::environment_arg(func, &values[0])?;
+                Ok(env.bindings())
+            }
+            _ => Err(format!("{} is not an environment function", func.print())),
+        }
+    }
+
+    // (cons key value) gives (key value) when value isn't a list, or
+    // (key v1 v2 ...) when it is, so take whatever follows the key.
+    fn alist_entry_value(entry: &List) -> SExpression {
+        let rest = entry.rest();
+        if !rest.is_empty() && rest.rest().is_empty() {
+            *rest.first()
+        } else {
+            SExpression::List(rest)
+        }
+    }
+
     pub fn apply_special_form(
         &mut self,
         func: SpecialForm,
@@ -438,8 +1700,27 @@ impl Environment<'_> {
             SpecialForm::Set => self.evaluate_set(args),
             SpecialForm::While => self.evaluate_while(args),
             SpecialForm::Begin => self.eval_each_return_last(args),
-            SpecialForm::Output => self.evaluate_output(args),
-            SpecialForm::OutputLine => self.evaluate_output_line(args),
+            SpecialForm::Quote => {
+                if args.is_empty() || !args.rest().is_empty() {
+                    return Err("quote takes exactly one expression.".to_string());
+                }
+                Ok(*args.first())
+            }
+            SpecialForm::Lambda => self.evaluate_lambda(args),
+            SpecialForm::Output => self.evaluate_output(args, false),
+            SpecialForm::OutputLine => self.evaluate_output(args, true),
+            SpecialForm::Input => self.evaluate_input(args),
+            SpecialForm::Include => self.evaluate_include(args),
+            SpecialForm::DefineLibrary => library::define_library(self, args),
+            SpecialForm::Import => library::import(self, args),
+            SpecialForm::Guard => self.evaluate(exceptions::expand_guard(&args)?),
+            // The definitions in the current scope
+            SpecialForm::Env => {
+                if !args.is_empty() {
+                    return Err("env takes no arguments.".to_string());
+                }
+                Ok(self.bindings())
+            }
             SpecialForm::Define => {
                 let new_symbol = args.first();
                 let value_for_symbol = args.rest().first();
@@ -453,9 +1734,15 @@ impl Environment<'_> {
                 match *new_symbol.clone() {
                     // If it's a cell, it must be a symbol Cell::Symbol
                     SExpression::Cell(cell) => match cell {
-                        Cell::Symbol(number, name) => {
+                        Cell::Symbol(number, _name) => {
+                            let evaluated_value = self.evaluate(*value_for_symbol)?;
+                            self.define(number, evaluated_value)?;
+                            Ok(*new_symbol)
+                        }
+                        // Inside a lambda the analysis has already found the slot
+                        Cell::Local(0, slot, number) => {
                             let evaluated_value = self.evaluate(*value_for_symbol)?;
-                            let def_id = self.define(name, evaluated_value)?;
+                            self.define_local(slot, number, evaluated_value)?;
                             Ok(*new_symbol)
                         }
                         _ => Err(format!("Cannot re-define {}", &cell.print())),
@@ -468,11 +1755,16 @@ impl Environment<'_> {
                         match *function_signature.first() {
                             SExpression::Cell(n) => match n {
                                 // This *should* be the name of the function
-                                Cell::Symbol(number, name) => {
-                                    let params = Box::new(SExpression::List(parameter_names));
-                                    let body = Box::new(*value_for_symbol);
-                                    let value = SExpression::Cell(Cell::Lambda(params, body));
-                                    let def_id = self.define(name, value);
+                                Cell::Symbol(number, _name) => {
+                                    let template = analyze_lambda(
+                                        Some(number),
+                                        &parameter_names,
+                                        *value_for_symbol,
+                                        &self.context,
+                                    )?;
+                                    let value =
+                                        SExpression::Cell(Cell::Lambda(template, self.clone()));
+                                    self.define(number, value)?;
                                     Ok(*new_symbol.clone())
                                 }
                                 _ => Err(format!("Invalid function name: {}", &n.print())),
@@ -489,10 +1781,27 @@ impl Environment<'_> {
                     )),
                 }
             }
-            _ => Err(format!("Special form {} not implemented!", "not printable")),
+            _ => Err(format!("Special form {} not implemented!", func.print())),
         }
     }
 
+    // (lambda (params) body ...) makes a procedure. Lambdas inside other lambdas
+    // were already analyzed along with their enclosing lambda and are
+    // evaluated as templates instead.
+    fn evaluate_lambda(&mut self, args: List) -> Result<SExpression, String> {
+        let params = match *args.first() {
+            SExpression::List(params) => params,
+            other => {
+                return Err(format!(
+                    "lambda requires a list of parameters, got {}",
+                    other.print()
+                ))
+            }
+        };
+        let template = analyze_lambda(None, &params, lambda_body(args.rest()), &self.context)?;
+        Ok(SExpression::Cell(Cell::Lambda(template, self.clone())))
+    }
+
     // Requires three arguments: 'if' must have a test expression and both outcomes of the test.
     fn evaluate_if(&mut self, clauses: List) -> Result<SExpression, String> {
         if clauses.is_empty() {
@@ -570,7 +1879,7 @@ impl Environment<'_> {
         Ok(return_value)
     }
 
-    // The result of set! will be the symbol number in the current scope. Mostly this
+    // The result of set! will be the slot number in the scope the variable lives in. Mostly this
     // value is only a side-effect of set! which will be ignored.
     fn evaluate_set(&mut self, args: List) -> Result<SExpression, String> {
         if args.is_empty() {
@@ -580,33 +1889,87 @@ impl Environment<'_> {
         let variable_to_change = *args.first();
         let change_to = args.rest();
         if change_to.is_empty() {
-            return Err(format!("set! expression must have two arguments."));
+            return Err("set! expression must have two arguments.".to_string());
         }
 
         if !change_to.rest().is_empty() {
-            return Err(format!("set! expression must have only two arguments."));
+            return Err("set! expression must have only two arguments.".to_string());
         }
 
         let change_to_value = self.evaluate(*change_to.first())?;
 
-        let (local_number, name) = match variable_to_change {
+        match variable_to_change {
             SExpression::Cell(c) => match c {
-                Cell::Symbol(n, s) => Ok((n, s)),
+                Cell::Symbol(n, _) => self.set_by_symbol(n, change_to_value),
+                Cell::Local(depth, slot, n) => self.set_by_address(depth, slot, n, change_to_value),
                 _ => Err(format!(
                     "First argument to set! must be a symbol but was {} instead.",
                     c.print()
                 )),
             },
             _ => Err("First argument to set! must be a symbol.".to_string()),
-        }?;
+        }
+    }
+
+    // set! changes the variable wherever it was defined: this scope or any
+    // scope around it, out to the top level. The frames are shared, so a
+    // closure sees the change and so does everything else using the frame.
+    pub(crate) fn set_by_symbol(
+        &self,
+        symbol: i32,
+        value: SExpression,
+    ) -> Result<SExpression, String> {
+        let mut frame = self.frame.clone();
+        loop {
+            let parent = {
+                let current = frame.borrow();
+                if let Some(slot) = current.slot_of(symbol) {
+                    drop(current);
+                    return Environment::set_slot(&frame, slot, symbol, value);
+                }
+                current.parent.clone()
+            };
+            match parent {
+                Some(outer) => frame = outer,
+                None => return Err(format!("{} not defined.", symbol_name(symbol))),
+            }
+        }
+    }
 
-        let number = match self.definitions_by_symbol.get(&name) {
-            Some(n) => Ok(*n),
-            None => Err(format!("{} not defined.", &name)),
-        }?;
+    pub(crate) fn set_by_address(
+        &self,
+        depth: usize,
+        slot: usize,
+        symbol: i32,
+        value: SExpression,
+    ) -> Result<SExpression, String> {
+        match self.frame_at(depth) {
+            Some(frame) => Environment::set_slot(&frame, slot, symbol, value),
+            None => Err(format!("{} not defined.", symbol_name(symbol))),
+        }
+    }
 
-        self.definitions[number] = change_to_value;
-        Ok(SExpression::Cell(Cell::Int(number as i64)))
+    // The frame depth steps out from this one
+    fn frame_at(&self, depth: usize) -> Option<Rc<RefCell<Frame>>> {
+        let mut frame = Some(self.frame.clone());
+        for _ in 0..depth {
+            frame = frame.and_then(|f| f.borrow().parent.clone());
+        }
+        frame
+    }
+
+    // Only a variable that's been defined can be set
+    fn set_slot(
+        frame: &Rc<RefCell<Frame>>,
+        slot: usize,
+        symbol: i32,
+        value: SExpression,
+    ) -> Result<SExpression, String> {
+        match frame.borrow_mut().definitions.get_mut(slot) {
+            Some(Some(old_value)) => *old_value = value,
+            _ => return Err(format!("{} not defined.", symbol_name(symbol))),
+        }
+        Ok(SExpression::Cell(Cell::Int(slot as i64)))
     }
 
     // Instead of evaluating the list as a whole, evaluate each s-expression
@@ -645,68 +2008,53 @@ impl Environment<'_> {
     //
     // This is the behavior we want from a (begin ...) block of expressions.
     fn eval_each_return_last(&mut self, exprs: List) -> Result<SExpression, String> {
-        let results = self.eval_each(exprs)?;
-        Ok(results[results.len() - 1].clone())
-    }
-
-    // Assign all values to names in args
-    fn define_all(&mut self, params: SExpression, values: Vec<SExpression>) {
-        let param_names = match params {
-            SExpression::List(names) => names,
-            _ => panic!("Invalid parameter list {:?}", params.print()),
-        };
-
-        let mut remaining_names = param_names;
-        let mut arg_num = 0;
-        while !remaining_names.is_empty() {
-            let name = remaining_names.first();
-            if arg_num + 1 > values.len() {
-                panic!("Mismatch between number of arguments and function parameters!");
-            }
-            let value = values[arg_num].clone();
-            //if TRACE {println!("Define {} as {}",&name, &value.print());}
-            match *name {
-                SExpression::Cell(Cell::Symbol(_, n)) => self.define(n, value),
-                _ => panic!(
-                    "A parameter name must be a symbol but you used {}",
-                    &*name.print()
-                ),
-            };
-            arg_num += 1;
-            remaining_names = remaining_names.rest();
-        }
+        let mut results = self.eval_each(exprs)?;
+        Ok(results.pop().unwrap_or(SExpression::Null))
     }
 
+    // Call whatever procedure the first item of a list evaluates to: a
+    // variable holding a lambda, or a lambda expression.
     pub fn apply_function(
         &mut self,
-        number: i32,
-        name: String,
+        function: SExpression,
         args: List,
     ) -> Result<SExpression, String> {
         if TRACE {
-            println!("Try to evaluate symbol '{}' as function call", &name);
+            println!("Try to evaluate '{}' as function call", &function.print());
         }
-        let func = self.get_definition_by_symbol(name)?;
-        if let SExpression::Cell(Cell::Lambda(params, body)) = func {
-            // match the params to the args
-            // then evaluate the body in the
-            // new environment:
-
-            // Evaluate the arguments in the current context
-            let values_from_args = self.eval_each(args)?;
-
-            // Make a new environment with the current one as the parent
-            let mut local_env = self.make_child();
+        let func = self.evaluate(function)?;
+        // Evaluate the arguments in the current context
+        let values_from_args = self.eval_each(args)?;
+        self.apply_procedure(func, values_from_args)
+    }
 
-            // Add all evaluated args to the child env with the 'params' names
-            // according to order in the function call:
-            local_env.define_all(*params, values_from_args);
-            if TRACE {
-                println!("Created child env\n {}", &local_env.print());
+    // Call a procedure value with arguments that have already been evaluated,
+    // for instance a lambda passed to a core function like hash-table-walk.
+    pub fn apply_procedure(
+        &mut self,
+        procedure: SExpression,
+        values_from_args: Vec<SExpression>,
+    ) -> Result<SExpression, String> {
+        match procedure {
+            SExpression::Cell(Cell::Lambda(template, defining_env)) => {
+                // The arguments fill the first slots of a new frame whose parent
+                // is the environment the lambda was created in.
+                let mut local_env =
+                    Environment::make_call_frame(&template, &defining_env, values_from_args)?;
+                if TRACE {
+                    println!("Created child env\n {}", &local_env.print());
+                }
+                local_env.evaluate(template.body.clone())
             }
-            local_env.evaluate(*body)
-        } else {
-            Err(format!("Can't evaluate as function: {}", &func.print()))
+            SExpression::Cell(Cell::Core(func)) => self.call_core_func(func, values_from_args),
+            SExpression::Cell(Cell::Native(function)) => function.call(&values_from_args),
+            // An operator under another name, as from an import with a prefix
+            SExpression::Cell(Cell::Op(operator)) => operator.apply(&values_from_args),
+            SExpression::Cell(Cell::Logical(operator)) => operator.compare(&values_from_args),
+            _ => Err(format!(
+                "Can't evaluate as function: {}",
+                &procedure.print()
+            )),
         }
     }
 
@@ -725,15 +2073,8 @@ impl Environment<'_> {
             return Err(String::from("Operator ") + func.print() + " requires two arguments");
         }
 
-        use crate::primitives::NumericOperator::*;
-        let not_implemented = format!("Operator '{}' not implemented", func.print());
-        match func {
-            Add => self.add(list),
-            Subtract => self.subtract(list),
-            Multiply => self.multiply(list),
-            Divide => self.divide(list),
-            _ => Err(not_implemented),
-        }
+        let values_from_args = self.eval_each(list)?;
+        func.apply(&values_from_args)
     }
 
     // Assuming it is not a null list and we have an operator or function, pass its cdr in and apply it:
@@ -753,14 +2094,11 @@ impl Environment<'_> {
         use crate::primitives::LogicalOperator::*;
         let not_implemented = format!("Operator '{}' not implemented", func.print());
         match func {
-            Greater => self.eval_greater(list),
-            Less => self.eval_less(list),
-            Equal => self.eval_equal(list),
+            Greater | Less | Equal => func.compare(&self.eval_each(list)?),
             Or => self.eval_or(list),
             And => self.eval_and(list),
             //Not=> eval_not(list),
             //Xor=> eval_xor(list),
-            Greater => Err(not_implemented),
             _ => Err(not_implemented),
         }
     }
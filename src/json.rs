@@ -0,0 +1,488 @@
+use crate::convert::alist_entries;
+use crate::hash_table::{Equivalence, HashTable};
+use crate::list::List;
+use crate::parser::symbol_cell;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use std::cell::RefCell;
+use std::io::BufRead;
+use std::rc::Rc;
+
+// JSON text to and from interpreter values:
+//
+//   object    an association list of (key value) entries with symbol keys, or
+//             an equal? hash table with symbol keys if asked for
+//   array     a vector
+//   string    a string
+//   number    an integer if it has no fraction or exponent, otherwise a float
+//   true      #t, and false is #f
+//   null      a value of its own, (json-null), so it can't be mistaken for
+//             false, '() or the symbol null
+//
+// Writing goes the other way; strings or symbols both work as object keys, and
+// any other symbol is written as a string.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectType {
+    Alist,
+    HashTable,
+}
+
+impl ObjectType {
+    // From the optional argument to json-read and string->json: 'alist or 'hash-table
+    pub fn from_arg(arg: Option<&SExpression>) -> Result<ObjectType, String> {
+        match arg {
+            None => Ok(ObjectType::Alist),
+            Some(SExpression::Cell(Cell::Symbol(_, name))) if name == "alist" => {
+                Ok(ObjectType::Alist)
+            }
+            Some(SExpression::Cell(Cell::Symbol(_, name))) if name == "hash-table" => {
+                Ok(ObjectType::HashTable)
+            }
+            Some(other) => Err(format!(
+                "JSON objects can be read as 'alist or 'hash-table, got {}",
+                other.print()
+            )),
+        }
+    }
+}
+
+// Deeper nesting than this is almost certainly bad input, and would otherwise
+// overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+pub fn null() -> SExpression {
+    SExpression::Cell(Cell::JsonNull)
+}
+
+pub fn is_null(value: &SExpression) -> bool {
+    matches!(value, SExpression::Cell(Cell::JsonNull))
+}
+
+// Read one value from the input, leaving anything after it unread so the next
+// value can be read from the same stream. None at the end of the input.
+pub fn read<R: BufRead>(input: &mut R, objects: ObjectType) -> Result<Option<SExpression>, String> {
+    let mut reader = Reader {
+        input,
+        objects,
+        position: 0,
+        depth: 0,
+    };
+    reader.skip_whitespace()?;
+    if reader.peek()?.is_none() {
+        return Ok(None);
+    }
+    reader.value().map(Some)
+}
+
+// The whole text must be a single value
+pub fn parse(text: &str, objects: ObjectType) -> Result<SExpression, String> {
+    let mut input = text.as_bytes();
+    let mut reader = Reader {
+        input: &mut input,
+        objects,
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace()?;
+    match reader.peek()? {
+        None => Ok(value),
+        Some(_) => Err(reader.error("Unexpected text after the JSON value")),
+    }
+}
+
+// Reads a byte at a time so a value can end in the middle of the input's
+// buffer without losing what comes after it.
+struct Reader<'a, R: BufRead> {
+    input: &'a mut R,
+    objects: ObjectType,
+    position: usize,
+    depth: usize,
+}
+
+impl<'a, R: BufRead> Reader<'a, R> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {} of JSON input", message, self.position)
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        let buffer = self
+            .input
+            .fill_buf()
+            .map_err(|error| format!("Can't read JSON input: {}", error))?;
+        Ok(buffer.first().copied())
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        match self.peek()? {
+            Some(byte) => {
+                self.input.consume(1);
+                self.position += 1;
+                Ok(byte)
+            }
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn expect(&mut self, wanted: u8) -> Result<(), String> {
+        match self.next()? {
+            byte if byte == wanted => Ok(()),
+            byte => Err(self.error(&format!(
+                "Expected '{}', got '{}'",
+                wanted as char, byte as char
+            ))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), String> {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek()? {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<SExpression, String> {
+        self.skip_whitespace()?;
+        match self.peek()? {
+            Some(b'{') => self.nested(Reader::object),
+            Some(b'[') => self.nested(Reader::array),
+            Some(b'"') => Ok(SExpression::Cell(Cell::Str(self.string()?))),
+            Some(b't') => self.literal("true", SExpression::Cell(Cell::Bool(true))),
+            Some(b'f') => self.literal("false", SExpression::Cell(Cell::Bool(false))),
+            Some(b'n') => self.literal("null", null()),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(byte) => Err(self.error(&format!("Unexpected character '{}'", byte as char))),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<SExpression, String>,
+    ) -> Result<SExpression, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("JSON nested too deeply"));
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn literal(&mut self, word: &str, value: SExpression) -> Result<SExpression, String> {
+        for wanted in word.bytes() {
+            if self.next()? != wanted {
+                return Err(self.error(&format!("Expected '{}'", word)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn digits(&mut self, text: &mut String) -> Result<(), String> {
+        let start = text.len();
+        while let Some(byte @ b'0'..=b'9') = self.peek()? {
+            text.push(byte as char);
+            self.next()?;
+        }
+        if text.len() == start {
+            return Err(self.error("Expected a digit"));
+        }
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<SExpression, String> {
+        let mut text = String::new();
+        if self.peek()? == Some(b'-') {
+            text.push(self.next()? as char);
+        }
+        // No leading zeros, so 0 is on its own
+        if self.peek()? == Some(b'0') {
+            text.push(self.next()? as char);
+        } else {
+            self.digits(&mut text)?;
+        }
+        let mut integer = true;
+        if self.peek()? == Some(b'.') {
+            text.push(self.next()? as char);
+            self.digits(&mut text)?;
+            integer = false;
+        }
+        if let Some(b'e') | Some(b'E') = self.peek()? {
+            text.push(self.next()? as char);
+            if let Some(b'+') | Some(b'-') = self.peek()? {
+                text.push(self.next()? as char);
+            }
+            self.digits(&mut text)?;
+            integer = false;
+        }
+        // Integers too big for i64 fall back to floats
+        match text.parse::<i64>() {
+            Ok(value) if integer => Ok(SExpression::Cell(Cell::Int(value))),
+            _ => match text.parse::<f64>() {
+                Ok(value) => Ok(SExpression::Cell(Cell::Flt(value))),
+                Err(_) => Err(self.error(&format!("Bad number {}", text))),
+            },
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = (self.next()? as char)
+                .to_digit(16)
+                .ok_or_else(|| self.error("Expected four hex digits after \\u"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    // Characters outside the basic plane are written as two \u escapes, a
+    // surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex_escape()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            self.expect(b'\\')?;
+            self.expect(b'u')?;
+            let low = self.hex_escape()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("Unpaired surrogate in \\u escape"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Unpaired surrogate in \\u escape"))
+    }
+
+    // The bytes are collected first so multi-byte characters come through whole
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.next()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        byte => {
+                            return Err(self.error(&format!("Unknown escape \\{}", byte as char)))
+                        }
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                }
+                byte if byte < 0x20 => {
+                    return Err(self.error("Control characters must be escaped in JSON strings"))
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    fn array(&mut self) -> Result<SExpression, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace()?;
+        if self.peek()? == Some(b']') {
+            self.next()?;
+        } else {
+            loop {
+                items.push(self.value()?);
+                self.skip_whitespace()?;
+                match self.next()? {
+                    b',' => continue,
+                    b']' => break,
+                    _ => return Err(self.error("Expected ',' or ']' in array")),
+                }
+            }
+        }
+        Ok(SExpression::Cell(Cell::Vector(Rc::new(RefCell::new(
+            items,
+        )))))
+    }
+
+    fn object(&mut self) -> Result<SExpression, String> {
+        self.expect(b'{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace()?;
+        if self.peek()? == Some(b'}') {
+            self.next()?;
+        } else {
+            loop {
+                self.skip_whitespace()?;
+                let key = SExpression::Cell(symbol_cell(&self.string()?));
+                self.skip_whitespace()?;
+                self.expect(b':')?;
+                entries.push((key, self.value()?));
+                self.skip_whitespace()?;
+                match self.next()? {
+                    b',' => continue,
+                    b'}' => break,
+                    _ => return Err(self.error("Expected ',' or '}' in object")),
+                }
+            }
+        }
+        match self.objects {
+            ObjectType::Alist => {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, value)| SExpression::List(List::make_from_sexps(vec![key, value])))
+                    .collect();
+                Ok(SExpression::List(List::make_from_sexps(entries)))
+            }
+            ObjectType::HashTable => {
+                let mut table = HashTable::new(Equivalence::Equal);
+                for (key, value) in entries {
+                    table.set(key, value)?;
+                }
+                Ok(SExpression::Cell(Cell::HashTable(Rc::new(RefCell::new(
+                    table,
+                )))))
+            }
+        }
+    }
+} // impl Reader
+
+// Compact JSON when indent is 0; otherwise one item per line, nested items
+// indented by that many more spaces.
+pub fn write(value: &SExpression, indent: usize) -> Result<String, String> {
+    let mut writer = Writer {
+        indent,
+        text: String::new(),
+    };
+    writer.value(value, 0)?;
+    Ok(writer.text)
+}
+
+struct Writer {
+    indent: usize,
+    text: String,
+}
+
+impl Writer {
+    fn value(&mut self, value: &SExpression, level: usize) -> Result<(), String> {
+        match value {
+            SExpression::Null => self.text.push_str("null"),
+            SExpression::List(_) => {
+                let entries = alist_entries(value).map_err(|_| {
+                    format!(
+                        "Only association lists can be written as JSON objects, got {}; use a vector for an array",
+                        value.print()
+                    )
+                })?;
+                self.object(&entries, level)?;
+            }
+            SExpression::Cell(cell) => match cell {
+                Cell::Bool(truth) => self.text.push_str(if *truth { "true" } else { "false" }),
+                Cell::Int(number) => self.text.push_str(&number.to_string()),
+                Cell::Flt(number) if number.is_finite() => self.text.push_str(&number.to_string()),
+                Cell::Flt(number) => return Err(format!("Can't write {} as JSON", number)),
+                Cell::Str(string) => self.string(string),
+                Cell::Vector(items) => self.array(&items.borrow(), level)?,
+                Cell::HashTable(table) => self.object(&table.borrow().entries(), level)?,
+                Cell::JsonNull => self.text.push_str("null"),
+                _ => match key_name(value) {
+                    Some(name) => self.string(&name),
+                    None => return Err(format!("Can't write {} as JSON", value.print())),
+                },
+            },
+        }
+        Ok(())
+    }
+
+    // Starts a new line at the given nesting level when pretty printing
+    fn line(&mut self, level: usize) {
+        if self.indent > 0 {
+            self.text.push('\n');
+            self.text.push_str(&" ".repeat(self.indent * level));
+        }
+    }
+
+    fn array(&mut self, items: &[SExpression], level: usize) -> Result<(), String> {
+        self.text.push('[');
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                self.text.push(',');
+            }
+            self.line(level + 1);
+            self.value(item, level + 1)?;
+        }
+        if !items.is_empty() {
+            self.line(level);
+        }
+        self.text.push(']');
+        Ok(())
+    }
+
+    fn object(
+        &mut self,
+        entries: &[(SExpression, SExpression)],
+        level: usize,
+    ) -> Result<(), String> {
+        self.text.push('{');
+        for (index, (key, value)) in entries.iter().enumerate() {
+            if index > 0 {
+                self.text.push(',');
+            }
+            self.line(level + 1);
+            match key_name(key) {
+                Some(name) => self.string(&name),
+                None => {
+                    return Err(format!(
+                        "JSON object keys must be strings or symbols, got {}",
+                        key.print()
+                    ))
+                }
+            }
+            self.text.push_str(if self.indent > 0 { ": " } else { ":" });
+            self.value(value, level + 1)?;
+        }
+        if !entries.is_empty() {
+            self.line(level);
+        }
+        self.text.push('}');
+        Ok(())
+    }
+
+    fn string(&mut self, string: &str) {
+        self.text.push('"');
+        for c in string.chars() {
+            match c {
+                '"' => self.text.push_str("\\\""),
+                '\\' => self.text.push_str("\\\\"),
+                '\n' => self.text.push_str("\\n"),
+                '\r' => self.text.push_str("\\r"),
+                '\t' => self.text.push_str("\\t"),
+                c if (c as u32) < 0x20 => self.text.push_str(&format!("\\u{:04x}", c as u32)),
+                c => self.text.push(c),
+            }
+        }
+        self.text.push('"');
+    }
+} // impl Writer
+
+// Strings, symbols, and names like list that the parser reads as built-ins
+fn key_name(key: &SExpression) -> Option<String> {
+    match key {
+        SExpression::Cell(Cell::Str(name)) | SExpression::Cell(Cell::Symbol(_, name)) => {
+            Some(name.clone())
+        }
+        SExpression::Cell(
+            cell @ Cell::Op(_)
+            | cell @ Cell::Logical(_)
+            | cell @ Cell::Special(_)
+            | cell @ Cell::Core(_),
+        ) => Some(cell.print()),
+        _ => None,
+    }
+}
@@ -0,0 +1,240 @@
+use crate::bytecode::Chunk;
+use crate::context::Context;
+use crate::exceptions::expand_guard;
+use crate::list::List;
+use crate::load::expand_includes;
+use crate::primitives::Cell;
+use crate::primitives::SpecialForm;
+use crate::symbolic_expression::SExpression;
+use crate::symbols::symbol_name;
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+// Lexical addressing: before a lambda is first used its body is walked once and
+// every reference to one of its own variables, or to a variable of a lambda
+// it's nested in, is rewritten into a Cell::Local holding the variable's
+// address: how many frames out it lives (depth) and its position in that
+// frame (slot). Evaluating the reference is then an index into a vector
+// rather than a hash lookup by name.
+//
+// A frame's slots are the lambda's parameters followed by every name the body
+// defines, in the order they're first seen. Names that aren't found in any
+// enclosing lambda are left as symbols; they're globals (which may not be
+// defined yet) and get looked up by name when they're evaluated.
+//
+// Addresses like these only hold still because scoping is lexical: a call's
+// frame sits inside the frame the lambda was made in, not the caller's. The
+// interpreter used to look names up through the callers instead, so a
+// function could see the variables of whatever called it; it can't now.
+
+// A lambda whose body has been analyzed. Evaluating one captures the current
+// environment to make a procedure.
+pub struct LambdaTemplate {
+    pub name: Option<i32>,
+    pub param_count: usize,
+    // Symbol number of each slot in the frame, starting with the parameters
+    pub slots: Rc<Vec<i32>>,
+    pub body: SExpression,
+    // The body compiled to bytecode, the first time the VM calls the lambda
+    pub code: OnceCell<Rc<Chunk>>,
+}
+
+impl LambdaTemplate {
+    pub fn print(&self) -> String {
+        match self.name {
+            Some(number) => format!("Lambda: {}", symbol_name(number)),
+            None => String::from("Lambda: "),
+        }
+    }
+}
+
+// The slots of each lambda enclosing the code being analyzed, innermost last
+type Scopes = Vec<Vec<i32>>;
+
+// Analyze a lambda that isn't nested in another one being analyzed, like a
+// top-level (define (f x) ...) or a lambda built at run time.
+pub fn analyze_lambda(
+    name: Option<i32>,
+    params: &List,
+    body: SExpression,
+    context: &Context,
+) -> Result<Rc<LambdaTemplate>, String> {
+    let body = expand_includes(context, &body)?;
+    let mut scopes: Scopes = Vec::new();
+    analyze_nested_lambda(name, params, body, &mut scopes)
+}
+
+// (lambda (x y) a b c) has the same body as (lambda (x y) (begin a b c))
+pub fn lambda_body(body_expressions: List) -> SExpression {
+    if !body_expressions.is_empty() && body_expressions.rest().is_empty() {
+        *body_expressions.first()
+    } else {
+        let mut expressions = vec![SExpression::Cell(Cell::Special(SpecialForm::Begin))];
+        expressions.extend(body_expressions.to_vec());
+        SExpression::List(List::make_from_sexps(expressions))
+    }
+}
+
+fn analyze_nested_lambda(
+    name: Option<i32>,
+    params: &List,
+    body: SExpression,
+    scopes: &mut Scopes,
+) -> Result<Rc<LambdaTemplate>, String> {
+    let mut slots = Vec::new();
+    for param in params.to_vec() {
+        match param {
+            SExpression::Cell(Cell::Symbol(number, _)) => slots.push(number),
+            _ => {
+                return Err(format!(
+                    "A parameter name must be a symbol but you used {}",
+                    param.print()
+                ))
+            }
+        }
+    }
+    let param_count = slots.len();
+    collect_defines(&body, &mut slots);
+
+    scopes.push(slots);
+    let analyzed_body = rewrite(&body, scopes);
+    let slots = scopes.pop().unwrap_or_default();
+
+    Ok(Rc::new(LambdaTemplate {
+        name,
+        param_count,
+        slots: Rc::new(slots),
+        body: analyzed_body?,
+        code: OnceCell::new(),
+    }))
+}
+
+// The symbol a define form gives a value to, for (define x ...) or (define (x ...) ...)
+fn defined_symbol(target: &SExpression) -> Option<i32> {
+    match target {
+        SExpression::Cell(Cell::Symbol(number, _)) => Some(*number),
+        SExpression::List(signature) => match *signature.first() {
+            SExpression::Cell(Cell::Symbol(number, _)) => Some(number),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Find every name defined in a lambda body, but not inside lambdas nested in
+// it since those will have their own frames.
+fn collect_defines(exp: &SExpression, slots: &mut Vec<i32>) {
+    if let SExpression::List(list) = exp {
+        let items = list.to_vec();
+        match items.first() {
+            // A guard's body and clauses end up in lambdas of their own
+            Some(SExpression::Cell(Cell::Special(SpecialForm::Quote)))
+            | Some(SExpression::Cell(Cell::Special(SpecialForm::Lambda)))
+            | Some(SExpression::Cell(Cell::Special(SpecialForm::Guard))) => return,
+            Some(SExpression::Cell(Cell::Special(SpecialForm::Define))) => {
+                if let Some(target) = items.get(1) {
+                    if let Some(number) = defined_symbol(target) {
+                        if !slots.contains(&number) {
+                            slots.push(number);
+                        }
+                    }
+                    // The body of (define (f ...) body) is a lambda body
+                    if let SExpression::List(_) = target {
+                        return;
+                    }
+                }
+            }
+            _ => (),
+        }
+        for item in &items {
+            collect_defines(item, slots);
+        }
+    }
+}
+
+fn resolve(number: i32, scopes: &[Vec<i32>]) -> Option<(usize, usize)> {
+    for (depth, scope) in scopes.iter().rev().enumerate() {
+        if let Some(slot) = scope.iter().position(|s| *s == number) {
+            return Some((depth, slot));
+        }
+    }
+    None
+}
+
+fn rewrite(exp: &SExpression, scopes: &mut Scopes) -> Result<SExpression, String> {
+    match exp {
+        SExpression::Cell(Cell::Symbol(number, _)) => match resolve(*number, scopes) {
+            Some((depth, slot)) => Ok(SExpression::Cell(Cell::Local(depth, slot, *number))),
+            None => Ok(exp.clone()),
+        },
+        SExpression::List(list) => {
+            let items = list.to_vec();
+            let rewritten = match items.first() {
+                Some(SExpression::Cell(Cell::Special(SpecialForm::Quote))) => {
+                    return Ok(exp.clone())
+                }
+                Some(SExpression::Cell(Cell::Special(SpecialForm::Lambda))) => {
+                    return rewrite_lambda(list, scopes)
+                }
+                Some(SExpression::Cell(Cell::Special(SpecialForm::Guard))) => {
+                    return rewrite(&expand_guard(&list.rest())?, scopes)
+                }
+                Some(SExpression::Cell(Cell::Special(SpecialForm::Define))) => {
+                    rewrite_define(&items, scopes)?
+                }
+                _ => items
+                    .iter()
+                    .map(|item| rewrite(item, scopes))
+                    .collect::<Result<Vec<SExpression>, String>>()?,
+            };
+            Ok(SExpression::List(List::make_from_sexps(rewritten)))
+        }
+        _ => Ok(exp.clone()),
+    }
+}
+
+// (lambda (params) body ...) becomes the analyzed template
+fn rewrite_lambda(lambda_form: &List, scopes: &mut Scopes) -> Result<SExpression, String> {
+    let params = match *lambda_form.rest().first() {
+        SExpression::List(params) => params,
+        ref other => {
+            return Err(format!(
+                "lambda requires a list of parameters, got {}",
+                other.print()
+            ))
+        }
+    };
+    let body = lambda_body(lambda_form.rest().rest());
+    let template = analyze_nested_lambda(None, &params, body, scopes)?;
+    Ok(SExpression::Cell(Cell::Template(template)))
+}
+
+// (define x value) addresses x in the current frame; (define (f params) body)
+// becomes (define f template)
+fn rewrite_define(items: &[SExpression], scopes: &mut Scopes) -> Result<Vec<SExpression>, String> {
+    let mut rewritten = vec![items[0].clone()];
+    match items.get(1) {
+        Some(SExpression::List(signature)) => {
+            let name = match defined_symbol(&items[1]) {
+                Some(number) => number,
+                // Leave it for define to report the bad function name
+                None => return Ok(items.to_vec()),
+            };
+            let target = *signature.first();
+            let body = items.get(2).cloned().unwrap_or(SExpression::Null);
+            let template = analyze_nested_lambda(Some(name), &signature.rest(), body, scopes)?;
+            rewritten.push(rewrite(&target, scopes)?);
+            rewritten.push(SExpression::Cell(Cell::Template(template)));
+            // define complains about anything after the body
+            for item in items.iter().skip(3) {
+                rewritten.push(rewrite(item, scopes)?);
+            }
+        }
+        _ => {
+            for item in items.iter().skip(1) {
+                rewritten.push(rewrite(item, scopes)?);
+            }
+        }
+    }
+    Ok(rewritten)
+}
@@ -0,0 +1,39 @@
+// A Scheme interpreter that can be embedded in Rust programs. The Interpreter
+// type in embed.rs is the way in; values going in and out are SExpressions.
+mod bytecode;
+mod compiler;
+mod context;
+mod convert;
+mod de;
+mod embed;
+mod equality;
+mod error;
+mod exceptions;
+mod hash_table;
+mod interpreter;
+mod json;
+mod lexical;
+mod library;
+pub mod list;
+mod load;
+mod native;
+mod parser;
+mod port;
+pub mod primitives;
+mod reader;
+mod ser;
+pub mod symbolic_expression;
+mod symbols;
+mod vm;
+
+pub use crate::convert::{AlistBuilder, AlistReader, FromArgs, FromScheme, ToScheme};
+pub use crate::de::{from_sexpression, from_str};
+pub use crate::embed::{disassemble, parse, Backend, Interpreter};
+pub use crate::error::Error;
+pub use crate::interpreter::Redefinition;
+pub use crate::list::List;
+pub use crate::native::{Arity, NativeFunction};
+pub use crate::parser::{scan, Lexeme};
+pub use crate::primitives::Cell;
+pub use crate::ser::{to_sexpression, to_string};
+pub use crate::symbolic_expression::SExpression;
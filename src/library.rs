@@ -0,0 +1,343 @@
+use crate::interpreter::Environment;
+use crate::list::List;
+use crate::load;
+use crate::parser::symbol_cell;
+use crate::primitives::{map_cell_from_string, Cell, CoreFunc, LogicalOperator};
+use crate::symbolic_expression::SExpression;
+use crate::symbols::intern;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Libraries, R7RS style:
+//
+//     (define-library (geometry shapes)
+//       (export area (rename make-square square))
+//       (import (scheme base))
+//       (begin (define (area s) (* s s)) (define (make-square n) n)))
+//
+//     (import (prefix (geometry shapes) shapes:))
+//
+// Each library's definitions live in an environment of its own, so names in
+// different libraries don't collide. Importing copies the values of the
+// exported names into the importing environment; a library's procedures
+// still run in the library's environment.
+//
+// The built-ins are always there under their own names, because the parser
+// turns them straight into cells. The (scheme ...) libraries list them by the
+// usual R7RS grouping so they can be imported under other names with
+// 'prefix' and 'rename'. Since 'only' and 'except' couldn't hide any of them,
+// using those on a built-in library is an error rather than doing nothing.
+//
+// The libraries defined so far belong to the interpreter, in its Context.
+
+// The exported names and their values, in the order they're exported
+pub type Bindings = Vec<(String, SExpression)>;
+
+fn library_name(name: &SExpression) -> Result<String, String> {
+    match name {
+        SExpression::List(parts) if !parts.is_empty() => Ok(name.print()),
+        _ => Err(format!("A library name is a list, not {}", name.print())),
+    }
+}
+
+fn symbol_text(value: &SExpression) -> Option<String> {
+    match value {
+        SExpression::Cell(Cell::Symbol(_, name)) => Some(name.clone()),
+        // Built-in names are cells of their own
+        SExpression::Cell(
+            cell @ (Cell::Core(_) | Cell::Op(_) | Cell::Logical(_) | Cell::Special(_)),
+        ) => Some(cell.print()),
+        _ => None,
+    }
+}
+
+fn name_arg(value: &SExpression, context: &str) -> Result<String, String> {
+    symbol_text(value).ok_or_else(|| format!("{} needs names, got {}", context, value.print()))
+}
+
+// (define-library name declaration ...)
+pub fn define_library(env: &Environment, args: List) -> Result<SExpression, String> {
+    let name = *args.first();
+    let key = library_name(&name)?;
+    let mut env = env.new_top_level();
+    // (name as defined in the library, name it's exported as)
+    let mut exports = Vec::new();
+
+    for declaration in args.rest().to_vec() {
+        let parts = match &declaration {
+            SExpression::List(parts) if !parts.is_empty() => parts.clone(),
+            _ => return Err(format!("Bad library declaration {}", declaration.print())),
+        };
+        let keyword = symbol_text(&parts.first()).unwrap_or_default();
+        match keyword.as_str() {
+            "export" => {
+                for spec in parts.rest().to_vec() {
+                    exports.push(export_spec(&spec)?);
+                }
+            }
+            "import" => {
+                import(&mut env, parts.rest())?;
+            }
+            // include reads its files relative to the library's file
+            "begin" | "include" => {
+                env.evaluate(declaration)?;
+            }
+            _ => return Err(format!("Unknown library declaration {}", keyword)),
+        }
+    }
+
+    let mut bindings = Vec::new();
+    for (internal, external) in exports {
+        let value = match symbol_cell(&internal) {
+            Cell::Symbol(number, _) => env
+                .get_definition_by_symbol(number)
+                .map_err(|_| format!("{} exports {} but doesn't define it", key, internal))?,
+            builtin => SExpression::Cell(builtin),
+        };
+        bindings.push((external, value));
+    }
+    env.context()
+        .libraries
+        .borrow_mut()
+        .insert(key, Rc::new(bindings));
+    Ok(name)
+}
+
+// name, or (rename internal external)
+fn export_spec(spec: &SExpression) -> Result<(String, String), String> {
+    if let SExpression::List(parts) = spec {
+        let parts = parts.to_vec();
+        if parts.len() == 3 && symbol_text(&parts[0]).as_deref() == Some("rename") {
+            let internal = name_arg(&parts[1], "rename")?;
+            let external = name_arg(&parts[2], "rename")?;
+            return Ok((internal, external));
+        }
+        return Err(format!("Bad export {}", spec.print()));
+    }
+    let name = name_arg(spec, "export")?;
+    Ok((name.clone(), name))
+}
+
+// (import set ...) defines the names from each import set in env
+pub fn import(env: &mut Environment, sets: List) -> Result<SExpression, String> {
+    for set in sets.to_vec() {
+        for (name, value) in import_set(env, &set)? {
+            let builtin = symbol_cell(&name);
+            match (&builtin, &value) {
+                // A built-in under its own name is already there
+                (Cell::Symbol(..), _) => {}
+                (_, SExpression::Cell(cell)) if cell.print() == name => continue,
+                _ => return Err(format!("Can't import {}: it's a built-in name", name)),
+            }
+            match &value {
+                SExpression::Cell(Cell::Special(_))
+                | SExpression::Cell(Cell::Logical(LogicalOperator::And))
+                | SExpression::Cell(Cell::Logical(LogicalOperator::Or)) => {
+                    return Err(format!("Can't rename syntax {}", value.print()))
+                }
+                _ => env.define_or_replace(intern(&name), value),
+            }
+        }
+    }
+    Ok(SExpression::Null)
+}
+
+// A library name, or only, except, prefix or rename applied to an import set
+fn import_set(env: &Environment, set: &SExpression) -> Result<Bindings, String> {
+    let parts = match set {
+        SExpression::List(parts) => parts.to_vec(),
+        _ => return Err(format!("Bad import set {}", set.print())),
+    };
+    let modifier = parts.first().and_then(symbol_text).unwrap_or_default();
+    let modified = matches!(modifier.as_str(), "only" | "except" | "prefix" | "rename")
+        && matches!(parts.get(1), Some(SExpression::List(_)));
+    if !modified {
+        return find_library(env, set);
+    }
+
+    if matches!(modifier.as_str(), "only" | "except") {
+        let library = innermost_library(&parts[1]);
+        let key = library_name(&library)?;
+        if defined_library(env, &key).is_none() && builtin_library(&key).is_some() {
+            return Err(format!(
+                "{} can't hide built-ins, and {} is always there; import it whole, or with prefix or rename",
+                modifier, key
+            ));
+        }
+    }
+    let bindings = import_set(env, &parts[1])?;
+    let names = parts[2..]
+        .iter()
+        .map(|name| name_arg(name, &modifier))
+        .collect::<Result<Vec<String>, String>>();
+    match modifier.as_str() {
+        "only" => {
+            let names = names?;
+            for name in &names {
+                if !bindings.iter().any(|(exported, _)| exported == name) {
+                    return Err(format!("{} doesn't export {}", parts[1].print(), name));
+                }
+            }
+            Ok(bindings
+                .into_iter()
+                .filter(|(name, _)| names.contains(name))
+                .collect())
+        }
+        "except" => {
+            let names = names?;
+            Ok(bindings
+                .into_iter()
+                .filter(|(name, _)| !names.contains(name))
+                .collect())
+        }
+        "prefix" => {
+            let prefix = match names?.as_slice() {
+                [prefix] => prefix.clone(),
+                _ => return Err(format!("prefix takes one prefix: {}", set.print())),
+            };
+            Ok(bindings
+                .into_iter()
+                .map(|(name, value)| (format!("{}{}", prefix, name), value))
+                .collect())
+        }
+        _ => {
+            let mut renames = HashMap::new();
+            for pair in &parts[2..] {
+                match pair {
+                    SExpression::List(pair) if pair.to_vec().len() == 2 => {
+                        let pair = pair.to_vec();
+                        renames
+                            .insert(name_arg(&pair[0], "rename")?, name_arg(&pair[1], "rename")?);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "rename needs (old new) pairs, got {}",
+                            pair.print()
+                        ))
+                    }
+                }
+            }
+            Ok(bindings
+                .into_iter()
+                .map(|(name, value)| match renames.get(&name) {
+                    Some(new_name) => (new_name.clone(), value),
+                    None => (name, value),
+                })
+                .collect())
+        }
+    }
+}
+
+// The library an import set starts from, inside any only, except, prefix or
+// rename
+fn innermost_library(set: &SExpression) -> SExpression {
+    if let SExpression::List(parts) = set {
+        let parts = parts.to_vec();
+        let modifier = parts.first().and_then(symbol_text).unwrap_or_default();
+        if matches!(modifier.as_str(), "only" | "except" | "prefix" | "rename") {
+            if let Some(inner @ SExpression::List(_)) = parts.get(1) {
+                return innermost_library(inner);
+            }
+        }
+    }
+    set.clone()
+}
+
+// A library that's been defined, a built-in one, or one in a file: (a b c)
+// is looked for as a/b/c.sld, then a/b/c.scm, the way load looks for files.
+fn find_library(env: &Environment, name: &SExpression) -> Result<Bindings, String> {
+    let key = library_name(name)?;
+    if let Some(bindings) = defined_library(env, &key) {
+        return Ok(bindings);
+    }
+    if let Some(bindings) = builtin_library(&key) {
+        return Ok(bindings);
+    }
+
+    let parts = match name {
+        SExpression::List(parts) => parts.to_vec(),
+        _ => Vec::new(),
+    };
+    let stem = parts
+        .iter()
+        .map(|part| part.print())
+        .collect::<Vec<String>>()
+        .join("/");
+    let context = env.context();
+    let path = load::resolve(context, &format!("{}.sld", stem))
+        .or_else(|_| load::resolve(context, &format!("{}.scm", stem)))
+        .map_err(|_| format!("Can't find library {}", key))?;
+    load::with_loading(context, &path, || {
+        let forms = load::read_forms(&path)?;
+        env.new_top_level().eval_each(forms)
+    })??;
+    defined_library(env, &key)
+        .ok_or_else(|| format!("{} doesn't define library {}", path.display(), key))
+}
+
+fn defined_library(env: &Environment, key: &str) -> Option<Bindings> {
+    env.context()
+        .libraries
+        .borrow()
+        .get(key)
+        .map(|bindings| bindings.as_ref().clone())
+}
+
+// Which built-in library each built-in belongs to
+fn builtin_library_of(cell: &Cell) -> &'static str {
+    use CoreFunc::*;
+    match cell {
+        Cell::Core(Display | Write) => "(scheme write)",
+        Cell::Core(Read) => "(scheme read)",
+        Cell::Core(
+            OpenInputFile | OpenOutputFile | OpenBinaryInputFile | OpenBinaryOutputFile
+            | CallWithInputFile | CallWithOutputFile | WithInputFromFile | WithOutputToFile,
+        ) => "(scheme file)",
+        Cell::Core(Eval | SchemeReportEnvironment) => "(scheme eval)",
+        Cell::Core(InteractionEnvironment) => "(scheme repl)",
+        Cell::Core(Load) => "(scheme load)",
+        Cell::Core(
+            IsEnvironment | MakeEnvironment | CurrentEnvironment | IsEnvironmentBound
+            | EnvironmentRef | EnvironmentDefine | EnvironmentBindings,
+        ) => "(rs-lisp environments)",
+        Cell::Core(JsonRead | JsonWrite | StringToJson | JsonToString | IsJsonNull | JsonNull) => {
+            "(rs-lisp json)"
+        }
+        Cell::Core(
+            IsHashTable
+            | MakeHashTable
+            | AlistToHashTable
+            | HashTableSet
+            | HashTableRef
+            | HashTableRefDefault
+            | HashTableUpdate
+            | HashTableUpdateDefault
+            | HashTableDelete
+            | HashTableContains
+            | HashTableSize
+            | HashTableKeys
+            | HashTableValues
+            | HashTableToAlist
+            | HashTableWalk
+            | HashTableFold
+            | HashTableClear
+            | HashTableCopy,
+        ) => "(srfi 69)",
+        Cell::Core(Exit | CommandLine) => "(scheme process-context)",
+        Cell::Core(WithExceptionCatcher) => "(rs-lisp exceptions)",
+        _ => "(scheme base)",
+    }
+}
+
+fn builtin_library(key: &str) -> Option<Bindings> {
+    let mut bindings: Bindings = map_cell_from_string()
+        .into_iter()
+        .filter(|(_, cell)| builtin_library_of(cell) == key)
+        .map(|(name, cell)| (name, SExpression::Cell(cell)))
+        .collect();
+    if bindings.is_empty() {
+        return None;
+    }
+    bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(bindings)
+}
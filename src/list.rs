@@ -1,6 +1,7 @@
 use crate::interpreter::Environment;
 use crate::primitives::Cell;
 use crate::symbolic_expression::SExpression;
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Pair {
@@ -8,14 +9,16 @@ pub struct Pair {
     cdr: Link,
 }
 
+// Pairs are shared rather than copied when a list is cloned, which also gives
+// each list an identity for eq?
 #[derive(Clone)]
 pub enum Link {
-    Data(Box<Pair>),
+    Data(Rc<Pair>),
     Nil,
 }
 
 pub fn cons(object: SExpression, list: Link) -> Link {
-    Link::Data(Box::<Pair>::new(Pair {
+    Link::Data(Rc::new(Pair {
         car: Box::<SExpression>::new(object),
         cdr: list,
     }))
@@ -34,6 +37,24 @@ impl List {
         }
     }
 
+    // True if both lists are the very same list and not just copies with the
+    // same contents. There is only one empty list.
+    pub fn same_object(&self, other: &List) -> bool {
+        match (&self.head, &other.head) {
+            (Link::Nil, Link::Nil) => true,
+            (Link::Data(l), Link::Data(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+
+    // A number unique to this list object, for hashing by identity
+    pub fn identity(&self) -> usize {
+        match &self.head {
+            Link::Nil => 0,
+            Link::Data(pair) => Rc::as_ptr(pair) as usize,
+        }
+    }
+
     pub fn first(&self) -> Box<SExpression> {
         match &self.head {
             Link::Nil => {
@@ -63,7 +84,7 @@ impl List {
         for index in (0..objects.len()).rev() {
             head = cons(SExpression::Cell(objects[index].clone()), head);
         }
-        List { head: head }
+        List { head }
     }
 
     // If the second argument is a list wemove the head of the list to the left
@@ -89,17 +110,27 @@ impl List {
         for index in (0..exps.len()).rev() {
             head = cons(exps[index].clone(), head);
         }
-        List { head: head }
+        List { head }
+    }
+
+    pub fn to_vec(&self) -> Vec<SExpression> {
+        let mut items = Vec::new();
+        let mut link = &self.head;
+        while let Link::Data(pair) = link {
+            items.push((*pair.car).clone());
+            link = &pair.cdr;
+        }
+        items
     }
 
     pub fn print(&self) -> String {
-        match &self.head {
-            Link::Nil => String::from("()"),
-            Link::Data(_) => {
-                let first_print: String = self.first().print();
-                first_print + " " + &self.rest().print()
-            }
+        let mut printed_items = Vec::new();
+        let mut link = &self.head;
+        while let Link::Data(pair) = link {
+            printed_items.push(pair.car.print());
+            link = &pair.cdr;
         }
+        format!("({})", printed_items.join(" "))
     }
 
     pub fn evaluate(&self, envr: &mut Environment) -> Result<SExpression, String> {
@@ -111,13 +142,27 @@ impl List {
             SExpression::Cell(cell) => match cell {
                 Cell::Special(form) => envr.apply_special_form(form, self.rest()),
                 Cell::Core(func) => envr.apply_core_func(func, self.rest()),
-                // If it's a symbol at the head of the list, it must be a function call
-                Cell::Symbol(number, name) => envr.apply_function(number, name, self.rest()),
                 Cell::Op(operator) => envr.apply_operator(operator, self.rest()),
                 Cell::Logical(operator) => envr.apply_logical_operator(operator, self.rest()),
+                // If it's a variable or a lambda at the head of the list, it must be a function call
+                Cell::Symbol(_, _) | Cell::Local(_, _, _) | Cell::Template(_) => {
+                    envr.apply_function(SExpression::Cell(cell), self.rest())
+                }
                 _ => Err("Evaluation on this cell type  not supported".to_string()),
             },
-            SExpression::List(sub_list) => sub_list.evaluate(envr),
+            // A list at the head of a list is called if it evaluates to a
+            // procedure, as in ((lambda (x) x) 5). Otherwise the list is a
+            // sequence of expressions and this gives the value of the first one.
+            SExpression::List(sub_list) => {
+                let head_value = sub_list.evaluate(envr)?;
+                match head_value {
+                    SExpression::Cell(Cell::Lambda(_, _)) => {
+                        let values_from_args = envr.eval_each(self.rest())?;
+                        envr.apply_procedure(head_value, values_from_args)
+                    }
+                    _ => Ok(head_value),
+                }
+            }
             SExpression::Null => Ok(SExpression::Null),
         }
     }
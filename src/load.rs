@@ -0,0 +1,170 @@
+use crate::context::Context;
+use crate::embed::{location, read_program};
+use crate::interpreter::Environment;
+use crate::list::List;
+use crate::parser::{FormReader, ParseError};
+use crate::primitives::{Cell, SpecialForm};
+use crate::symbolic_expression::SExpression;
+use std::path::{Path, PathBuf};
+
+// Finding and reading the files 'load' and 'include' pull in. A relative name
+// is looked for next to the file doing the loading (or in the current
+// directory if there isn't one), then in each directory of the search path.
+// The search path and the files being loaded belong to the interpreter, in
+// its Context.
+
+// The environment variable holding extra directories to search, separated
+// like PATH
+pub const SEARCH_PATH_VARIABLE: &str = "RS_LISP_PATH";
+
+pub fn add_search_directory(context: &Context, directory: &Path) {
+    context
+        .search_path
+        .borrow_mut()
+        .push(directory.to_path_buf());
+}
+
+pub fn resolve(context: &Context, name: &str) -> Result<PathBuf, String> {
+    let name = Path::new(name);
+    if name.is_absolute() {
+        return Ok(name.to_path_buf());
+    }
+    let here = context
+        .loading
+        .borrow()
+        .last()
+        .and_then(|file| file.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let mut candidates = vec![here.join(name)];
+    candidates.extend(
+        context
+            .search_path
+            .borrow()
+            .iter()
+            .map(|directory| directory.join(name)),
+    );
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| format!("Can't find {} to load", name.display()))
+}
+
+// Pops the file off the loading stack even if loading it failed
+struct Finished<'a>(&'a Context);
+
+impl Drop for Finished<'_> {
+    fn drop(&mut self) {
+        self.0.loading.borrow_mut().pop();
+    }
+}
+
+// Run body while path is the file being loaded. A file that's already being
+// loaded further out would load itself forever, so that's an error.
+pub fn with_loading<T>(
+    context: &Context,
+    path: &Path,
+    body: impl FnOnce() -> T,
+) -> Result<T, String> {
+    let path = path
+        .canonicalize()
+        .map_err(|error| format!("Can't read {}: {}", path.display(), error))?;
+    if context.loading.borrow().contains(&path) {
+        return Err(format!("Circular load of {}", path.display()));
+    }
+    context.loading.borrow_mut().push(path);
+    let _finished = Finished(context);
+    Ok(body())
+}
+
+// All the forms in a file, for include, which needs them before any of them
+// runs
+pub fn read_forms(path: &Path) -> Result<List, String> {
+    let program = read_program(path).map_err(|error| error.to_string())?;
+    let source = path.display().to_string();
+    let mut forms = Vec::new();
+    for form in FormReader::new(&program) {
+        match form {
+            Ok((form, _)) => forms.push(form),
+            Err((ParseError::Reason(reason), position)) => {
+                return Err(format!("{}: {}", location(Some(&source), position), reason))
+            }
+        }
+    }
+    Ok(List::make_from_sexps(forms))
+}
+
+// Run a file a form at a time in env, giving the last form's value. An error
+// says where the form it came from starts.
+pub fn eval_file(path: &Path, env: &mut Environment) -> Result<SExpression, String> {
+    let program = read_program(path).map_err(|error| error.to_string())?;
+    let source = path.display().to_string();
+    let mut result = SExpression::Null;
+    for form in FormReader::new(&program) {
+        result = match form {
+            Ok((form, position)) => env
+                .evaluate(form)
+                .map_err(|error| format!("{}: {}", location(Some(&source), position), error))?,
+            Err((ParseError::Reason(reason), position)) => {
+                return Err(format!("{}: {}", location(Some(&source), position), reason))
+            }
+        };
+    }
+    Ok(result)
+}
+
+// The file an (include "name") form names
+pub fn include_path(context: &Context, name: &SExpression) -> Result<PathBuf, String> {
+    match name {
+        SExpression::Cell(Cell::Str(name)) => resolve(context, name),
+        _ => Err(format!("include needs file names, got {}", name.print())),
+    }
+}
+
+// Lambda bodies have their includes replaced by (begin forms...) when the
+// lambda is analyzed, while the file defining it is still being loaded, so
+// the names are relative to that file and the included definitions get slots
+// like any others. Quoted data is left alone.
+pub fn expand_includes(context: &Context, exp: &SExpression) -> Result<SExpression, String> {
+    if !mentions_include(exp) {
+        return Ok(exp.clone());
+    }
+    let items = match exp {
+        SExpression::List(list) => list.to_vec(),
+        _ => return Ok(exp.clone()),
+    };
+    let expanded = match items.first() {
+        Some(SExpression::Cell(Cell::Special(SpecialForm::Include))) => {
+            let mut forms = vec![SExpression::Cell(Cell::Special(SpecialForm::Begin))];
+            for name in &items[1..] {
+                let path = include_path(context, name)?;
+                let included = with_loading(context, &path, || {
+                    let forms = read_forms(&path)?;
+                    forms
+                        .to_vec()
+                        .iter()
+                        .map(|form| expand_includes(context, form))
+                        .collect::<Result<Vec<_>, String>>()
+                })?;
+                forms.extend(included?);
+            }
+            forms
+        }
+        _ => items
+            .iter()
+            .map(|item| expand_includes(context, item))
+            .collect::<Result<Vec<_>, String>>()?,
+    };
+    Ok(SExpression::List(List::make_from_sexps(expanded)))
+}
+
+fn mentions_include(exp: &SExpression) -> bool {
+    let items = match exp {
+        SExpression::List(list) => list.to_vec(),
+        _ => return false,
+    };
+    match items.first() {
+        Some(SExpression::Cell(Cell::Special(SpecialForm::Include))) => true,
+        Some(SExpression::Cell(Cell::Special(SpecialForm::Quote))) => false,
+        _ => items.iter().any(mentions_include),
+    }
+}
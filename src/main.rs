@@ -1,185 +1,163 @@
-mod interpreter;
-mod list;
-mod parser;
-mod primitives;
-mod symbolic_expression;
-use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use cli::Program;
+use config::ReplConfig;
+use rs_lisp::{Error, Interpreter, SExpression};
+use std::env;
 use std::fs;
-
-use crate::list::cons;
-use crate::list::Link;
-use crate::list::List;
-use crate::primitives::Cell;
-
-use crate::primitives::NumericOperator;
-use crate::symbolic_expression::SExpression;
-
-// Put this in the REPL loop
-fn interpret(program: String, environment: &mut interpreter::Environment) -> String {
-    let p = parser::Parser::new();
-    match p.parse_tokens(&parser::tokenize(program)) {
-        Ok((valid_ast, _)) => {
-            // Parser seemed to work, so attempt to interpret the AST
-            let r = environment.evaluate(valid_ast);
-            // Check for interpreter errors
-            match r {
-                Ok(good_result) => good_result.print(),
-                Err(error) => format!("Interpreter Error: {}", error),
-            }
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+mod cli;
+mod commands;
+mod config;
+mod helper;
+mod repl;
+
+// The text of the program, for disassembling
+fn program_text(program: &Program) -> Result<String, Error> {
+    match program {
+        Program::Expressions(expressions) => Ok(expressions.join("\n")),
+        Program::File(path) => {
+            fs::read_to_string(path).map_err(|error| Error::Io(path.clone(), error))
         }
-        // Parsing errors
-        Err(error) => {
-            // handle different types of errors
-            match error {
-                parser::ParseError::Reason(reason) => reason,
-            }
+        Program::Stdin => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|error| Error::Io(String::from("standard input"), error))?;
+            Ok(text)
         }
+        Program::Nothing => Ok(String::new()),
     }
 }
 
-fn interpret_top_level(program: String, environment: &mut interpreter::Environment) -> String {
-    let main_program: String = String::from("(") + &program + ")";
-    let p = parser::Parser::new();
-
-    let result = match p.parse_tokens(&parser::tokenize(main_program)) {
-        Ok((valid_ast, _)) =>
-        // Parser seemed to work, so attempt to interpret the AST
-        {
-            match valid_ast {
-                SExpression::List(list) => match environment.eval_each(list) {
-                    Ok(items) => items
-                        .iter()
-                        .map(|i| i.print())
-                        .collect::<Vec<String>>()
-                        .join("\n"),
-                    Err(error) => format!("Interpreter error {}", error),
-                },
-                _ => match environment.evaluate(valid_ast) {
-                    Ok(ref result) => result.print(),
-                    Err(error) => format!("Interpreter error {}", error),
-                },
+// Run the program a form at a time, printing the value of each top-level
+// form if asked to. An error stops it, unless it's to keep going, when each
+// error is reported and the program carries on with the next form.
+fn run_program(
+    program: &Program,
+    interp: &mut Interpreter,
+    print_results: bool,
+    keep_going: bool,
+) -> Result<(), Error> {
+    let mut failed = 0;
+    let mut stopped = None;
+    let each = |result: Result<SExpression, Error>| match result {
+        Ok(value) => {
+            if print_results {
+                println!("{}", value.print());
             }
+            true
+        }
+        Err(error @ Error::Exit(_)) => {
+            stopped = Some(error);
+            false
+        }
+        Err(error) if keep_going => {
+            eprintln!("rs_lisp: {}", error);
+            failed += 1;
+            true
         }
         Err(error) => {
-            // handle different types of errors
-            match error {
-                parser::ParseError::Reason(reason) => format!("{}", reason),
-            }
+            stopped = Some(error);
+            false
         }
     };
-    println!("{}", &result);
-
-    result
+    match program {
+        Program::File(path) => interp.eval_file_each(path, each)?,
+        Program::Expressions(_) => {
+            interp.eval_forms_each(&program_text(program)?, Some("-e"), each)
+        }
+        Program::Stdin => interp.eval_forms_each(&program_text(program)?, Some("<stdin>"), each),
+        Program::Nothing => {}
+    }
+    match (stopped, failed) {
+        (Some(error), _) => Err(error),
+        (None, 0) => Ok(()),
+        (None, 1) => Err(Error::Eval(String::from("1 form failed"))),
+        (None, failed) => Err(Error::Eval(format!("{} forms failed", failed))),
+    }
 }
 
-// From the Rustyline README
-fn repl() {
-    // `()` can be used when no completer is required
-    let mut rl = Editor::<()>::new();
-
-    if rl.load_history("history.txt").is_err() {
-        println!("No previous history.");
+fn main() {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}", error);
+            eprintln!("{}", cli::USAGE);
+            process::exit(1);
+        }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
     }
 
-    // The environment for the duration of the REPL session
-    let mut envr = interpreter::Environment::new();
-    loop {
-        let readline = rl.readline(">> ");
-        match readline {
-            Ok(line) => {
-                rl.add_history_entry(line.as_str());
-                let results = interpret(line, &mut envr);
-                println!("=>  {}", &results);
-            }
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
-            }
-            Err(ReadlineError::Eof) => {
-                println!("CTRL-D");
-                break;
-            }
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break;
+    let mut interp = Interpreter::with_backend(options.backend);
+    interp.set_redefinition(options.redefinition);
+    for directory in &options.search_path {
+        interp.add_search_path(directory);
+    }
+    interp.add_search_path_from_env();
+
+    // Piped into, with nothing else to do, the program comes from stdin
+    let mut program = options.program;
+    let mut command_line = options.command_line;
+    if matches!(program, Program::Nothing) && !options.interactive && !io::stdin().is_terminal() {
+        program = Program::Stdin;
+        command_line[0] = String::from("-");
+    }
+    interp.set_command_line(command_line);
+
+    if options.disassemble {
+        let listing = program_text(&program).and_then(|code| rs_lisp::disassemble(&code));
+        match listing {
+            Ok(listing) => println!("{}", listing),
+            Err(error) => {
+                eprintln!("Compile error: {}", error);
+                process::exit(1);
             }
         }
+        return;
     }
-    rl.save_history("history.txt").unwrap();
-}
 
-// Construct some basic list types as tests
-fn number_list() -> List {
-    let c = cons(
-        SExpression::Cell(Cell::Int(25)),
-        cons(SExpression::Cell(Cell::Int(5)), Link::Nil),
+    let started = Instant::now();
+    let result = run_program(
+        &program,
+        &mut interp,
+        options.print_results,
+        options.keep_going,
     );
-    List { head: c }
-}
-
-// These aren't unit or acceptance  tests, but a place to put experimental
-// language features and see what crashes.
-fn run_tests() {
-    let n = number_list();
-    let m = List::make_from_cells(vec![
-        Cell::Int(5),
-        Cell::Int(7),
-        Cell::Str("abc".to_string()),
-    ]);
-    let p = List::make_from_sexps(vec![
-        SExpression::List(m.clone()),
-        SExpression::List(n.clone()),
-    ]);
-
-    let summed = List {
-        head: cons(
-            SExpression::Cell(Cell::Op(NumericOperator::Add)),
-            number_list().head,
-        ),
-    };
-
-    println!("Content: {}", &n.print());
-    println!("Variable type list: {}", &m.print());
-    println!("List of lists {}", p.print());
-
-    let mut envr = interpreter::Environment::new();
-    println!(
-        "sum of numbers: {}",
-        summed.evaluate(&mut envr).unwrap().print()
-    );
-
-    println!(
-        "{}",
-        interpret(String::from("(+ 1 2 3 (+ 5 6))"), &mut envr)
-    );
-
-    println!(
-        "{}",
-        interpret(String::from("(+ 1 2 3 (* 5 6))"), &mut envr)
-    );
-
-    println!("{}", interpret(String::from("(/ 10 5)"), &mut envr));
-    println!("{}", interpret(String::from("(/ 5 10)"), &mut envr));
-    println!("{}", interpret(String::from("(* 8 (/ 5 10))"), &mut envr));
-    println!(
-        "{}",
-        interpret(String::from("(- 8 (* 2 25) (+ 2 3) (/ 5 10))"), &mut envr)
-    );
-}
-
-fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
-
-    if args.len() < 2 {
-        repl();
-    } else {
-        let program_file = &args[1];
-        let code = fs::read_to_string(program_file)
-            .expect(&format!("File at {} unreadable.", program_file));
+    if options.time {
+        eprintln!("Elapsed: {:.3}s", started.elapsed().as_secs_f64());
+    }
+    match result {
+        Ok(()) => {}
+        Err(Error::Exit(code)) => process::exit(code),
+        // With -i the REPL still starts, to look into what went wrong
+        Err(error) => {
+            eprintln!("rs_lisp: {}", error);
+            if !options.interactive {
+                process::exit(1);
+            }
+        }
+    }
 
-        let mut envr = interpreter::Environment::new();
-        let results = interpret_top_level(code, &mut envr);
-        //println!("=>  {}", &results);
+    if options.interactive || matches!(program, Program::Nothing) {
+        let mut config = match ReplConfig::load(options.config_file.as_deref().map(Path::new)) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        };
+        if let Some(file) = options.history_file {
+            config.history_file = Some(config::expand_home(&file));
+        }
+        if let Some(file) = options.init_file {
+            config.init_file = Some(config::expand_home(&file));
+        }
+        repl::repl(interp, config)
     }
 }
@@ -0,0 +1,109 @@
+use crate::symbolic_expression::SExpression;
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+// How many arguments a native function takes. A plain number means exactly
+// that many.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    Range(usize, usize),
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Self {
+        Arity::Exact(count)
+    }
+}
+
+impl Arity {
+    fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == n,
+            Arity::AtLeast(min) => count >= min,
+            Arity::Range(min, max) => count >= min && count <= max,
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Arity::Exact(1) => "1 argument".to_string(),
+            Arity::Exact(n) => format!("{} arguments", n),
+            Arity::AtLeast(min) => format!("at least {} arguments", min),
+            Arity::Range(min, max) => format!("{} to {} arguments", min, max),
+        }
+    }
+}
+
+type NativeCallback = dyn Fn(&[SExpression]) -> Result<SExpression, String>;
+
+// A procedure written in Rust by the program embedding the interpreter. It's
+// called with arguments that have already been evaluated, just like a lambda.
+pub struct NativeFunction {
+    name: String,
+    arity: Arity,
+    doc: String,
+    function: Box<NativeCallback>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: Arity, doc: &str, function: F) -> Self
+    where
+        F: Fn(&[SExpression]) -> Result<SExpression, String> + 'static,
+    {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            doc: doc.to_string(),
+            function: Box::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    pub fn doc(&self) -> &str {
+        &self.doc
+    }
+
+    pub fn print(&self) -> String {
+        format!("Native: {}", self.name)
+    }
+
+    // Check the argument count, then run the callback. A panic in the callback
+    // becomes an ordinary error rather than taking the whole interpreter down.
+    pub fn call(&self, args: &[SExpression]) -> Result<SExpression, String> {
+        if !self.arity.accepts(args.len()) {
+            return Err(format!(
+                "'{}' takes {}, got {}.",
+                self.name,
+                self.arity.describe(),
+                args.len()
+            ));
+        }
+        match catch_unwind(AssertUnwindSafe(|| (self.function)(args))) {
+            Ok(result) => result,
+            Err(payload) => Err(format!(
+                "'{}' panicked: {}",
+                self.name,
+                panic_message(payload.as_ref())
+            )),
+        }
+    }
+} // impl NativeFunction
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
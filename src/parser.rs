@@ -1,8 +1,12 @@
 use crate::list::*;
 use crate::primitives::*;
 use crate::symbolic_expression::SExpression;
+use crate::symbols::intern;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::num::ParseFloatError;
+use std::ops::Range;
+use std::rc::Rc;
 
 const TRACE: bool = false;
 
@@ -14,9 +18,11 @@ pub enum ParseError {
 enum TokenType {
     StringLiteral(String),
     LeftParen,
+    VectorStart,
     RightParen,
+    Quote,
     Other(String),
-    EOF,
+    Eof,
 }
 #[derive(Clone, Debug)]
 pub struct Token {
@@ -25,9 +31,41 @@ pub struct Token {
     column: usize,
 }
 
+// The pieces program text is made of, whitespace and comments included.
+// The parser's tokens are made from these, and the REPL uses them as they are
+// for colors, completion and knowing when a form is complete, so they all
+// agree on where a string or a comment ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lexeme {
+    Space,
+    Comment,
+    Open,
+    VectorStart,
+    Close,
+    Quote,
+    // A string with its quotes, which may not have got to its closing one
+    Str { finished: bool },
+    // A symbol or a number
+    Word,
+    // A character nothing starts with
+    Unknown,
+}
+
+// Every lexeme in some text, with where it is as byte offsets
+pub fn scan(text: &str) -> Vec<(Lexeme, Range<usize>)> {
+    let mut lexer = Lexer::new(text.to_string());
+    let mut lexemes = Vec::new();
+    while let Some(lexeme) = lexer.scan() {
+        lexemes.push(lexeme);
+    }
+    lexemes
+}
+
 // maintain the state of the lexical analysis plus keep track of column and line
 pub struct Lexer {
     pos: usize,
+    // The byte offset of pos in text
+    offset: usize,
     line: usize,
     column: usize,
     text: String,
@@ -36,28 +74,31 @@ pub struct Lexer {
 
 impl Lexer {
     fn end_of_input(&self) -> bool {
-        self.pos == self.text.len()
+        self.pos == self.all_chars.len()
     }
 
     pub fn new(text: String) -> Self {
         Self {
-            text: text.clone(),
             all_chars: text.chars().collect(),
+            text,
             pos: 0,
+            offset: 0,
             line: 1,
-            column: 0,
+            column: 1,
         }
     }
 
+    // line and column are always where this_char() is, counting from 1
     fn advance(&mut self) {
         if !self.end_of_input() {
-            self.pos += 1;
             if self.this_char() == '\n' {
                 self.line += 1;
                 self.column = 1;
             } else {
                 self.column += 1;
             }
+            self.offset += self.this_char().len_utf8();
+            self.pos += 1;
         }
     }
 
@@ -69,6 +110,10 @@ impl Lexer {
         }
     }
 
+    fn next_char(&self) -> Option<char> {
+        self.all_chars.get(self.pos + 1).copied()
+    }
+
     fn whitespace(&self) -> bool {
         self.this_char() == ' '
             || self.this_char() == '\t'
@@ -76,8 +121,10 @@ impl Lexer {
             || self.this_char() == '\r'
     }
 
+    // #! comments out the rest of the line too, so a script can start with
+    // a #!/usr/bin/env rs_lisp line
     fn begin_comment(&self) -> bool {
-        self.this_char() == ';'
+        self.this_char() == ';' || (self.this_char() == '#' && self.next_char() == Some('!'))
     }
 
     fn symbol_or_number_char(&self) -> bool {
@@ -95,123 +142,259 @@ impl Lexer {
             || c == '<'
             || c == '>'
             || c == '!'
+            || c == '#'
+            || c == '_'
+            || c == ':' // as in library prefixes like g:area
     }
 
-    fn skip_whitespace(&mut self) {
-        while !self.end_of_input() && self.whitespace() {
-            self.advance();
-        }
-    }
-
-    fn skip_comment(&mut self) {
-        if self.begin_comment() {
-            while self.this_char() != '\n' && !self.end_of_input() {
-                self.advance();
-            }
-        }
-        if TRACE {
-            println!("Skipped comments");
-        }
-    }
-
-    pub fn next(&mut self) -> Token {
-        self.skip_whitespace();
-        self.skip_comment();
-
+    // The next lexeme and where it is, or None at the end of the text
+    fn scan(&mut self) -> Option<(Lexeme, Range<usize>)> {
         if self.end_of_input() {
-            return Token {
-                token_type: TokenType::EOF,
-                line: self.line,
-                column: self.column,
-            };
+            return None;
         }
-
-        if TRACE {
-            println!("Look at {}", &self.this_char());
-        }
-
-        match self.this_char() {
-            '(' => {
+        let start = self.offset;
+        let lexeme = if self.whitespace() {
+            while !self.end_of_input() && self.whitespace() {
                 self.advance();
-                Token {
-                    token_type: TokenType::LeftParen,
-                    line: self.line,
-                    column: self.column,
-                }
             }
-            ')' => {
+            Lexeme::Space
+        } else if self.begin_comment() {
+            while self.this_char() != '\n' && !self.end_of_input() {
                 self.advance();
-                Token {
-                    token_type: TokenType::RightParen,
-                    line: self.line,
-                    column: self.column,
-                }
             }
-            '"' => {
-                let starting_line = self.line;
-                let starting_column = self.column;
-
-                self.advance(); // eat first "
-                let mut content = "".to_string();
-                while !self.end_of_input() && self.this_char() != '"' {
-                    content.push(self.this_char());
+            Lexeme::Comment
+        } else if self.this_char() == '"' {
+            self.advance(); // eat first "
+            while !self.end_of_input() && self.this_char() != '"' {
+                // A backslash escapes the next character, so \" doesn't
+                // end the string
+                if self.this_char() == '\\' {
                     self.advance();
                 }
-
-                self.advance(); // eat the second "
-                Token {
-                    token_type: TokenType::StringLiteral(content),
-                    line: starting_line,
-                    column: starting_column,
-                }
+                self.advance();
             }
-            _ => {
-                if TRACE {
-                    println!("unclassified token...");
-                }
-                if self.symbol_or_number_char() {
-                    if TRACE {
-                        println!("symbol or number...");
-                    }
-                    let mut content = "".to_string();
+            let finished = !self.end_of_input();
+            self.advance(); // eat the second "
+            Lexeme::Str { finished }
+        } else if self.this_char() == '#' && self.next_char() == Some('(') {
+            // #( starts a vector literal
+            self.advance();
+            self.advance();
+            Lexeme::VectorStart
+        } else {
+            let lexeme = match self.this_char() {
+                '(' => Lexeme::Open,
+                ')' => Lexeme::Close,
+                // 'x is shorthand for (quote x)
+                '\'' => Lexeme::Quote,
+                _ if self.symbol_or_number_char() => {
                     while self.symbol_or_number_char() {
-                        content.push(self.this_char());
                         self.advance();
                     }
-                    Token {
-                        token_type: TokenType::Other(content),
-                        line: self.line,
-                        column: self.column,
-                    }
-                } else {
+                    return Some((Lexeme::Word, start..self.offset));
+                }
+                _ => Lexeme::Unknown,
+            };
+            self.advance();
+            lexeme
+        };
+        Some((lexeme, start..self.offset))
+    }
+
+    pub fn next(&mut self) -> Result<Token, ParseError> {
+        loop {
+            // Tokens are located by where they start
+            let line = self.line;
+            let column = self.column;
+            let (lexeme, range) = match self.scan() {
+                Some(found) => found,
+                None => {
+                    return Ok(Token {
+                        token_type: TokenType::Eof,
+                        line,
+                        column,
+                    })
+                }
+            };
+            let text = &self.text[range];
+            let token_type = match lexeme {
+                // A comment may be followed by more whitespace and more comments
+                Lexeme::Space | Lexeme::Comment => continue,
+                Lexeme::Open => TokenType::LeftParen,
+                Lexeme::VectorStart => TokenType::VectorStart,
+                Lexeme::Close => TokenType::RightParen,
+                Lexeme::Quote => TokenType::Quote,
+                Lexeme::Str { .. } => TokenType::StringLiteral(string_contents(text)),
+                Lexeme::Word => TokenType::Other(text.to_string()),
+                Lexeme::Unknown => {
                     // Something we didn't account for
-                    // TODO: throw a real error!
-                    panic!(
+                    return Err(ParseError::Reason(format!(
                         "Can't handle character '{}' at {}, {}",
-                        self.this_char(),
-                        self.line,
-                        self.column
-                    );
+                        text, line, column
+                    )));
                 }
+            };
+            if TRACE {
+                println!("Token: {:?}", &token_type);
             }
+            return Ok(Token {
+                token_type,
+                line,
+                column,
+            });
+        }
+    }
+}
+
+// What a string lexeme stands for. Backslash escapes: \n, \t and \r, and any
+// other escaped character stands for itself, so \" and \\ work.
+fn string_contents(text: &str) -> String {
+    let mut content = String::new();
+    let mut chars = text.chars().skip(1); // the first "
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => content.push('\n'),
+                Some('t') => content.push('\t'),
+                Some('r') => content.push('\r'),
+                Some(other) => content.push(other),
+                None => break,
+            },
+            _ => content.push(c),
         }
     }
+    content
 }
 
-pub fn tokenize(text: String) -> Vec<Token> {
+pub fn tokenize(text: String) -> Result<Vec<Token>, ParseError> {
     let mut lex = Lexer::new(text);
     let mut all_tokens = Vec::new();
     if TRACE {
         println!("Started lexing...");
     }
     while !lex.end_of_input() {
-        let t = lex.next();
+        let t = lex.next()?;
         if TRACE {
             println!("Token: {:?}", &t);
         }
         all_tokens.push(t);
     }
-    all_tokens
+    Ok(all_tokens)
+}
+
+// Where a top-level form starts in the program text, counting from 1
+#[derive(Clone, Copy, Debug)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+pub type FormResult = Result<(SExpression, Position), (ParseError, Position)>;
+
+// Reads a program one top-level form at a time, so each form can run as soon
+// as it's been read, and a mistake late in a file doesn't stop the forms
+// before it from running. After an error it carries on with the next form,
+// so every bad form in a program can be reported.
+pub struct FormReader {
+    lexer: Lexer,
+    parser: Parser,
+    finished: bool,
+}
+
+impl FormReader {
+    pub fn new(text: &str) -> Self {
+        FormReader {
+            lexer: Lexer::new(text.to_string()),
+            parser: Parser::new(),
+            finished: false,
+        }
+    }
+}
+
+impl Iterator for FormReader {
+    type Item = FormResult;
+
+    fn next(&mut self) -> Option<FormResult> {
+        if self.finished {
+            return None;
+        }
+        // The tokens of one form: until the parens balance again, and past
+        // any quotes to the datum they quote
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        let mut start = None;
+        // A character the lexer couldn't handle. The lexer has gone past it,
+        // so the rest of the form is read, then it's reported for the whole
+        // form.
+        let mut bad_character = None;
+        loop {
+            let position = Position {
+                line: self.lexer.line,
+                column: self.lexer.column,
+            };
+            let token = match self.lexer.next() {
+                Ok(token) => token,
+                Err(error) => {
+                    if tokens.is_empty() {
+                        return Some(Err((error, position)));
+                    }
+                    bad_character.get_or_insert((error, position));
+                    continue;
+                }
+            };
+            let position = Position {
+                line: token.line,
+                column: token.column,
+            };
+            let start = *start.get_or_insert(position);
+            match token.token_type {
+                TokenType::Eof => {
+                    self.finished = true;
+                    if tokens.is_empty() {
+                        return None;
+                    }
+                    let reason = "the program ended before this form did".to_string();
+                    return Some(Err((ParseError::Reason(reason), start)));
+                }
+                TokenType::LeftParen | TokenType::VectorStart => depth += 1,
+                TokenType::RightParen if depth == 0 => {
+                    let reason = "unexpected `)`".to_string();
+                    return Some(Err((ParseError::Reason(reason), position)));
+                }
+                TokenType::RightParen => depth -= 1,
+                _ => {}
+            }
+            let quote = matches!(token.token_type, TokenType::Quote);
+            tokens.push(token);
+            if depth == 0 && !quote {
+                break;
+            }
+        }
+        let start = start.expect("A form has a first token");
+        if let Some(error) = bad_character {
+            return Some(Err(error));
+        }
+        Some(
+            self.parser
+                .parse_tokens(&tokens)
+                .map(|(form, _)| (form, start))
+                .map_err(|error| (error, start)),
+        )
+    }
+}
+
+thread_local! {
+    static RESERVED_SYMBOLS: HashMap<String, Cell> = map_cell_from_string();
+}
+
+// The cell the parser would read for a bare name: a built-in like car or +,
+// otherwise a symbol.
+pub fn symbol_cell(name: &str) -> Cell {
+    RESERVED_SYMBOLS.with(|reserved| match reserved.get(name) {
+        Some(cell) => cell.clone(),
+        None => Cell::Symbol(intern(name), name.to_string()),
+    })
 }
 
 pub struct Parser {
@@ -235,8 +418,20 @@ impl Parser {
 
         match &token.token_type {
             TokenType::LeftParen => self.read_list_tokens(rest),
+            TokenType::VectorStart => match self.read_list_tokens(rest)? {
+                (SExpression::List(items), rest) => {
+                    let vector = Cell::Vector(Rc::new(RefCell::new(items.to_vec())));
+                    Ok((SExpression::Cell(vector), rest))
+                }
+                (other, rest) => Ok((other, rest)),
+            },
             TokenType::RightParen => Err(ParseError::Reason("unexpected `)`".to_string())),
-            _ => Ok((self.parse_cell_token(token), rest)),
+            TokenType::Quote => {
+                let (quoted, rest) = self.parse_tokens(rest)?;
+                let quote_form = vec![SExpression::Cell(Cell::Special(SpecialForm::Quote)), quoted];
+                Ok((SExpression::List(List::make_from_sexps(quote_form)), rest))
+            }
+            _ => Ok((self.parse_cell_token(token)?, rest)),
         }
     }
 
@@ -256,7 +451,7 @@ impl Parser {
                     return Ok((SExpression::List(List::make_from_sexps(res)), rest));
                     // skip `)`, head to the token after
                 }
-                TokenType::EOF => {
+                TokenType::Eof => {
                     return Err(ParseError::Reason(
                         "could not find closing `)` and reached end of input.".to_string(),
                     ))
@@ -270,12 +465,17 @@ impl Parser {
         } // loop
     }
 
-    fn parse_cell_token(&self, token: &Token) -> SExpression {
+    fn parse_cell_token(&self, token: &Token) -> Result<SExpression, ParseError> {
         //println!("TOKEN : '{}' ", token);
         let (token_data, is_string) = match token.token_type {
             TokenType::Other(ref value) => (value, false),
             TokenType::StringLiteral(ref value) => (value, true),
-            _ => panic!("Unexpected token type at {}, {}", token.line, token.column),
+            _ => {
+                return Err(ParseError::Reason(format!(
+                    "Unexpected end of input at {}, {}",
+                    token.line, token.column
+                )))
+            }
         };
 
         if !is_string
@@ -288,18 +488,27 @@ impl Parser {
                 .get(token_data.as_str())
                 .unwrap();
             //			println!("Identified reserved word: '{}' ", op.print());
-            SExpression::Cell(op.clone())
+            Ok(SExpression::Cell(op.clone()))
         } else {
-            if is_string {
+            let exp = if is_string {
                 SExpression::Cell(Cell::Str(token_data.to_string()))
+            } else if token_data == "#t" || token_data == "#true" {
+                SExpression::Cell(Cell::Bool(true))
+            } else if token_data == "#f" || token_data == "#false" {
+                SExpression::Cell(Cell::Bool(false))
+            } else if token_data == "#nil" {
+                SExpression::Null
             // it should parse as a number or else it's a symbol; built-in operators and strings are already eliminated
             } else {
                 let potential_float: Result<f64, ParseFloatError> = token_data.parse();
                 match potential_float {
                     Ok(v) => SExpression::Cell(Cell::Flt(v)),
-                    Err(_) => SExpression::Cell(Cell::Symbol(0, token_data.to_string())),
+                    Err(_) => {
+                        SExpression::Cell(Cell::Symbol(intern(token_data), token_data.to_string()))
+                    }
                 }
-            }
+            };
+            Ok(exp)
         }
     }
 } // impl Parser
@@ -0,0 +1,345 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::rc::Rc;
+
+// Ports are where input comes from and output goes: standard input and
+// output, files, or strings. A port is either for input or for output, and either
+// textual (characters and strings) or binary (bytes.) Ports are shared, like
+// hash tables, so closing one closes it for everything holding it.
+pub struct Port {
+    name: String,
+    input: bool,
+    binary: bool,
+    stream: Stream,
+}
+
+enum Stream {
+    Reader(Input),
+    Writer(Box<dyn Write>),
+    // An output string port collects everything written to it
+    Text(Vec<u8>),
+    Closed,
+}
+
+pub type PortRef = Rc<RefCell<Port>>;
+
+impl Port {
+    fn new(name: &str, input: bool, binary: bool, stream: Stream) -> PortRef {
+        Rc::new(RefCell::new(Port {
+            name: name.to_string(),
+            input,
+            binary,
+            stream,
+        }))
+    }
+
+    pub fn stdin() -> PortRef {
+        let reader = Input::new(Box::new(BufReader::new(io::stdin())));
+        Port::new("stdin", true, false, Stream::Reader(reader))
+    }
+
+    pub fn stdout() -> PortRef {
+        Port::new(
+            "stdout",
+            false,
+            false,
+            Stream::Writer(Box::new(io::stdout())),
+        )
+    }
+
+    pub fn open_input_file(path: &str, binary: bool) -> Result<PortRef, String> {
+        let file = File::open(path).map_err(|error| format!("Can't open {}: {}", path, error))?;
+        let reader = Input::new(Box::new(BufReader::new(file)));
+        Ok(Port::new(path, true, binary, Stream::Reader(reader)))
+    }
+
+    pub fn open_output_file(path: &str, binary: bool) -> Result<PortRef, String> {
+        let file =
+            File::create(path).map_err(|error| format!("Can't create {}: {}", path, error))?;
+        let writer = Box::new(BufWriter::new(file));
+        Ok(Port::new(path, false, binary, Stream::Writer(writer)))
+    }
+
+    pub fn open_input_string(text: &str) -> PortRef {
+        let reader = Input::new(Box::new(Cursor::new(text.as_bytes().to_vec())));
+        Port::new("string", true, false, Stream::Reader(reader))
+    }
+
+    pub fn open_output_string() -> PortRef {
+        Port::new("string", false, false, Stream::Text(Vec::new()))
+    }
+
+    // What's been written to an output string port so far
+    pub fn output_string(&self) -> Result<String, String> {
+        match &self.stream {
+            Stream::Text(bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            _ => Err(format!("{} is not an output string port", self.print())),
+        }
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    pub fn print(&self) -> String {
+        let direction = if self.input { "input" } else { "output" };
+        let kind = if self.binary { "binary" } else { "textual" };
+        format!("#<{} {}-port {}>", kind, direction, self.name)
+    }
+
+    // Closing twice is fine; reading or writing afterwards isn't.
+    pub fn close(&mut self) -> Result<(), String> {
+        if let Stream::Writer(writer) = &mut self.stream {
+            writer.flush().map_err(|error| self.io_error(error))?;
+        }
+        self.stream = Stream::Closed;
+        Ok(())
+    }
+
+    fn io_error(&self, error: io::Error) -> String {
+        format!("Error on port {}: {}", self.name, error)
+    }
+
+    pub fn reader(&mut self) -> Result<&mut Input, String> {
+        match &mut self.stream {
+            Stream::Reader(reader) => Ok(reader),
+            Stream::Writer(_) | Stream::Text(_) => {
+                Err(format!("{} is not an input port", self.name))
+            }
+            Stream::Closed => Err(format!("Port {} is closed", self.name)),
+        }
+    }
+
+    fn writer(&mut self) -> Result<&mut dyn Write, String> {
+        match &mut self.stream {
+            Stream::Writer(writer) => Ok(writer),
+            Stream::Text(bytes) => Ok(bytes),
+            Stream::Reader(_) => Err(format!("{} is not an output port", self.name)),
+            Stream::Closed => Err(format!("Port {} is closed", self.name)),
+        }
+    }
+
+    // Text goes out straight away, so output shows up in order with anything
+    // else the program prints.
+    pub fn write_str(&mut self, text: &str) -> Result<(), String> {
+        self.write_bytes(text.as_bytes())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let writer = self.writer()?;
+        let result = writer.write_all(bytes).and_then(|_| writer.flush());
+        result.map_err(|error| self.io_error(error))
+    }
+
+    pub fn read_char(&mut self) -> Result<Option<char>, String> {
+        let result = self.reader()?.read_char();
+        result.map_err(|error| self.io_error(error))
+    }
+
+    pub fn peek_char(&mut self) -> Result<Option<char>, String> {
+        let result = self.reader()?.peek_char();
+        result.map_err(|error| self.io_error(error))
+    }
+
+    // The line without its line ending; None at the end of the input
+    pub fn read_line(&mut self) -> Result<Option<String>, String> {
+        let mut line = Vec::new();
+        let result = self.reader()?.read_until(b'\n', &mut line);
+        match result {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+                String::from_utf8(line)
+                    .map(Some)
+                    .map_err(|_| format!("Invalid UTF-8 on port {}", self.name))
+            }
+            Err(error) => Err(self.io_error(error)),
+        }
+    }
+
+    // Up to count characters; fewer at the end of the input
+    pub fn read_string(&mut self, count: usize) -> Result<Option<String>, String> {
+        let mut text = String::new();
+        for _ in 0..count {
+            match self.read_char()? {
+                Some(c) => text.push(c),
+                None => break,
+            }
+        }
+        if text.is_empty() && count > 0 {
+            return Ok(None);
+        }
+        Ok(Some(text))
+    }
+
+    pub fn read_byte(&mut self) -> Result<Option<u8>, String> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.reader()?.consume(1);
+        }
+        Ok(byte)
+    }
+
+    pub fn peek_byte(&mut self) -> Result<Option<u8>, String> {
+        let result = self
+            .reader()?
+            .fill_buf()
+            .map(|buffer| buffer.first().copied());
+        result.map_err(|error| self.io_error(error))
+    }
+} // impl Port
+
+// An input stream that can look one character ahead. A peeked character's
+// bytes are held until they're read, by read_char or through BufRead, so the
+// JSON reader and read-line see them too.
+pub struct Input {
+    inner: Box<dyn BufRead>,
+    pending: Vec<u8>,
+}
+
+impl Input {
+    fn new(inner: Box<dyn BufRead>) -> Self {
+        Input {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+
+    pub(crate) fn peek_char(&mut self) -> io::Result<Option<char>> {
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) if !text.is_empty() => return Ok(text.chars().next()),
+                Err(error) if error.error_len().is_some() => return Err(invalid_utf8()),
+                // Nothing yet, or the start of a character that's more than one byte
+                _ => {}
+            }
+            let mut byte = [0];
+            match self.inner.read(&mut byte)? {
+                0 if self.pending.is_empty() => return Ok(None),
+                0 => return Err(invalid_utf8()),
+                _ => self.pending.push(byte[0]),
+            }
+        }
+    }
+
+    pub(crate) fn read_char(&mut self) -> io::Result<Option<char>> {
+        let c = self.peek_char()?;
+        if let Some(c) = c {
+            self.pending.drain(..c.len_utf8());
+        }
+        Ok(c)
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8")
+}
+
+impl Read for Input {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pending.is_empty() {
+            self.inner.fill_buf()
+        } else {
+            Ok(&self.pending)
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if self.pending.is_empty() {
+            self.inner.consume(amount);
+        } else {
+            self.pending.drain(..amount);
+        }
+    }
+}
+
+thread_local! {
+    // The process has one standard input and output, so every interpreter's
+    // ports for them are the same ones, with the same buffer.
+    static STDIN: PortRef = Port::stdin();
+    static STDOUT: PortRef = Port::stdout();
+}
+
+// The ports that read-line, output and the rest use when they aren't given
+// one. with-output-to-file swaps a different one in for a while. Each
+// interpreter has its own, in its Context, so one running inside another's
+// with-output-to-string doesn't print into it.
+pub struct CurrentPorts {
+    input: RefCell<PortRef>,
+    output: RefCell<PortRef>,
+}
+
+impl Default for CurrentPorts {
+    fn default() -> Self {
+        CurrentPorts {
+            input: RefCell::new(STDIN.with(Rc::clone)),
+            output: RefCell::new(STDOUT.with(Rc::clone)),
+        }
+    }
+}
+
+impl CurrentPorts {
+    pub fn input(&self) -> PortRef {
+        self.input.borrow().clone()
+    }
+
+    pub fn output(&self) -> PortRef {
+        self.output.borrow().clone()
+    }
+
+    pub fn write_output(&self, text: &str) -> Result<(), String> {
+        self.output().borrow_mut().write_str(text)
+    }
+
+    pub fn with_input<T>(&self, port: PortRef, body: impl FnOnce() -> T) -> T {
+        with_current(&self.input, port, body)
+    }
+
+    pub fn with_output<T>(&self, port: PortRef, body: impl FnOnce() -> T) -> T {
+        with_current(&self.output, port, body)
+    }
+}
+
+// Puts the previous port back when dropped, even if the body returned an
+// error or a native function panicked.
+struct Restore<'a> {
+    current: &'a RefCell<PortRef>,
+    previous: Option<PortRef>,
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            *self.current.borrow_mut() = previous;
+        }
+    }
+}
+
+fn with_current<T>(current: &RefCell<PortRef>, port: PortRef, body: impl FnOnce() -> T) -> T {
+    let previous = current.replace(port);
+    let _restore = Restore {
+        current,
+        previous: Some(previous),
+    };
+    body()
+}
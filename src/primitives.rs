@@ -1,10 +1,20 @@
+use crate::exceptions::Condition;
+use crate::hash_table::HashTable;
+use crate::interpreter::Environment;
+use crate::lexical::LambdaTemplate;
+use crate::native::NativeFunction;
+use crate::port::PortRef;
 use crate::symbolic_expression::SExpression;
+use crate::symbols::symbol_name;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use strum::IntoEnumIterator; // 0.17.1
 use strum_macros::EnumIter; // 0.17.1
 
-#[derive(Debug, Clone, Copy, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
 pub enum CoreFunc {
     // A subset of the "essential procedures" pertaining to type checking and conversion
     IsChar,
@@ -32,6 +42,127 @@ pub enum CoreFunc {
     First,
     Rest,
     Append,
+
+    // Equivalence predicates, also used to choose how hash tables compare keys
+    IsEq,
+    IsEqv,
+    IsEqual,
+    StringEqual,
+    Member,
+    Memq,
+    Memv,
+    Assoc,
+    Assq,
+    Assv,
+
+    // Hash tables
+    IsHashTable,
+    MakeHashTable,
+    AlistToHashTable,
+    HashTableSet,
+    HashTableRef,
+    HashTableRefDefault,
+    HashTableUpdate,
+    HashTableUpdateDefault,
+    HashTableDelete,
+    HashTableContains,
+    HashTableSize,
+    HashTableKeys,
+    HashTableValues,
+    HashTableToAlist,
+    HashTableWalk,
+    HashTableFold,
+    HashTableClear,
+    HashTableCopy,
+
+    // Vectors
+    IsVector,
+    MakeVector,
+    Vector,
+    VectorLength,
+    VectorRef,
+    VectorSet,
+    VectorToList,
+    ListToVector,
+
+    // JSON reading and writing
+    JsonRead,
+    JsonWrite,
+    StringToJson,
+    JsonToString,
+    IsJsonNull,
+    JsonNull,
+
+    // Ports and file I/O
+    IsInputPort,
+    IsOutputPort,
+    IsTextualPort,
+    IsBinaryPort,
+    OpenInputFile,
+    OpenOutputFile,
+    OpenBinaryInputFile,
+    OpenBinaryOutputFile,
+    CallWithInputFile,
+    CallWithOutputFile,
+    WithInputFromFile,
+    WithOutputToFile,
+    CurrentInputPort,
+    CurrentOutputPort,
+    ReadLine,
+    ReadChar,
+    PeekChar,
+    ReadString,
+    ReadU8,
+    PeekU8,
+    WriteString,
+    WriteU8,
+    Newline,
+    Display,
+    Write,
+    ClosePort,
+    CloseInputPort,
+    CloseOutputPort,
+    IsEofObject,
+    EofObject,
+
+    // String ports
+    OpenInputString,
+    OpenOutputString,
+    GetOutputString,
+    WithOutputToString,
+    CallWithOutputString,
+
+    // Reading data
+    Read,
+
+    // Evaluation and first-class environments
+    Eval,
+    IsEnvironment,
+    InteractionEnvironment,
+    SchemeReportEnvironment,
+    MakeEnvironment,
+    CurrentEnvironment,
+    IsEnvironmentBound,
+    EnvironmentRef,
+    EnvironmentDefine,
+    EnvironmentBindings,
+
+    // Programs in more than one file
+    Load,
+
+    // The program's surroundings
+    Exit,
+    CommandLine,
+
+    // Exceptions
+    Error,
+    Raise,
+    RaiseContinuable,
+    WithExceptionHandler,
+    WithExceptionCatcher,
+    IsErrorObject,
+    ErrorObjectMessage,
+    ErrorObjectIrritants,
 }
 
 impl CoreFunc {
@@ -60,10 +191,109 @@ impl CoreFunc {
             CoreFunc::First => "first", // aliases for car and cdr
             CoreFunc::Rest => "rest",
             CoreFunc::Append => "append",
+            CoreFunc::IsEq => "eq?",
+            CoreFunc::IsEqv => "eqv?",
+            CoreFunc::IsEqual => "equal?",
+            CoreFunc::StringEqual => "string=?",
+            CoreFunc::Member => "member",
+            CoreFunc::Memq => "memq",
+            CoreFunc::Memv => "memv",
+            CoreFunc::Assoc => "assoc",
+            CoreFunc::Assq => "assq",
+            CoreFunc::Assv => "assv",
+            CoreFunc::IsHashTable => "hash-table?",
+            CoreFunc::MakeHashTable => "make-hash-table",
+            CoreFunc::AlistToHashTable => "alist->hash-table",
+            CoreFunc::HashTableSet => "hash-table-set!",
+            CoreFunc::HashTableRef => "hash-table-ref",
+            CoreFunc::HashTableRefDefault => "hash-table-ref/default",
+            CoreFunc::HashTableUpdate => "hash-table-update!",
+            CoreFunc::HashTableUpdateDefault => "hash-table-update!/default",
+            CoreFunc::HashTableDelete => "hash-table-delete!",
+            CoreFunc::HashTableContains => "hash-table-contains?",
+            CoreFunc::HashTableSize => "hash-table-size",
+            CoreFunc::HashTableKeys => "hash-table-keys",
+            CoreFunc::HashTableValues => "hash-table-values",
+            CoreFunc::HashTableToAlist => "hash-table->alist",
+            CoreFunc::HashTableWalk => "hash-table-walk",
+            CoreFunc::HashTableFold => "hash-table-fold",
+            CoreFunc::HashTableClear => "hash-table-clear!",
+            CoreFunc::HashTableCopy => "hash-table-copy",
+            CoreFunc::IsVector => "vector?",
+            CoreFunc::MakeVector => "make-vector",
+            CoreFunc::Vector => "vector",
+            CoreFunc::VectorLength => "vector-length",
+            CoreFunc::VectorRef => "vector-ref",
+            CoreFunc::VectorSet => "vector-set!",
+            CoreFunc::VectorToList => "vector->list",
+            CoreFunc::ListToVector => "list->vector",
+            CoreFunc::JsonRead => "json-read",
+            CoreFunc::JsonWrite => "json-write",
+            CoreFunc::StringToJson => "string->json",
+            CoreFunc::JsonToString => "json->string",
+            CoreFunc::IsJsonNull => "json-null?",
+            CoreFunc::JsonNull => "json-null",
+            CoreFunc::IsInputPort => "input-port?",
+            CoreFunc::IsOutputPort => "output-port?",
+            CoreFunc::IsTextualPort => "textual-port?",
+            CoreFunc::IsBinaryPort => "binary-port?",
+            CoreFunc::OpenInputFile => "open-input-file",
+            CoreFunc::OpenOutputFile => "open-output-file",
+            CoreFunc::OpenBinaryInputFile => "open-binary-input-file",
+            CoreFunc::OpenBinaryOutputFile => "open-binary-output-file",
+            CoreFunc::CallWithInputFile => "call-with-input-file",
+            CoreFunc::CallWithOutputFile => "call-with-output-file",
+            CoreFunc::WithInputFromFile => "with-input-from-file",
+            CoreFunc::WithOutputToFile => "with-output-to-file",
+            CoreFunc::CurrentInputPort => "current-input-port",
+            CoreFunc::CurrentOutputPort => "current-output-port",
+            CoreFunc::ReadLine => "read-line",
+            CoreFunc::ReadChar => "read-char",
+            CoreFunc::PeekChar => "peek-char",
+            CoreFunc::ReadString => "read-string",
+            CoreFunc::ReadU8 => "read-u8",
+            CoreFunc::PeekU8 => "peek-u8",
+            CoreFunc::WriteString => "write-string",
+            CoreFunc::WriteU8 => "write-u8",
+            CoreFunc::Newline => "newline",
+            CoreFunc::Display => "display",
+            CoreFunc::Write => "write",
+            CoreFunc::ClosePort => "close-port",
+            CoreFunc::CloseInputPort => "close-input-port",
+            CoreFunc::CloseOutputPort => "close-output-port",
+            CoreFunc::IsEofObject => "eof-object?",
+            CoreFunc::EofObject => "eof-object",
+            CoreFunc::OpenInputString => "open-input-string",
+            CoreFunc::OpenOutputString => "open-output-string",
+            CoreFunc::GetOutputString => "get-output-string",
+            CoreFunc::WithOutputToString => "with-output-to-string",
+            CoreFunc::CallWithOutputString => "call-with-output-string",
+            CoreFunc::Read => "read",
+            CoreFunc::Eval => "eval",
+            CoreFunc::IsEnvironment => "environment?",
+            CoreFunc::InteractionEnvironment => "interaction-environment",
+            CoreFunc::SchemeReportEnvironment => "scheme-report-environment",
+            CoreFunc::MakeEnvironment => "make-environment",
+            CoreFunc::CurrentEnvironment => "current-environment",
+            CoreFunc::IsEnvironmentBound => "environment-bound?",
+            CoreFunc::EnvironmentRef => "environment-ref",
+            CoreFunc::EnvironmentDefine => "environment-define!",
+            CoreFunc::EnvironmentBindings => "environment-bindings",
+            CoreFunc::Load => "load",
+            CoreFunc::Exit => "exit",
+            CoreFunc::CommandLine => "command-line",
+            CoreFunc::Error => "error",
+            CoreFunc::Raise => "raise",
+            CoreFunc::RaiseContinuable => "raise-continuable",
+            CoreFunc::WithExceptionHandler => "with-exception-handler",
+            CoreFunc::WithExceptionCatcher => "with-exception-catcher",
+            CoreFunc::IsErrorObject => "error-object?",
+            CoreFunc::ErrorObjectMessage => "error-object-message",
+            CoreFunc::ErrorObjectIrritants => "error-object-irritants",
         }
     }
 } // impl corefunc
-#[derive(Debug, Clone, Copy, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
 pub enum NumericOperator {
     Add,
     Subtract,
@@ -91,7 +321,7 @@ pub enum NumericOperator {
     */
 }
 
-#[derive(Debug, Clone, Copy, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
 pub enum LogicalOperator {
     Less,
     Greater,
@@ -121,6 +351,59 @@ impl NumericOperator {
             NumericOperator::Modulo => "%",
         }
     }
+
+    // Apply the operator left to right to numbers that have already been
+    // evaluated, so (- 10 2 3) is (10 - 2) - 3. Both the tree walker and the
+    // bytecode VM come through here.
+    pub fn apply(self, values: &[SExpression]) -> Result<SExpression, String> {
+        let mut numbers = values.iter().map(|value| value.as_number());
+        let mut result = match numbers.next() {
+            Some(number) => number?,
+            None => {
+                return Err(String::from("Operator ") + self.print() + " requires two arguments")
+            }
+        };
+        for number in numbers {
+            result = self.combine(result, number?)?;
+        }
+        Ok(SExpression::Cell(result))
+    }
+
+    fn combine(self, left: Cell, right: Cell) -> Result<Cell, String> {
+        let (l, r) = match (left, right) {
+            (Cell::Int(l), Cell::Int(r)) => {
+                let result = match self {
+                    NumericOperator::Add => l.checked_add(r),
+                    NumericOperator::Subtract => l.checked_sub(r),
+                    NumericOperator::Multiply => l.checked_mul(r),
+                    NumericOperator::Divide if r == 0 => return Err("Division by zero".to_string()),
+                    NumericOperator::Divide => l.checked_div(r),
+                    _ => return Err(format!("Operator '{}' not implemented", self.print())),
+                };
+                return result.map(Cell::Int).ok_or_else(|| {
+                    format!("Integer overflow in '{}' of {} and {}", self.print(), l, r)
+                });
+            }
+            (Cell::Int(l), Cell::Flt(r)) => (l as f64, r),
+            (Cell::Flt(l), Cell::Int(r)) => (l, r as f64),
+            (Cell::Flt(l), Cell::Flt(r)) => (l, r),
+            (l, r) => {
+                return Err(format!(
+                    "Operator '{}' needs numbers, got {} and {}",
+                    self.print(),
+                    l.print(),
+                    r.print()
+                ))
+            }
+        };
+        match self {
+            NumericOperator::Add => Ok(Cell::Flt(l + r)),
+            NumericOperator::Subtract => Ok(Cell::Flt(l - r)),
+            NumericOperator::Multiply => Ok(Cell::Flt(l * r)),
+            NumericOperator::Divide => Ok(Cell::Flt(l / r)),
+            _ => Err(format!("Operator '{}' not implemented", self.print())),
+        }
+    }
 }
 
 impl LogicalOperator {
@@ -137,11 +420,47 @@ impl LogicalOperator {
             LogicalOperator::Xor => "xor",
         }
     }
+
+    // Numeric comparisons chain, so (< a b c) means a < b and b < c. 'and' and
+    // 'or' don't come through here since they only evaluate what they need to.
+    pub fn compare(self, values: &[SExpression]) -> Result<SExpression, String> {
+        let numbers = values
+            .iter()
+            .map(|value| value.as_number())
+            .collect::<Result<Vec<Cell>, String>>()?;
+        for pair in numbers.windows(2) {
+            let ordering = match (&pair[0], &pair[1]) {
+                (Cell::Int(i), Cell::Int(j)) => i.partial_cmp(j),
+                (Cell::Int(i), Cell::Flt(j)) => (*i as f64).partial_cmp(j),
+                (Cell::Flt(i), Cell::Int(j)) => i.partial_cmp(&(*j as f64)),
+                (Cell::Flt(i), Cell::Flt(j)) => i.partial_cmp(j),
+                (i, j) => {
+                    return Err(format!(
+                        "Operator '{}' needs numbers, got {} and {}",
+                        self.print(),
+                        i.print(),
+                        j.print()
+                    ))
+                }
+            };
+            let holds = match self {
+                LogicalOperator::Greater => ordering == Some(Ordering::Greater),
+                LogicalOperator::Less => ordering == Some(Ordering::Less),
+                LogicalOperator::Equal => ordering == Some(Ordering::Equal),
+                _ => return Err(format!("Operator '{}' not implemented", self.print())),
+            };
+            if !holds {
+                return Ok(SExpression::Cell(Cell::Bool(false)));
+            }
+        }
+        Ok(SExpression::Cell(Cell::Bool(true)))
+    }
 }
 
-#[derive(Debug, Clone, Copy, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
 pub enum SpecialForm {
     Define,
+    Lambda,
     Let,
     SetCar,
     Set,
@@ -154,18 +473,23 @@ pub enum SpecialForm {
     Break,
     Continue,
     Begin,
+    Quote,
 
     Env,
-    Exit,
     Input,
     Output,
     OutputLine,
+    Include,
+    DefineLibrary,
+    Import,
+    Guard,
 }
 
 impl SpecialForm {
     pub fn print(self) -> &'static str {
         match self {
             SpecialForm::Define => "define",
+            SpecialForm::Lambda => "lambda",
             SpecialForm::Let => "let",
             SpecialForm::SetCar => "setcar!",
             SpecialForm::Set => "set!",
@@ -178,11 +502,15 @@ impl SpecialForm {
             SpecialForm::Break => "break",
             SpecialForm::Continue => "continue",
             SpecialForm::Begin => "begin",
+            SpecialForm::Quote => "quote",
             SpecialForm::Env => "env",
-            SpecialForm::Exit => "exit",
             SpecialForm::Input => "input",
             SpecialForm::Output => "output",
             SpecialForm::OutputLine => "output-line",
+            SpecialForm::Include => "include",
+            SpecialForm::DefineLibrary => "define-library",
+            SpecialForm::Import => "import",
+            SpecialForm::Guard => "guard",
         }
     }
 }
@@ -198,7 +526,19 @@ pub enum Cell {
     Logical(LogicalOperator),
     Special(SpecialForm), // other built-in functions
     Core(CoreFunc),
-    Lambda(Box<SExpression>, Box<SExpression>), // arguments and body of the lambda
+    Lambda(Rc<LambdaTemplate>, Environment), // a lambda and the environment it was created in
+    // A variable's lexical address: frames out from the current one, slot in
+    // that frame, and the symbol number for error messages
+    Local(usize, usize, i32),
+    Template(Rc<LambdaTemplate>),      // an analyzed lambda expression
+    HashTable(Rc<RefCell<HashTable>>), // shared, so updates are seen by every reference
+    Native(Rc<NativeFunction>),        // a procedure written in Rust
+    Vector(Rc<RefCell<Vec<SExpression>>>), // shared like hash tables, written #(1 2 3)
+    Port(PortRef),                     // a file or standard input or output
+    Eof,                               // what reading at the end of the input gives
+    JsonNull,                          // JSON's null, which isn't #f or '()
+    Environment(Environment),          // a scope, for eval and friends
+    Condition(Rc<Condition>),          // what error raises, or an error that was caught
 }
 
 impl Cell {
@@ -207,13 +547,27 @@ impl Cell {
             Cell::Int(value) => value.to_string(),
             Cell::Flt(value) => value.to_string(),
             Cell::Str(value) => value.to_string(),
-            Cell::Symbol(number, name) => format!("Symbol {}: {}", number, &name),
+            Cell::Symbol(_number, name) => name.to_string(),
             Cell::Bool(value) => value.to_string(),
-            Cell::Op(operator) => format!("Numeric operator {}", operator.print()),
-            Cell::Logical(operator) => format!("Logical operator{}", operator.print()),
-            Cell::Special(special_form) => String::from("Special form"),
-            Cell::Core(func) => String::from("core-function"),
-            Cell::Lambda(_, _) => String::from("Lambda: "),
+            // Reserved words print as written so quoted code reads back the same
+            Cell::Op(operator) => operator.print().to_string(),
+            Cell::Logical(operator) => operator.print().to_string(),
+            Cell::Special(special_form) => special_form.print().to_string(),
+            Cell::Core(func) => func.print().to_string(),
+            Cell::Lambda(template, _) => template.print(),
+            Cell::Local(_, _, number) => symbol_name(*number),
+            Cell::Template(template) => template.print(),
+            Cell::HashTable(table) => table.borrow().print(),
+            Cell::Native(function) => function.print(),
+            Cell::Port(port) => port.borrow().print(),
+            Cell::Eof => String::from("#<eof>"),
+            Cell::JsonNull => String::from("#<json-null>"),
+            Cell::Environment(_) => String::from("#<environment>"),
+            Cell::Condition(condition) => condition.print(),
+            Cell::Vector(items) => {
+                let printed: Vec<String> = items.borrow().iter().map(|item| item.print()).collect();
+                format!("#({})", printed.join(" "))
+            }
         }
     }
 
@@ -0,0 +1,145 @@
+use crate::parser::{tokenize, ParseError, Parser};
+use crate::port::Input;
+use crate::symbolic_expression::SExpression;
+
+// Reads data one at a time from a port, for 'read'. The characters of the
+// next datum are collected first, skipping whitespace and comments, and then
+// handed to the parser, so whatever follows the datum stays unread on the
+// port. Nothing is evaluated.
+pub struct DatumReader<'a> {
+    input: &'a mut Input,
+    text: String,
+}
+
+impl<'a> DatumReader<'a> {
+    pub fn new(input: &'a mut Input) -> Self {
+        DatumReader {
+            input,
+            text: String::new(),
+        }
+    }
+
+    // The next datum, or None when there's nothing but whitespace and
+    // comments left. Running out in the middle of a datum is an error.
+    pub fn read(mut self) -> Result<Option<SExpression>, String> {
+        self.skip_atmosphere()?;
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+        self.datum()?;
+        let tokens = tokenize(self.text).map_err(reason)?;
+        let (exp, _) = Parser::new().parse_tokens(&tokens).map_err(reason)?;
+        Ok(Some(exp))
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, String> {
+        self.input.peek_char().map_err(|error| error.to_string())
+    }
+
+    // Move the next character over to the datum's text
+    fn take(&mut self) -> Result<Option<char>, String> {
+        let c = self.input.read_char().map_err(|error| error.to_string())?;
+        if let Some(c) = c {
+            self.text.push(c);
+        }
+        Ok(c)
+    }
+
+    fn skip(&mut self) -> Result<(), String> {
+        self.input.read_char().map_err(|error| error.to_string())?;
+        Ok(())
+    }
+
+    fn skip_atmosphere(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek()? {
+                Some(c) if c.is_whitespace() => self.skip()?,
+                Some(';') => {
+                    while !matches!(self.peek()?, None | Some('\n')) {
+                        self.skip()?;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn datum(&mut self) -> Result<(), String> {
+        match self.peek()? {
+            None => Err("Unexpected end of input in read".to_string()),
+            // 'x is (quote x), so read x too
+            Some('\'') => {
+                self.take()?;
+                self.skip_atmosphere()?;
+                self.datum()
+            }
+            Some('(') => {
+                self.take()?;
+                self.list()
+            }
+            Some(')') => {
+                self.skip()?;
+                Err("Unexpected `)` in read".to_string())
+            }
+            Some('"') => self.string(),
+            Some('#') => {
+                self.take()?;
+                if self.peek()? == Some('(') {
+                    self.take()?;
+                    return self.list();
+                }
+                self.atom()
+            }
+            Some(_) => self.atom(),
+        }
+    }
+
+    // Everything up to the closing paren, with its own comments skipped
+    fn list(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_atmosphere()?;
+            match self.peek()? {
+                None => return Err("Could not find closing `)` in read".to_string()),
+                Some(')') => {
+                    self.take()?;
+                    return Ok(());
+                }
+                Some(_) => {
+                    self.datum()?;
+                    self.text.push(' ');
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        self.take()?;
+        loop {
+            match self.take()? {
+                None => return Err("Unterminated string in read".to_string()),
+                Some('"') => return Ok(()),
+                Some('\\') => {
+                    self.take()?;
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Symbols, numbers and #t and the like run until a delimiter
+    fn atom(&mut self) -> Result<(), String> {
+        while let Some(c) = self.peek()? {
+            if c.is_whitespace() || matches!(c, '(' | ')' | '"' | ';') {
+                break;
+            }
+            self.take()?;
+        }
+        Ok(())
+    }
+}
+
+fn reason(error: ParseError) -> String {
+    match error {
+        ParseError::Reason(reason) => reason,
+    }
+}
@@ -0,0 +1,123 @@
+use crate::commands::Session;
+use crate::config::ReplConfig;
+use crate::helper::{self, ContinueForm, LispHelper};
+use rs_lisp::Interpreter;
+use rustyline::config::{ColorMode, Config};
+use rustyline::error::ReadlineError;
+use rustyline::{Editor, EventHandler, KeyCode, KeyEvent, Modifiers};
+use std::fs;
+use std::io::{self, IsTerminal};
+
+// From the Rustyline README
+pub fn repl(interp: Interpreter, config: ReplConfig) {
+    let editor_config = Config::builder()
+        .max_history_size(config.max_history)
+        // The helper leaves out the colors when they're turned off, but it
+        // still has to draw the continuation prompt
+        .color_mode(ColorMode::Enabled)
+        .build();
+    // Completion, hints and colors, for the names defined so far
+    let mut rl = Editor::<LispHelper>::with_config(editor_config);
+    rl.set_helper(Some(LispHelper::new(
+        &interp,
+        &config.continuation_prompt,
+        config.color,
+    )));
+    let continue_form = ContinueForm::new(&config.continuation_prompt);
+    rl.bind_sequence(
+        KeyEvent(KeyCode::Enter, Modifiers::NONE),
+        EventHandler::Conditional(Box::new(continue_form)),
+    );
+    let mut session = Session::new(interp);
+
+    if let Some(history_file) = &config.history_file {
+        if rl.load_history(history_file).is_err() {
+            println!("No previous history.");
+        }
+    }
+
+    let mut exit_code = None;
+    if let Some(init_file) = &config.init_file {
+        exit_code = session.run_init_file(init_file);
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(session.interp());
+        }
+    }
+
+    // The interpreter's environment lasts for the whole REPL session. The
+    // helper's validator keeps the editor going until the forms typed are
+    // complete, so a define can be typed over several lines, or a whole file
+    // pasted in.
+    let piped = !io::stdin().is_terminal();
+    while exit_code.is_none() {
+        let entry = if piped {
+            read_piped_entry(&mut rl)
+        } else {
+            rl.readline(&config.prompt)
+        };
+        match entry {
+            // Commands to the REPL itself start with a comma
+            Ok(line) if line.trim_start().starts_with(',') => {
+                rl.add_history_entry(line.trim());
+                exit_code = session.command(&line);
+                if let Some(helper) = rl.helper_mut() {
+                    helper.refresh(session.interp());
+                }
+            }
+            Ok(program) => {
+                if program.trim().is_empty() {
+                    continue;
+                }
+                rl.add_history_entry(program.trim_end());
+                exit_code = session.interpret(&program);
+                if let Some(helper) = rl.helper_mut() {
+                    helper.refresh(session.interp());
+                }
+            }
+            // CTRL-C throws away what's been typed
+            Err(ReadlineError::Interrupted) => println!("CTRL-C"),
+            Err(ReadlineError::Eof) => {
+                println!("CTRL-D");
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        }
+    }
+    if let Some(history_file) = &config.history_file {
+        if let Some(directory) = history_file.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        if let Err(error) = rl.save_history(history_file) {
+            eprintln!(
+                "Can't save history to {}: {}",
+                history_file.display(),
+                error
+            );
+        }
+    }
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
+}
+
+// Rustyline only validates what's typed at a terminal; from a pipe it gives
+// back one line at a time, so they're joined up here until the validator
+// would have let them through.
+fn read_piped_entry(rl: &mut Editor<LispHelper>) -> rustyline::Result<String> {
+    let mut entry = String::new();
+    loop {
+        match rl.readline("") {
+            Ok(line) => entry.push_str(&line),
+            Err(ReadlineError::Eof) if !entry.is_empty() => return Ok(entry),
+            Err(error) => return Err(error),
+        }
+        // A comma command is one line
+        let command = entry.trim_start().starts_with(',');
+        if command || !helper::needs_more(&entry) {
+            return Ok(entry);
+        }
+    }
+}
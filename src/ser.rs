@@ -0,0 +1,424 @@
+use crate::error::Error;
+use crate::list::List;
+use crate::parser::symbol_cell;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use serde::ser::{self, Serialize};
+use std::convert::TryFrom;
+
+// A serde data format for s-expressions. Any Serialize type can be written as
+// text the parser reads back:
+//
+//     #[derive(Serialize)]
+//     struct Window { title: String, size: (u32, u32), mode: Mode }
+//
+//     to_string(&window)?  =>  ((title "main") (size (640 480)) (mode Fullscreen))
+//
+// Structs and maps become association lists of (key value) entries with the
+// field names as symbols. Enum variants are tagged with their name: a unit
+// variant is just the symbol, the others are lists that start with it, like
+// (Windowed 640 480) or (Tiled (rows 2) (columns 3)). Sequences and tuples are
+// written as lists, though vectors read back as sequences too. None and () are
+// #nil, and Some(x) is just x.
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    Ok(to_sexpression(value)?.write())
+}
+
+// The value as an SExpression, without going through text
+pub fn to_sexpression<T: Serialize + ?Sized>(value: &T) -> Result<SExpression, Error> {
+    value.serialize(Serializer)
+}
+
+// Serde has no symbols, so an SExpression serializes a symbol as a newtype
+// struct with this name. Other formats just see a string; ours makes a symbol.
+pub(crate) const SYMBOL_TOKEN: &str = "$rs_lisp::Symbol";
+
+fn symbol(name: &str) -> SExpression {
+    SExpression::Cell(symbol_cell(name))
+}
+
+fn list(items: Vec<SExpression>) -> SExpression {
+    SExpression::List(List::make_from_sexps(items))
+}
+
+// A list that starts with the variant name when there is one
+fn tagged(tag: Option<SExpression>, items: Vec<SExpression>) -> SExpression {
+    list(tag.into_iter().chain(items).collect())
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = SExpression;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, value: bool) -> Result<SExpression, Error> {
+        Ok(SExpression::Cell(Cell::Bool(value)))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<SExpression, Error> {
+        Ok(SExpression::Cell(Cell::Int(value)))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<SExpression, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    // Integers are i64 inside the interpreter
+    fn serialize_u64(self, value: u64) -> Result<SExpression, Error> {
+        match i64::try_from(value) {
+            Ok(value) => self.serialize_i64(value),
+            Err(_) => Err(Error::Data(format!("{} is too big for an integer", value))),
+        }
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<SExpression, Error> {
+        self.serialize_f64(f64::from(value))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<SExpression, Error> {
+        Ok(SExpression::Cell(Cell::Flt(value)))
+    }
+
+    // There are no characters yet, so a char is a one-character string
+    fn serialize_char(self, value: char) -> Result<SExpression, Error> {
+        self.serialize_str(&value.to_string())
+    }
+
+    fn serialize_str(self, value: &str) -> Result<SExpression, Error> {
+        Ok(SExpression::Cell(Cell::Str(value.to_string())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<SExpression, Error> {
+        let bytes = value
+            .iter()
+            .map(|byte| SExpression::Cell(Cell::Int(i64::from(*byte))))
+            .collect();
+        Ok(list(bytes))
+    }
+
+    fn serialize_none(self) -> Result<SExpression, Error> {
+        Ok(SExpression::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<SExpression, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<SExpression, Error> {
+        Ok(SExpression::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<SExpression, Error> {
+        Ok(SExpression::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<SExpression, Error> {
+        Ok(symbol(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<SExpression, Error> {
+        let exp = value.serialize(self)?;
+        match exp {
+            SExpression::Cell(Cell::Str(ref symbol_name)) if name == SYMBOL_TOKEN => {
+                Ok(symbol(symbol_name))
+            }
+            _ => Ok(exp),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<SExpression, Error> {
+        Ok(list(vec![symbol(variant), value.serialize(self)?]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            tag: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            tag: Some(symbol(variant)),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer {
+            tag: None,
+            entries: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer {
+            tag: Some(symbol(variant)),
+            entries: Vec::with_capacity(len),
+        })
+    }
+} // impl Serializer
+
+struct SeqSerializer {
+    tag: Option<SExpression>,
+    items: Vec<SExpression>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.items))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.items))
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.items))
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.items))
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<SExpression>,
+    key: Option<SExpression>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Data("Map value without a key".to_string()))?;
+        self.entries
+            .push(list(vec![key, value.serialize(Serializer)?]));
+        Ok(())
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(list(self.entries))
+    }
+}
+
+struct StructSerializer {
+    tag: Option<SExpression>,
+    entries: Vec<SExpression>,
+}
+
+impl StructSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let entry = list(vec![symbol(key), value.serialize(Serializer)?]);
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.entries))
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = SExpression;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<SExpression, Error> {
+        Ok(tagged(self.tag, self.entries))
+    }
+}
+
+// Interpreter values serialize as the data they hold, so they can go out in
+// any serde format, or back in through this one. Procedures can't.
+impl Serialize for SExpression {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        match self {
+            SExpression::Null | SExpression::Cell(Cell::JsonNull) => serializer.serialize_unit(),
+            SExpression::List(items) => {
+                let items = items.to_vec();
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in &items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            SExpression::Cell(cell) => match cell {
+                Cell::Int(value) => serializer.serialize_i64(*value),
+                Cell::Flt(value) => serializer.serialize_f64(*value),
+                Cell::Str(value) => serializer.serialize_str(value),
+                Cell::Bool(value) => serializer.serialize_bool(*value),
+                Cell::Vector(items) => {
+                    let items = items.borrow();
+                    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                    for item in items.iter() {
+                        seq.serialize_element(item)?;
+                    }
+                    seq.end()
+                }
+                Cell::HashTable(table) => {
+                    let entries = table.borrow().entries();
+                    let mut map = serializer.serialize_map(Some(entries.len()))?;
+                    for (key, value) in &entries {
+                        map.serialize_entry(key, value)?;
+                    }
+                    map.end()
+                }
+                Cell::Lambda(..) | Cell::Template(_) | Cell::Native(_) => Err(ser::Error::custom(
+                    format!("Can't serialize a procedure: {}", cell.print()),
+                )),
+                Cell::Port(_) | Cell::Eof | Cell::Environment(_) | Cell::Condition(_) => Err(
+                    ser::Error::custom(format!("Can't serialize {}", cell.print())),
+                ),
+                // Symbols, and built-in names like car that were read as code
+                _ => serializer.serialize_newtype_struct(SYMBOL_TOKEN, &cell.print()),
+            },
+        }
+    }
+}
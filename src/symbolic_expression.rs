@@ -17,21 +17,41 @@ impl SExpression {
         }
     }
 
-    pub fn as_number(self) -> Result<Cell, String> {
+    // Like print, but as text the parser reads back to the same value: strings
+    // are quoted and escaped, booleans are #t and #f, and Null is #nil.
+    pub fn write(&self) -> String {
+        match self {
+            SExpression::Cell(Cell::Str(value)) => write_string(value),
+            SExpression::Cell(Cell::Bool(true)) => String::from("#t"),
+            SExpression::Cell(Cell::Bool(false)) => String::from("#f"),
+            SExpression::Cell(Cell::Vector(items)) => {
+                let written: Vec<String> = items.borrow().iter().map(|item| item.write()).collect();
+                format!("#({})", written.join(" "))
+            }
+            SExpression::Cell(cell) => cell.print(),
+            SExpression::List(list) => {
+                let items: Vec<String> = list.to_vec().iter().map(|item| item.write()).collect();
+                format!("({})", items.join(" "))
+            }
+            SExpression::Null => String::from("#nil"),
+        }
+    }
+
+    pub fn as_number(&self) -> Result<Cell, String> {
         match self {
             SExpression::Cell(cell) => cell.eval_as_number(),
             _ => Err(String::from("Not a number type")),
         }
     }
 
-    pub fn as_bool(self) -> Result<Cell, String> {
+    pub fn as_bool(&self) -> Result<Cell, String> {
         match self {
             SExpression::Cell(cell) => cell.eval_as_bool(),
             _ => Err(String::from("Not a boolean type")),
         }
     }
 
-    pub fn as_rust_bool(self) -> Result<bool, String> {
+    pub fn as_rust_bool(&self) -> Result<bool, String> {
         let bool_cell = self.as_bool()?;
         match bool_cell {
             Cell::Bool(truth) => Ok(truth),
@@ -39,3 +59,19 @@ impl SExpression {
         }
     }
 } // impl SExpression
+
+fn write_string(value: &str) -> String {
+    let mut text = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            _ => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
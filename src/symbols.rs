@@ -0,0 +1,39 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Every symbol name is interned once, when it's read, and gets a number that
+// is stored in Cell::Symbol alongside the name. Comparing symbols or looking
+// them up in an environment then only needs the number; the name is kept for
+// printing and error messages.
+struct SymbolTable {
+    numbers_by_name: HashMap<String, i32>,
+    names: Vec<String>,
+}
+
+thread_local! {
+    static SYMBOL_TABLE: RefCell<SymbolTable> = RefCell::new(SymbolTable {
+        numbers_by_name: HashMap::new(),
+        names: Vec::new(),
+    });
+}
+
+// The number for a symbol name, adding the name to the table the first time it's seen
+pub fn intern(name: &str) -> i32 {
+    SYMBOL_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        if let Some(number) = table.numbers_by_name.get(name) {
+            return *number;
+        }
+        let number = table.names.len() as i32;
+        table.names.push(name.to_string());
+        table.numbers_by_name.insert(name.to_string(), number);
+        number
+    })
+}
+
+pub fn symbol_name(number: i32) -> String {
+    SYMBOL_TABLE.with(|table| match table.borrow().names.get(number as usize) {
+        Some(name) => name.clone(),
+        None => format!("<unknown symbol {}>", number),
+    })
+}
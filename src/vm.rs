@@ -0,0 +1,253 @@
+use crate::bytecode::{Chunk, Op};
+use crate::compiler::{compile_top_level, lambda_code};
+use crate::interpreter::Environment;
+use crate::primitives::Cell;
+use crate::symbolic_expression::SExpression;
+use std::rc::Rc;
+
+// A stack machine that runs the bytecode from compiler.rs. Values live on one
+// stack shared by every call; each call gets a CallFrame with its own code,
+// position in that code and environment. The environments are the same
+// frames the tree walker uses, so procedures, closures and definitions can
+// be passed back and forth between the two.
+//
+// Core functions like hash-table-walk that call procedures they're given run
+// those procedures with the tree walker.
+
+struct CallFrame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    env: Environment,
+    // Where this call's values start on the stack
+    base: usize,
+}
+
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<SExpression>,
+    frames: Vec<CallFrame>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::default()
+    }
+
+    // Compile and run one expression in the given environment
+    pub fn evaluate(
+        &mut self,
+        exp: &SExpression,
+        env: &Environment,
+    ) -> Result<SExpression, String> {
+        let chunk = compile_top_level(exp, env.context())?;
+        self.run(Rc::new(chunk), env.clone())
+    }
+
+    // Call a procedure value with arguments that have already been evaluated
+    pub fn apply(
+        &mut self,
+        procedure: SExpression,
+        values_from_args: Vec<SExpression>,
+        env: &Environment,
+    ) -> Result<SExpression, String> {
+        match procedure {
+            SExpression::Cell(Cell::Lambda(template, defining_env)) => {
+                let chunk = lambda_code(&template, defining_env.context())?;
+                let call_env =
+                    Environment::make_call_frame(&template, &defining_env, values_from_args)?;
+                self.run(chunk, call_env)
+            }
+            _ => env.clone().apply_procedure(procedure, values_from_args),
+        }
+    }
+
+    pub fn run(&mut self, chunk: Rc<Chunk>, env: Environment) -> Result<SExpression, String> {
+        let depth = self.frames.len();
+        self.frames.push(CallFrame {
+            chunk,
+            ip: 0,
+            env,
+            base: self.stack.len(),
+        });
+        let result = self.execute(depth);
+        if result.is_err() {
+            // Throw away whatever the failed code left behind
+            if let Some(frame) = self.frames.get(depth) {
+                self.stack.truncate(frame.base);
+            }
+            self.frames.truncate(depth);
+        }
+        result
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("No call frame")
+    }
+
+    fn pop(&mut self) -> SExpression {
+        self.stack.pop().expect("Value stack underflow")
+    }
+
+    fn pop_values(&mut self, count: usize) -> Vec<SExpression> {
+        let start = self.stack.len() - count;
+        self.stack.split_off(start)
+    }
+
+    // Run until the frame at 'depth' returns
+    fn execute(&mut self, depth: usize) -> Result<SExpression, String> {
+        loop {
+            let frame = self.frame();
+            let op = frame.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Constant(index) => {
+                    let value = self.frame().chunk.constants[index].clone();
+                    self.stack.push(value);
+                }
+                Op::GetLocal(depth, slot, symbol) => {
+                    let value = self
+                        .frame()
+                        .env
+                        .get_definition_by_address(depth, slot, symbol)?;
+                    self.stack.push(value);
+                }
+                Op::SetLocal(depth, slot, symbol) => {
+                    let value = self.pop();
+                    let result = self
+                        .frame()
+                        .env
+                        .set_by_address(depth, slot, symbol, value)?;
+                    self.stack.push(result);
+                }
+                Op::DefineLocal(slot, symbol) => {
+                    let value = self.pop();
+                    self.frame().env.define_local(slot, symbol, value)?;
+                }
+                Op::GetGlobal(symbol) => {
+                    let value = self.frame().env.get_definition_by_symbol(symbol)?;
+                    self.stack.push(value);
+                }
+                Op::SetGlobal(symbol) => {
+                    let value = self.pop();
+                    let result = self.frame().env.set_by_symbol(symbol, value)?;
+                    self.stack.push(result);
+                }
+                Op::DefineGlobal(symbol) => {
+                    let value = self.pop();
+                    self.frame().env.define(symbol, value)?;
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Jump(target) => self.frame().ip = target,
+                Op::JumpIfFalse(target) => {
+                    if !self.pop().as_rust_bool()? {
+                        self.frame().ip = target;
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if self.pop().as_rust_bool()? {
+                        self.frame().ip = target;
+                    }
+                }
+                Op::JumpUnlessProcedure(target) => {
+                    if !matches!(
+                        self.stack.last(),
+                        Some(SExpression::Cell(Cell::Lambda(_, _)))
+                    ) {
+                        self.frame().ip = target;
+                    }
+                }
+                Op::Call(argc) => self.call(argc, false)?,
+                Op::TailCall(argc) => self.call(argc, true)?,
+                Op::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("No call frame");
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                Op::MakeClosure(index) => {
+                    let frame = self.frame();
+                    let template = match &frame.chunk.constants[index] {
+                        SExpression::Cell(Cell::Template(template)) => template.clone(),
+                        other => {
+                            return Err(format!("Can't make a closure from {}", other.print()))
+                        }
+                    };
+                    let closure = Cell::Lambda(template, frame.env.clone());
+                    self.stack.push(SExpression::Cell(closure));
+                }
+                Op::Arithmetic(operator, argc) => {
+                    let values = self.pop_values(argc);
+                    self.stack.push(operator.apply(&values)?);
+                }
+                Op::Compare(operator, argc) => {
+                    let values = self.pop_values(argc);
+                    self.stack.push(operator.compare(&values)?);
+                }
+                Op::Output(argc, newline) => {
+                    let values = self.pop_values(argc);
+                    let value = self.frame().env.output_values(&values, newline)?;
+                    self.stack.push(value);
+                }
+                Op::Input(argc) => {
+                    let prompt = self.pop_values(argc);
+                    let value = self.frame().env.input_line(&prompt)?;
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    // The procedure is on the stack below its arguments. Calling a lambda
+    // pushes a frame for its body; a tail call replaces the caller's frame
+    // with it so loops written as recursion don't grow the stack.
+    fn call(&mut self, argc: usize, tail: bool) -> Result<(), String> {
+        let args = self.pop_values(argc);
+        let procedure = self.pop();
+        match procedure {
+            SExpression::Cell(Cell::Lambda(template, defining_env)) => {
+                let chunk = lambda_code(&template, defining_env.context())?;
+                let env = Environment::make_call_frame(&template, &defining_env, args)?;
+                if tail {
+                    let finished = self.frames.pop().expect("No call frame");
+                    self.stack.truncate(finished.base);
+                }
+                self.frames.push(CallFrame {
+                    chunk,
+                    ip: 0,
+                    env,
+                    base: self.stack.len(),
+                });
+                Ok(())
+            }
+            SExpression::Cell(Cell::Core(func)) => {
+                let mut env = self.frame().env.clone();
+                let result = env.call_core_func(func, args)?;
+                self.stack.push(result);
+                Ok(())
+            }
+            SExpression::Cell(Cell::Native(function)) => {
+                let result = function.call(&args)?;
+                self.stack.push(result);
+                Ok(())
+            }
+            SExpression::Cell(Cell::Op(operator)) => {
+                self.stack.push(operator.apply(&args)?);
+                Ok(())
+            }
+            SExpression::Cell(Cell::Logical(operator)) => {
+                self.stack.push(operator.compare(&args)?);
+                Ok(())
+            }
+            _ => Err(format!(
+                "Can't evaluate as function: {}",
+                &procedure.print()
+            )),
+        }
+    }
+} // impl Vm
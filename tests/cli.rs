@@ -0,0 +1,96 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn rs_lisp(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rs_lisp"))
+        .args(args)
+        .env("HOME", env::temp_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn script(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rs_lisp_cli_{}_{}.scm", std::process::id(), name));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn expressions_on_the_command_line() {
+    let output = rs_lisp(&["-e", "(define x 4)", "-e", "(display (* x x))"], "");
+    assert_eq!(stdout(&output), "16");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = rs_lisp(&["--print-results", "-e", "(+ 1 2) 'done"], "");
+    assert_eq!(stdout(&output), "3\ndone\n");
+}
+
+#[test]
+fn programs_from_standard_input() {
+    let output = rs_lisp(&[], "(display \"piped\")");
+    assert_eq!(stdout(&output), "piped");
+    let output = rs_lisp(&["--print-results", "-"], "(* 6 7)");
+    assert_eq!(stdout(&output), "42\n");
+}
+
+#[test]
+fn scripts_get_their_arguments() {
+    let path = script(
+        "arguments",
+        "#!/usr/bin/env rs_lisp\n(display (rest (command-line)))\n",
+    );
+    let file = path.display().to_string();
+    // Options after the file name belong to the script
+    let output = rs_lisp(&[&file, "one", "--two"], "");
+    let _ = fs::remove_file(&path);
+    assert_eq!(stdout(&output), "(one --two)");
+
+    let output = rs_lisp(&["-e", "(display (command-line))", "a", "b"], "");
+    assert_eq!(stdout(&output), "(-e a b)");
+}
+
+#[test]
+fn errors_give_a_failing_exit_code() {
+    let output = rs_lisp(&["-e", "(display 1) (no-such-function)"], "");
+    assert_eq!(stdout(&output), "1");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no-such-function"));
+
+    let output = rs_lisp(&[], "(unclosed");
+    assert_eq!(output.status.code(), Some(1));
+    let output = rs_lisp(&["/no/such/file.scm"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = rs_lisp(&["--no-such-option"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = rs_lisp(&["-e", "(exit 6)"], "");
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn the_repl_can_follow_the_program() {
+    let path = script("interactive", "(define (double x) (* 2 x))");
+    let file = path.display().to_string();
+    let history = env::temp_dir().join(format!("rs_lisp_cli_{}_history", std::process::id()));
+    let history = history.display().to_string();
+    let output = rs_lisp(&["-i", "--history", &history, &file], "(double 21)\n");
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(&history);
+    assert!(stdout(&output).contains("=>  42"), "{}", stdout(&output));
+}
@@ -0,0 +1,26 @@
+// Helpers shared by the integration tests. Each test file is its own crate
+// and uses only some of them.
+#![allow(dead_code)]
+
+use rs_lisp::{Backend, Interpreter};
+
+pub fn both_backends() -> Vec<Interpreter> {
+    vec![
+        Interpreter::with_backend(Backend::TreeWalker),
+        Interpreter::with_backend(Backend::Vm),
+    ]
+}
+
+// The value of the last form, as display would show it, on both backends
+pub fn eval_both(program: &str) -> String {
+    let mut results = Vec::new();
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(backend);
+        match interp.eval_str(program) {
+            Ok(value) => results.push(value.print()),
+            Err(error) => panic!("{:?} failed: {}", backend, error),
+        }
+    }
+    assert_eq!(results[0], results[1], "the backends disagree");
+    results.remove(0)
+}
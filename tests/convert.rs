@@ -0,0 +1,175 @@
+use rs_lisp::{AlistBuilder, AlistReader, FromScheme, Interpreter, SExpression, ToScheme};
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+    label: Option<String>,
+}
+
+impl ToScheme for Point {
+    fn to_scheme(&self) -> SExpression {
+        AlistBuilder::new()
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("label", &self.label)
+            .build()
+    }
+}
+
+impl FromScheme for Point {
+    fn from_scheme(exp: &SExpression) -> Result<Self, String> {
+        let fields = AlistReader::new(exp)?;
+        Ok(Point {
+            x: fields.field("x")?,
+            y: fields.field("y")?,
+            label: fields.optional_field::<Option<String>>("label")?.flatten(),
+        })
+    }
+}
+
+#[test]
+fn numbers_round_trip() {
+    assert_eq!(i64::from_scheme(&42i64.to_scheme()), Ok(42));
+    assert_eq!(u8::from_scheme(&7u8.to_scheme()), Ok(7));
+    assert_eq!(f64::from_scheme(&2.5f64.to_scheme()), Ok(2.5));
+    assert_eq!(f64::from_scheme(&3i32.to_scheme()), Ok(3.0));
+}
+
+#[test]
+fn big_unsigned_integers_wrap() {
+    // There's no failing in to_scheme, so u64s past i64::MAX wrap around
+    assert_eq!(i64::from_scheme(&u64::MAX.to_scheme()), Ok(-1));
+    assert_eq!(
+        i64::from_scheme(&(i64::MAX as u64 + 1).to_scheme()),
+        Ok(i64::MIN)
+    );
+    assert!(u64::from_scheme(&u64::MAX.to_scheme()).is_err());
+    assert_eq!(
+        u64::from_scheme(&(i64::MAX as u64).to_scheme()),
+        Ok(i64::MAX as u64)
+    );
+    // serde refuses them instead
+    assert!(rs_lisp::to_sexpression(&u64::MAX).is_err());
+}
+
+#[test]
+fn integers_from_whole_floats() {
+    let mut interp = Interpreter::new();
+    // Literals in code are floats
+    let value = interp.eval_str("(+ 40 2)").unwrap();
+    assert_eq!(i32::from_scheme(&value), Ok(42));
+    let fraction = interp.eval_str("(/ 1 2)").unwrap();
+    assert!(i32::from_scheme(&fraction).is_err());
+    assert!(u8::from_scheme(&300.to_scheme()).is_err());
+    assert!(u32::from_scheme(&(-1).to_scheme()).is_err());
+}
+
+#[test]
+fn strings_and_booleans() {
+    assert_eq!("hi".to_scheme().print(), "hi");
+    assert_eq!(String::from_scheme(&"hi".to_scheme()), Ok("hi".to_string()));
+    assert_eq!(bool::from_scheme(&true.to_scheme()), Ok(true));
+    assert!(bool::from_scheme(&1.to_scheme()).is_err());
+    assert!(String::from_scheme(&1.to_scheme()).is_err());
+}
+
+#[test]
+fn vectors_options_and_tuples() {
+    let numbers = vec![1, 2, 3].to_scheme();
+    assert_eq!(numbers.print(), "(1 2 3)");
+    assert_eq!(Vec::<i64>::from_scheme(&numbers), Ok(vec![1, 2, 3]));
+
+    assert!(matches!(None::<i64>.to_scheme(), SExpression::Null));
+    assert_eq!(Option::<i64>::from_scheme(&SExpression::Null), Ok(None));
+    assert_eq!(Option::<i64>::from_scheme(&5.to_scheme()), Ok(Some(5)));
+
+    let pair = ("a".to_string(), 1).to_scheme();
+    assert_eq!(pair.print(), "(a 1)");
+    assert_eq!(
+        <(String, i64)>::from_scheme(&pair),
+        Ok(("a".to_string(), 1))
+    );
+    assert!(<(String, i64, bool)>::from_scheme(&pair).is_err());
+}
+
+#[test]
+fn hash_maps_become_hash_tables() {
+    let mut scores = HashMap::new();
+    scores.insert("ann".to_string(), 3);
+    scores.insert("bob".to_string(), 5);
+
+    let mut interp = Interpreter::new();
+    interp.set_global("scores", scores.to_scheme());
+    let bob = interp.eval_str("(hash-table-ref scores \"bob\")").unwrap();
+    assert_eq!(i64::from_scheme(&bob), Ok(5));
+
+    let back: HashMap<String, i64> =
+        FromScheme::from_scheme(&interp.get_global("scores").unwrap()).unwrap();
+    assert_eq!(back, scores);
+}
+
+#[test]
+fn hash_maps_from_association_lists() {
+    let mut interp = Interpreter::new();
+    let alist = interp
+        .eval_str("(list (cons 'a 1) (cons 'b (list 2 3)))")
+        .unwrap();
+    let map: HashMap<String, SExpression> = FromScheme::from_scheme(&alist).unwrap();
+    assert_eq!(map["a"].print(), "1");
+    assert_eq!(map["b"].print(), "(2 3)");
+}
+
+#[test]
+fn structs_as_association_lists() {
+    let point = Point {
+        x: 1.5,
+        y: -2.0,
+        label: Some("start".to_string()),
+    };
+    let exp = point.to_scheme();
+    assert_eq!(exp.print(), "((x 1.5) (y -2) (label start))");
+    assert_eq!(Point::from_scheme(&exp), Ok(point));
+
+    let mut interp = Interpreter::new();
+    let unlabeled = interp.eval_str("(list (list 'y 4) (list 'x 3))").unwrap();
+    assert_eq!(
+        Point::from_scheme(&unlabeled),
+        Ok(Point {
+            x: 3.0,
+            y: 4.0,
+            label: None
+        })
+    );
+    let missing = interp.eval_str("(list (list 'x 3))").unwrap();
+    assert!(Point::from_scheme(&missing).unwrap_err().contains("y"));
+}
+
+#[test]
+fn typed_native_functions() {
+    let mut interp = Interpreter::new();
+    interp.register_typed_fn("hypot", |(a, b): (f64, f64)| Ok((a * a + b * b).sqrt()));
+    interp.register_typed_fn("repeat", |(text, times): (String, usize)| {
+        Ok(vec![text; times])
+    });
+    interp.register_typed_fn("midpoint", |(p, q): (Point, Point)| {
+        Ok(Point {
+            x: (p.x + q.x) / 2.0,
+            y: (p.y + q.y) / 2.0,
+            label: None,
+        })
+    });
+
+    assert_eq!(interp.eval_str("(hypot 3 4)").unwrap().print(), "5");
+    assert_eq!(
+        interp.eval_str("(repeat \"ab\" 3)").unwrap().print(),
+        "(ab ab ab)"
+    );
+    assert!(interp.eval_str("(repeat \"ab\" 1.5)").is_err());
+    assert!(interp.eval_str("(hypot 3)").is_err());
+    let mid = interp
+        .eval_str("(midpoint (list (list 'x 0) (list 'y 0)) (list (list 'x 2) (list 'y 4)))")
+        .unwrap();
+    assert_eq!(mid.print(), "((x 1) (y 2) (label Null))");
+}
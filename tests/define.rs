@@ -0,0 +1,88 @@
+mod common;
+
+use common::both_backends;
+use rs_lisp::{Error, Interpreter, Redefinition};
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn top_level_define_rebinds() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str("(define (area r) (* r 3)) (define (report r) (area r))")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(report 2)"), "6");
+        // Fixing a function is seen by everything that calls it
+        interp.eval_str("(define (area r) (* r r 3.14))").unwrap();
+        assert_eq!(eval(&mut interp, "(report 2)"), "12.56");
+        interp
+            .eval_str("(define limit 1) (define limit 2)")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "limit"), "2");
+    }
+}
+
+#[test]
+fn strict_mode_refuses_redefinition() {
+    for mut interp in both_backends() {
+        interp.set_redefinition(Redefinition::Error);
+        interp.eval_str("(define limit 1)").unwrap();
+        match interp.eval_str("(define limit 2)") {
+            Err(Error::Eval(message)) => assert!(message.contains("already defined")),
+            other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+        }
+        assert_eq!(eval(&mut interp, "limit"), "1");
+    }
+}
+
+#[test]
+fn the_redefinition_setting_is_per_interpreter() {
+    let mut strict = Interpreter::new();
+    strict.set_redefinition(Redefinition::Error);
+    let mut relaxed = Interpreter::new();
+    for interp in [&mut strict, &mut relaxed] {
+        interp.eval_str("(define limit 1)").unwrap();
+    }
+    assert!(strict.eval_str("(define limit 2)").is_err());
+    assert_eq!(eval(&mut relaxed, "(define limit 2) limit"), "2");
+    // Starting over keeps the setting
+    strict.reset();
+    strict.eval_str("(define limit 1)").unwrap();
+    assert!(strict.eval_str("(define limit 2)").is_err());
+}
+
+#[test]
+fn internal_defines_follow_letrec_star() {
+    for mut interp in both_backends() {
+        // Definitions in a body can refer to each other
+        interp
+            .eval_str(
+                "(define (parity n)
+                   (begin
+                     (define (is-even? k) (if (= k 0) #t (is-odd? (- k 1))))
+                     (define (is-odd? k) (if (= k 0) #f (is-even? (- k 1))))
+                     (if (is-even? n) 'even 'odd)))",
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(parity 7)"), "odd");
+
+        // but only once they've been evaluated
+        interp
+            .eval_str("(define (too-soon) (begin (define a b) (define b 1) a))")
+            .unwrap();
+        match interp.eval_str("(too-soon)") {
+            Err(Error::Eval(message)) => {
+                assert!(message.contains("before its definition"), "{}", message)
+            }
+            other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+        }
+
+        // and each name only once
+        interp
+            .eval_str("(define (twice) (begin (define a 1) (define a 2) a))")
+            .unwrap();
+        assert!(interp.eval_str("(twice)").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::eval_both;
+use rs_lisp::{Cell, Interpreter, SExpression};
+
+#[test]
+fn eq_compares_lists_by_identity() {
+    assert_eq!(
+        eval_both("(define x (list 1 2)) (list (eq? x x) (eq? x (list 1 2)))"),
+        "(true false)"
+    );
+    assert_eq!(
+        eval_both("(list (eq? 'a 'a) (eq? 'a 'b) (eq? #t #t) (eq? \"ab\" \"ab\"))"),
+        "(true false true true)"
+    );
+}
+
+#[test]
+fn eqv_compares_numbers_by_value() {
+    assert_eq!(
+        eval_both("(list (eqv? 2 2) (eqv? 2 3) (eqv? (vector 1) (vector 1)))"),
+        "(true false false)"
+    );
+    // Counts are integers and literals are floats, but they're the same number
+    assert_eq!(
+        eval_both(
+            "(list (eqv? (vector-length (vector 1 2)) 2) (memv (vector-length (vector)) '(1 0)))"
+        ),
+        "(true (0))"
+    );
+    let mut interp = Interpreter::new();
+    interp.set_global("three", SExpression::Cell(Cell::Int(3)));
+    assert_eq!(interp.eval_str("(eqv? three 3.0)").unwrap().print(), "true");
+}
+
+#[test]
+fn equal_compares_structure() {
+    assert_eq!(
+        eval_both(
+            "(list (equal? (list 1 (list 2 \"x\")) (list 1 (list 2 \"x\"))) \
+                   (equal? (vector 1 (list 2)) (vector 1 (list 2))) \
+                   (equal? (list 1 2) (list 1 2 3)) \
+                   (equal? 'a \"a\"))"
+        ),
+        "(true true false false)"
+    );
+}
+
+#[test]
+fn member_and_assoc_use_the_matching_predicate() {
+    assert_eq!(
+        eval_both("(list (member (list 1) (list (list 1) 2)) (memq (list 1) (list (list 1) 2)))"),
+        "(((1) 2) false)"
+    );
+    assert_eq!(
+        eval_both("(list (memq 'c '(a b c d)) (memv 5 '(1 2)))"),
+        "((c d) false)"
+    );
+    assert_eq!(
+        eval_both(
+            "(list (assoc \"b\" (list (list \"a\" 1) (list \"b\" 2))) \
+                   (assq 'x '((x 1))) \
+                   (assv 2 '((1 one) (2 two))))"
+        ),
+        "((b 2) (x 1) (2 two))"
+    );
+}
@@ -0,0 +1,101 @@
+use rs_lisp::{Backend, Interpreter};
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn eval_runs_data_as_code() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp.eval_str("(define x 10)").unwrap();
+        assert_eq!(
+            eval(&mut interp, "(eval '(+ x 1) (interaction-environment))"),
+            "11"
+        );
+        // Definitions made by eval are there afterwards
+        interp
+            .eval_str("(eval (read \"(define (square n) (* n n))\"))")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(square 5)"), "25");
+        assert_eq!(
+            eval(&mut interp, "(eval (list '* 'x 2) (current-environment))"),
+            "20"
+        );
+    }
+}
+
+#[test]
+fn environments_keep_definitions_apart() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(
+                "(define x 1)
+                 (define sandbox (make-environment))
+                 (eval '(define x 2) sandbox)
+                 (eval '(define (get-x) x) sandbox)",
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "x"), "1");
+        assert_eq!(eval(&mut interp, "(eval '(get-x) sandbox)"), "2");
+        assert_eq!(
+            eval(
+                &mut interp,
+                "(environment-bound? (interaction-environment) 'get-x)"
+            ),
+            "false"
+        );
+        // Nothing from the program leaks into a fresh environment
+        interp.eval_str("(define y 3)").unwrap();
+        assert!(interp
+            .eval_str("(eval 'y (scheme-report-environment 5))")
+            .is_err());
+    }
+}
+
+#[test]
+fn child_environments_see_their_parents() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str(
+            "(define base 10)
+             (define config (make-environment (interaction-environment)))
+             (environment-define! config 'scale 3)",
+        )
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(eval '(* base scale) config)"), "30");
+    assert_eq!(
+        eval(&mut interp, "(environment-bound? config 'base)"),
+        "true"
+    );
+    assert_eq!(eval(&mut interp, "(environment-ref config 'scale)"), "3");
+    assert_eq!(
+        eval(&mut interp, "(environment-bindings config)"),
+        "((scale 3))"
+    );
+    // Redefining is allowed, unlike with define
+    interp
+        .eval_str("(environment-define! config 'scale 4)")
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(environment-ref config 'scale)"), "4");
+    assert!(interp
+        .eval_str("(environment-ref config 'missing)")
+        .is_err());
+    assert!(interp.eval_str("(eval 1 'not-an-environment)").is_err());
+}
+
+#[test]
+fn env_lists_the_current_scope() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str("(define (f a) (begin (define b (* a 2)) (env)))")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(f 1)"), "((a 1) (b 2))");
+        assert_eq!(
+            eval(&mut interp, "(environment? (current-environment))"),
+            "true"
+        );
+    }
+}
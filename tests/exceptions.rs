@@ -0,0 +1,190 @@
+mod common;
+
+use common::eval_both;
+use rs_lisp::{Backend, Cell, Error, Interpreter, SExpression};
+
+#[test]
+fn guard_catches_what_is_raised() {
+    assert_eq!(
+        eval_both("(guard (e (#t (list 'caught e))) (raise 'oops))"),
+        "(caught oops)"
+    );
+    assert_eq!(
+        eval_both("(guard (e ((equal? e 1) 'one) ((equal? e 2) 'two)) (raise 2))"),
+        "two"
+    );
+    assert_eq!(
+        eval_both("(guard (e (else 'fallback)) (raise 'anything))"),
+        "fallback"
+    );
+    // Nothing goes wrong, so the body's value comes through
+    assert_eq!(eval_both("(guard (e (#t 'no)) (+ 1 2) (* 3 4))"), "12");
+}
+
+#[test]
+fn guard_clauses_can_pass_the_test_value_on() {
+    let program = "(define (lookup key) \
+                     (guard (e ((assq 'a e) => rest) ((assq 'b e))) \
+                       (raise (list (list key 42)))))";
+    assert_eq!(eval_both(&format!("{} (lookup 'a)", program)), "(42)");
+    assert_eq!(eval_both(&format!("{} (lookup 'b)", program)), "(b 42)");
+}
+
+#[test]
+fn guard_raises_again_when_no_clause_matches() {
+    assert_eq!(
+        eval_both(
+            "(guard (e (#t (list 'outer e))) \
+               (guard (e ((equal? e 1) 'one)) (raise 'not-one)))"
+        ),
+        "(outer not-one)"
+    );
+    let mut interp = Interpreter::new();
+    match interp.eval_str("(guard (e ((equal? e 1) 'one)) (raise 2))") {
+        Err(Error::Eval(message)) => assert_eq!(message, "Uncaught exception: 2"),
+        _ => panic!("expected the exception to get out"),
+    }
+}
+
+#[test]
+fn guard_works_inside_procedures() {
+    assert_eq!(
+        eval_both(
+            "(define (safe-divide x y) \
+               (guard (e ((error-object? e) (list x (error-object-message e)))) \
+                 (if (= y 0) (error \"Division by zero\") (/ x y)))) \
+             (list (safe-divide 10 2) (safe-divide 1 0))"
+        ),
+        "(5 (1 Division by zero))"
+    );
+}
+
+#[test]
+fn error_objects_have_a_message_and_irritants() {
+    assert_eq!(
+        eval_both(
+            "(guard (e ((error-object? e) \
+                        (list (error-object-message e) (error-object-irritants e)))) \
+               (error \"Bad thing:\" 1 'two))"
+        ),
+        "(Bad thing: (1 two))"
+    );
+    assert_eq!(eval_both("(error-object? 'oops)"), "false");
+
+    let mut interp = Interpreter::new();
+    match interp.eval_str("(error \"Something bad:\" 42 \"text\")") {
+        Err(Error::Eval(message)) => assert_eq!(message, "Something bad: 42 \"text\""),
+        _ => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn interpreter_errors_are_error_objects() {
+    assert_eq!(
+        eval_both("(guard (e ((error-object? e) (error-object-message e))) (no-such-thing 1))"),
+        "Symbol no-such-thing not defined."
+    );
+    assert_eq!(
+        eval_both("(guard (e ((error-object? e) (error-object-irritants e))) (first 1))"),
+        "()"
+    );
+}
+
+#[test]
+fn arity_errors_are_caught_too() {
+    assert_eq!(
+        eval_both("(guard (e ((error-object? e) (error-object-message e))) (cons 1))"),
+        "Too few arguments to 'cons': needs at least 2, got 1."
+    );
+    for program in ["(first)", "(rest)", "(cons 1 2 3)"] {
+        assert_eq!(
+            eval_both(&format!("(guard (e (#t 'caught)) {})", program)),
+            "caught"
+        );
+    }
+}
+
+#[test]
+fn raise_continuable_carries_on_with_the_handlers_value() {
+    assert_eq!(
+        eval_both(
+            "(with-exception-handler (lambda (c) 42) \
+               (lambda () (+ (raise-continuable 'c) 1)))"
+        ),
+        "43"
+    );
+    // The handler runs with the handlers further out
+    assert_eq!(
+        eval_both(
+            "(with-exception-handler (lambda (c) (* c 10)) \
+               (lambda () (with-exception-handler (lambda (c) (raise-continuable (+ c 1))) \
+                 (lambda () (raise-continuable 1)))))"
+        ),
+        "20"
+    );
+}
+
+#[test]
+fn a_handler_returning_from_raise_is_an_error() {
+    assert_eq!(
+        eval_both(
+            "(guard (e ((error-object? e) (error-object-message e))) \
+               (with-exception-handler (lambda (c) 'ignored) (lambda () (raise 'x))))"
+        ),
+        "The exception handler returned after a raise of x"
+    );
+    // Raising in the handler instead goes to the next one out
+    assert_eq!(
+        eval_both(
+            "(guard (e (#t (list 'outer e))) \
+               (with-exception-handler (lambda (c) (raise (list 'wrapped c))) \
+                 (lambda () (first 1))))"
+        ),
+        "(outer (wrapped #<error The 'first' function requires a list as its argument.>))"
+    );
+}
+
+#[test]
+fn exit_is_not_caught() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(backend);
+        assert!(matches!(
+            interp.eval_str("(guard (e (#t 'caught)) (exit 3))"),
+            Err(Error::Exit(3))
+        ));
+        assert!(matches!(
+            interp.eval_str("(with-exception-handler (lambda (c) 0) (lambda () (exit 4)))"),
+            Err(Error::Exit(4))
+        ));
+    }
+}
+
+#[test]
+fn an_interpreter_inside_another_has_its_own_handlers() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut outer = Interpreter::with_backend(backend);
+        // The inner interpreter's raise-continuable has no handler to go to,
+        // even while the outer one has one installed
+        outer.register_fn("run-inner", 0, move |_| {
+            let mut inner = Interpreter::with_backend(backend);
+            match inner.eval_str("(raise-continuable 'inner)") {
+                Ok(value) => Ok(value),
+                Err(_) => Ok(SExpression::Cell(Cell::Str("unhandled".to_string()))),
+            }
+        });
+        let result = outer
+            .eval_str("(with-exception-handler (lambda (c) 'outer) (lambda () (run-inner)))")
+            .unwrap();
+        assert_eq!(result.print(), "unhandled");
+        // What the inner one raises doesn't land in the outer one's guard
+        outer.register_fn("raise-inner", 0, move |_| {
+            let mut inner = Interpreter::with_backend(backend);
+            let _ = inner.eval_str("(raise 'inner)");
+            Err("failed".to_string())
+        });
+        match outer.eval_str("(guard (e (#t e)) (raise-inner))") {
+            Ok(value) => assert!(value.print().contains("failed"), "{}", value.print()),
+            Err(error) => panic!("{:?} failed: {}", backend, error),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use rs_lisp::{Backend, Cell, Error, Interpreter, SExpression};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+fn exit_code(interp: &mut Interpreter, program: &str) -> Option<i32> {
+    match interp.eval_str(program) {
+        Err(Error::Exit(code)) => Some(code),
+        _ => None,
+    }
+}
+
+#[test]
+fn exit_unwinds_with_its_code() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        assert_eq!(exit_code(&mut interp, "(exit)"), Some(0));
+        assert_eq!(exit_code(&mut interp, "(exit #t)"), Some(0));
+        assert_eq!(exit_code(&mut interp, "(exit #f)"), Some(1));
+        assert_eq!(exit_code(&mut interp, "(exit 3)"), Some(3));
+        // The forms after it don't run, even inside a function
+        interp
+            .eval_str("(define ran #f) (define (stop) (begin (exit 2) (set! ran #t)))")
+            .unwrap();
+        assert_eq!(exit_code(&mut interp, "(stop) (set! ran #t)"), Some(2));
+        assert_eq!(interp.eval_str("ran").unwrap().print(), "false");
+        // exit is a procedure like any other
+        assert_eq!(
+            exit_code(&mut interp, "(define quit exit) (quit 5)"),
+            Some(5)
+        );
+        assert!(matches!(
+            interp.eval_str("(exit \"soon\")"),
+            Err(Error::Eval(_))
+        ));
+        // An ordinary error afterwards is still an ordinary error
+        assert!(matches!(
+            interp.eval_str("(car)"),
+            Err(Error::Eval(_)) | Err(Error::Undefined(_))
+        ));
+    }
+}
+
+#[test]
+fn each_interpreter_has_its_own_exit() {
+    let mut outer = Interpreter::new();
+    // An interpreter inside a native function exits on its own
+    outer.register_fn("run-inner", 0, |_| {
+        let mut inner = Interpreter::new();
+        match inner.eval_str("(exit 7)") {
+            Err(Error::Exit(code)) => Ok(SExpression::Cell(Cell::Int(code as i64))),
+            _ => Err("the inner interpreter didn't exit".to_string()),
+        }
+    });
+    assert_eq!(outer.eval_str("(run-inner)").unwrap().print(), "7");
+    // and the outer one's errors are still caught, and still errors
+    assert_eq!(
+        outer
+            .eval_str("(guard (e (#t 'caught)) (run-inner) (car))")
+            .unwrap()
+            .print(),
+        "caught"
+    );
+    assert!(matches!(outer.eval_str("(car)"), Err(Error::Eval(_))));
+    let mut other = Interpreter::new();
+    assert_eq!(exit_code(&mut outer, "(exit 2)"), Some(2));
+    assert!(matches!(other.eval_str("(car)"), Err(Error::Eval(_))));
+}
+
+#[test]
+fn tracing_is_per_interpreter() {
+    let mut traced = Interpreter::new();
+    traced.set_tracing(true);
+    assert!(!Interpreter::new().tracing());
+    // Starting over keeps it on
+    traced.reset();
+    assert!(traced.tracing());
+}
+
+#[test]
+fn scripts_exit_with_the_code() {
+    let file = env::temp_dir().join(format!("rs_lisp_exit_{}.scm", std::process::id()));
+    fs::write(&file, "(display \"before\") (exit 9) (display \"after\")").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rs_lisp"))
+        .arg(&file)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&file);
+    assert_eq!(output.status.code(), Some(9));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before");
+}
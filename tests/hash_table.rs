@@ -0,0 +1,130 @@
+mod common;
+
+use common::eval_both;
+use rs_lisp::{Error, Interpreter};
+
+#[test]
+fn eq_tables_key_lists_by_identity() {
+    let program = "(define t (make-hash-table eq?)) \
+                   (define key (list 1 2)) \
+                   (hash-table-set! t key 'found) \
+                   (hash-table-set! t 'sym 'symbol)";
+    assert_eq!(
+        eval_both(&format!(
+            "{} (list (hash-table-ref/default t key 'no) \
+                      (hash-table-ref/default t (list 1 2) 'no) \
+                      (hash-table-ref/default t 'sym 'no))",
+            program
+        )),
+        "(found no symbol)"
+    );
+}
+
+#[test]
+fn eqv_tables_compare_numbers_by_value() {
+    assert_eq!(
+        eval_both(
+            "(define t (make-hash-table eqv?)) \
+             (hash-table-set! t 1 'one) \
+             (hash-table-set! t (list 1) 'list) \
+             (list (hash-table-ref/default t 1 'no) \
+                   (hash-table-ref/default t (list 1) 'no) \
+                   (hash-table-size t))"
+        ),
+        "(one no 2)"
+    );
+}
+
+#[test]
+fn equal_tables_key_lists_and_strings_by_contents() {
+    assert_eq!(
+        eval_both(
+            "(define t (make-hash-table equal?)) \
+             (hash-table-set! t (list 1 (list 2 3)) 'nested) \
+             (hash-table-set! t \"text\" 'string) \
+             (hash-table-set! t (vector 1 2) 'vector) \
+             (list (hash-table-ref/default t (list 1 (list 2 3)) 'no) \
+                   (hash-table-ref/default t \"text\" 'no) \
+                   (hash-table-ref/default t (vector 1 2) 'no) \
+                   (hash-table-ref/default t (list 1 2 3) 'no))"
+        ),
+        "(nested string vector no)"
+    );
+}
+
+#[test]
+fn string_tables_only_take_strings() {
+    assert_eq!(
+        eval_both(
+            "(define t (make-hash-table string=?)) \
+             (hash-table-set! t \"a\" 1) \
+             (hash-table-set! t \"a\" 2) \
+             (list (hash-table-ref t \"a\") (hash-table-size t))"
+        ),
+        "(2 1)"
+    );
+    let mut interp = Interpreter::new();
+    match interp.eval_str("(hash-table-set! (make-hash-table string=?) 'a 1)") {
+        Err(Error::Eval(message)) => assert_eq!(
+            message,
+            "Keys of a string=? hash table must be strings, got a"
+        ),
+        _ => panic!("expected a symbol key to be refused"),
+    }
+}
+
+#[test]
+fn entries_can_be_updated_and_deleted() {
+    let program = "(define t (make-hash-table equal?)) \
+                   (hash-table-set! t \"count\" 1) \
+                   (hash-table-update! t \"count\" (lambda (n) (+ n 1))) \
+                   (hash-table-update!/default t \"other\" (lambda (n) (+ n 10)) 0)";
+    assert_eq!(
+        eval_both(&format!(
+            "{} (list (hash-table-ref t \"count\") (hash-table-ref t \"other\"))",
+            program
+        )),
+        "(2 10)"
+    );
+    assert_eq!(
+        eval_both(&format!(
+            "{} (hash-table-delete! t \"count\") \
+             (list (hash-table-contains? t \"count\") \
+                   (hash-table-ref t \"count\" (lambda () 'missing)) \
+                   (hash-table-size t))",
+            program
+        )),
+        "(false missing 1)"
+    );
+}
+
+#[test]
+fn tables_turn_into_association_lists() {
+    assert_eq!(
+        eval_both(
+            "(define t (make-hash-table equal?)) \
+             (hash-table-set! t (list 'a 'b) 1) \
+             (hash-table->alist t)"
+        ),
+        "(((a b) 1))"
+    );
+    assert_eq!(eval_both("(hash-table->alist (make-hash-table eq?))"), "()");
+    assert_eq!(
+        eval_both(
+            "(define t (make-hash-table eqv?)) \
+             (hash-table-set! t 1 'one) \
+             (hash-table-set! t 2 'two) \
+             (list (hash-table-size t) (hash-table-fold t (lambda (k v sum) (+ k sum)) 0))"
+        ),
+        "(2 3)"
+    );
+}
+
+#[test]
+fn missing_keys_without_a_default_are_an_error() {
+    let mut interp = Interpreter::new();
+    assert!(matches!(
+        interp.eval_str("(hash-table-ref (make-hash-table eq?) 'nowhere)"),
+        Err(Error::Eval(_))
+    ));
+}
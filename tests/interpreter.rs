@@ -0,0 +1,235 @@
+mod common;
+
+use common::both_backends;
+use rs_lisp::list::cons;
+use rs_lisp::primitives::NumericOperator;
+use rs_lisp::{Backend, Cell, Error, Interpreter, List, SExpression};
+use std::io::Write;
+
+fn int(value: i64) -> SExpression {
+    SExpression::Cell(Cell::Int(value))
+}
+
+#[test]
+fn eval_str_gives_the_last_value() {
+    for mut interp in both_backends() {
+        let result = interp.eval_str("(define x 2) (* x 21)").unwrap();
+        assert_eq!(result.print(), "42");
+    }
+}
+
+#[test]
+fn eval_forms_gives_every_value() {
+    for mut interp in both_backends() {
+        let results = interp.eval_forms("(+ 1 2) \"two\" (quote (a b))").unwrap();
+        let printed: Vec<String> = results.iter().map(|r| r.print()).collect();
+        assert_eq!(printed, vec!["3", "two", "(a b)"]);
+    }
+}
+
+#[test]
+fn definitions_last_between_calls() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str("(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))")
+            .unwrap();
+        assert_eq!(interp.eval_str("(fib 15)").unwrap().print(), "610");
+    }
+}
+
+#[test]
+fn call_applies_a_global_procedure() {
+    for mut interp in both_backends() {
+        interp.eval_str("(define (add a b) (+ a b))").unwrap();
+        let sum = interp.call("add", vec![int(40), int(2)]).unwrap();
+        assert!(matches!(sum, SExpression::Cell(Cell::Int(42))));
+    }
+}
+
+#[test]
+fn lists_built_in_rust_print_and_evaluate() {
+    let numbers = List::make_from_cells(vec![Cell::Int(25), Cell::Int(5)]);
+    let mixed = List::make_from_cells(vec![
+        Cell::Int(5),
+        Cell::Int(7),
+        Cell::Str("abc".to_string()),
+    ]);
+    let nested = List::make_from_sexps(vec![
+        SExpression::List(mixed.clone()),
+        SExpression::List(numbers.clone()),
+    ]);
+    assert_eq!(numbers.print(), "(25 5)");
+    assert_eq!(mixed.print(), "(5 7 abc)");
+    assert_eq!(nested.print(), "((5 7 abc) (25 5))");
+
+    let sum = List {
+        head: cons(
+            SExpression::Cell(Cell::Op(NumericOperator::Add)),
+            numbers.head,
+        ),
+    };
+    for mut interp in both_backends() {
+        assert_eq!(
+            interp.eval(SExpression::List(sum.clone())).unwrap().print(),
+            "30"
+        );
+    }
+}
+
+#[test]
+fn arithmetic_mixes_integers_and_floats() {
+    for mut interp in both_backends() {
+        for (program, expected) in &[
+            ("(+ 1 2 3 (+ 5 6))", "17"),
+            ("(+ 1 2 3 (* 5 6))", "36"),
+            ("(/ 10 5)", "2"),
+            ("(/ 5 10)", "0.5"),
+            ("(* 8 (/ 5 10))", "4"),
+            ("(- 8 (* 2 25) (+ 2 3) (/ 5 10))", "-47.5"),
+        ] {
+            assert_eq!(interp.eval_str(program).unwrap().print(), *expected);
+        }
+    }
+}
+
+#[test]
+fn call_doesnt_evaluate_its_arguments() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define (second items) (first (rest items)))")
+        .unwrap();
+    let quoted = List::make_from_cells(vec![Cell::Int(1), Cell::Int(2)]);
+    let second = interp
+        .call("second", vec![SExpression::List(quoted)])
+        .unwrap();
+    assert_eq!(second.print(), "2");
+}
+
+#[test]
+fn call_of_an_undefined_name() {
+    let mut interp = Interpreter::new();
+    match interp.call("nothing-here", vec![]) {
+        Err(Error::Undefined(name)) => assert_eq!(name, "nothing-here"),
+        _ => panic!("Expected an Undefined error"),
+    }
+}
+
+#[test]
+fn globals_set_from_rust_are_seen_by_scheme() {
+    for mut interp in both_backends() {
+        interp.set_global("limit", int(10));
+        assert_eq!(interp.eval_str("(* limit 3)").unwrap().print(), "30");
+        interp.set_global("limit", int(11));
+        assert_eq!(interp.eval_str("(* limit 3)").unwrap().print(), "33");
+    }
+}
+
+#[test]
+fn globals_set_from_scheme_are_seen_by_rust() {
+    let mut interp = Interpreter::new();
+    interp.eval_str("(define greeting \"hello\")").unwrap();
+    assert_eq!(interp.get_global("greeting").unwrap().print(), "hello");
+    assert!(matches!(
+        interp.get_global("farewell"),
+        Err(Error::Undefined(_))
+    ));
+}
+
+#[test]
+fn parse_errors() {
+    let mut interp = Interpreter::new();
+    assert!(matches!(interp.eval_str("(+ 1 2"), Err(Error::Parse(_))));
+    assert!(matches!(interp.eval_str("(+ 1 $)"), Err(Error::Parse(_))));
+}
+
+#[test]
+fn evaluation_errors() {
+    for mut interp in both_backends() {
+        match interp.eval_str("(undefined-function 1)") {
+            Err(Error::Eval(message)) => assert!(message.contains("undefined-function")),
+            _ => panic!("Expected an Eval error"),
+        }
+        // The interpreter is still usable afterwards
+        assert_eq!(interp.eval_str("(+ 1 1)").unwrap().print(), "2");
+    }
+}
+
+#[test]
+fn arithmetic_errors_dont_panic() {
+    for mut interp in both_backends() {
+        interp.set_global("big", int(i64::MAX));
+        interp.set_global("two", int(2));
+        for program in ["(+ 1 \"two\")", "(< 1 'two)", "(* big big)", "(+ big big)"] {
+            assert!(
+                matches!(interp.eval_str(program), Err(Error::Eval(_))),
+                "{} should fail",
+                program
+            );
+        }
+        match interp.eval_str("(* big two)") {
+            Err(Error::Eval(message)) => assert_eq!(
+                message,
+                format!("Integer overflow in '*' of {} and 2", i64::MAX)
+            ),
+            _ => panic!("Expected an overflow"),
+        }
+    }
+}
+
+#[test]
+fn each_interpreter_has_its_own_command_line() {
+    let mut script = Interpreter::new();
+    script.set_command_line(vec!["script.scm".to_string(), "-v".to_string()]);
+    let mut other = Interpreter::new();
+    assert_eq!(
+        script.eval_str("(command-line)").unwrap().print(),
+        "(script.scm -v)"
+    );
+    assert_eq!(other.eval_str("(command-line)").unwrap().print(), "()");
+    // Starting over keeps it
+    script.reset();
+    assert_eq!(
+        script.eval_str("(command-line)").unwrap().print(),
+        "(script.scm -v)"
+    );
+}
+
+#[test]
+fn comment_on_the_last_line() {
+    let mut interp = Interpreter::new();
+    assert_eq!(interp.eval_str("(+ 1 2) ; three").unwrap().print(), "3");
+}
+
+#[test]
+fn eval_file_runs_a_program() {
+    let path = std::env::temp_dir().join(format!("rs_lisp_test_{}.scm", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "(define (square x) (* x x))\n(square 12)").unwrap();
+    let mut interp = Interpreter::new();
+    let result = interp.eval_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(result.unwrap().print(), "144");
+    assert!(matches!(
+        interp.eval_file("/no/such/file.scm"),
+        Err(Error::Io(_, _))
+    ));
+}
+
+#[test]
+fn vm_runs_deep_tail_recursion() {
+    let mut interp = Interpreter::with_backend(Backend::Vm);
+    interp
+        .eval_str("(define (count-down n) (if (= n 0) (quote done) (count-down (- n 1))))")
+        .unwrap();
+    assert_eq!(
+        interp.eval_str("(count-down 100000)").unwrap().print(),
+        "done"
+    );
+}
+
+#[test]
+fn disassemble_lists_lambdas() {
+    let listing = rs_lisp::disassemble("(define (f x) (+ x 1))").unwrap();
+    assert!(listing.contains("Lambda: f"));
+    assert!(listing.contains("RETURN"));
+}
@@ -0,0 +1,139 @@
+use rs_lisp::{Backend, Error, Interpreter};
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn vectors() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp.eval_str("(define v #(1 \"two\" (3)))").unwrap();
+        assert_eq!(eval(&mut interp, "v"), "#(1 two (3))");
+        assert_eq!(eval(&mut interp, "(vector-ref v 1)"), "two");
+        assert_eq!(eval(&mut interp, "(vector-length (make-vector 4 0))"), "4");
+        interp.eval_str("(vector-set! v 0 'one)").unwrap();
+        assert_eq!(eval(&mut interp, "(vector->list v)"), "(one two (3))");
+        assert_eq!(eval(&mut interp, "(list->vector (list 1 2))"), "#(1 2)");
+        assert_eq!(
+            eval(&mut interp, "(equal? v (vector 'one \"two\" '(3)))"),
+            "true"
+        );
+        assert_eq!(
+            eval(&mut interp, "(eqv? v (vector 'one \"two\" '(3)))"),
+            "false"
+        );
+        assert!(interp.eval_str("(vector-ref v 3)").is_err());
+        match interp.eval_str("(make-vector 1000000000000000000 0)") {
+            Err(Error::Eval(message)) => assert!(message.contains("too large"), "{}", message),
+            other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+        }
+    }
+}
+
+#[test]
+fn objects_read_as_association_lists() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str(
+            "(define doc (string->json \"{\\\"name\\\": \\\"rs\\\", \\\"tags\\\": [\\\"a\\\", 1, 2.5], \\\"ok\\\": true}\"))",
+        )
+        .unwrap();
+    assert_eq!(
+        eval(&mut interp, "doc"),
+        "((name rs) (tags #(a 1 2.5)) (ok true))"
+    );
+    assert_eq!(eval(&mut interp, "(first (rest (assq 'ok doc)))"), "true");
+    assert_eq!(
+        eval(&mut interp, "(json->string doc)"),
+        "{\"name\":\"rs\",\"tags\":[\"a\",1,2.5],\"ok\":true}"
+    );
+}
+
+#[test]
+fn objects_read_as_hash_tables() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define t (string->json \"{\\\"a\\\": {\\\"b\\\": [] }}\" 'hash-table))")
+        .unwrap();
+    assert_eq!(
+        eval(&mut interp, "(hash-table? (hash-table-ref t 'a))"),
+        "true"
+    );
+    assert_eq!(eval(&mut interp, "(json->string t)"), "{\"a\":{\"b\":[]}}");
+}
+
+#[test]
+fn null_is_its_own_value() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define items (string->json \"[null, false, {}]\"))")
+        .unwrap();
+    assert_eq!(
+        eval(&mut interp, "(json-null? (vector-ref items 0))"),
+        "true"
+    );
+    assert_eq!(
+        eval(&mut interp, "(json-null? (vector-ref items 1))"),
+        "false"
+    );
+    assert_eq!(
+        eval(&mut interp, "(json-null? (vector-ref items 2))"),
+        "false"
+    );
+    assert_eq!(eval(&mut interp, "(json->string items)"), "[null,false,{}]");
+    // The symbol null is just a symbol
+    assert_eq!(eval(&mut interp, "(json-null? 'null)"), "false");
+    assert_eq!(
+        eval(&mut interp, "(json->string (vector 'null (json-null) 1))"),
+        "[\"null\",null,1]"
+    );
+    assert_eq!(
+        eval(&mut interp, "(eq? (vector-ref items 0) (json-null))"),
+        "true"
+    );
+    assert_eq!(eval(&mut interp, "(vector-ref items 0)"), "#<json-null>");
+}
+
+#[test]
+fn strings_and_escapes() {
+    let mut interp = Interpreter::new();
+    let text = r#"(string->json "\"tab\\t quote\\\" \\u00e9 \\ud83d\\ude00\"")"#;
+    assert_eq!(eval(&mut interp, text), "tab\t quote\" é 😀");
+    assert_eq!(
+        eval(&mut interp, r#"(json->string "line\nbreak \"q\"")"#),
+        r#""line\nbreak \"q\"""#
+    );
+}
+
+#[test]
+fn pretty_printing() {
+    let mut interp = Interpreter::new();
+    let pretty = eval(
+        &mut interp,
+        "(json->string (list (list 'a (vector 1 2)) (list 'b (vector))) 2)",
+    );
+    assert_eq!(pretty, "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": []\n}");
+}
+
+#[test]
+fn bad_json_is_an_error() {
+    let mut interp = Interpreter::new();
+    for text in &["[1, 2,]", "{\\\"a\\\" 1}", "01", "[1] 2", "\\\"open", "tru"] {
+        match interp.eval_str(&format!("(string->json \"{}\")", text)) {
+            Err(Error::Eval(message)) => assert!(message.contains("JSON"), "{}", message),
+            other => panic!(
+                "Expected an error for {}, got {:?}",
+                text,
+                other.map(|r| r.print())
+            ),
+        }
+    }
+    // Lists that aren't association lists can't be objects
+    assert!(interp.eval_str("(json->string (list 1 2))").is_err());
+    // Without a port json-read only takes the object type
+    match interp.eval_str("(json-read 'alist 'hash-table)") {
+        Err(Error::Eval(message)) => assert!(message.contains("Too many"), "{}", message),
+        other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+    }
+}
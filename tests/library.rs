@@ -0,0 +1,155 @@
+use rs_lisp::{Backend, Error, Interpreter};
+use std::env;
+use std::fs;
+
+const SHAPES: &str = "(define-library (geometry shapes)
+  (export area (rename make-square square) scale)
+  (import (scheme base))
+  (begin
+    (define scale 2)
+    (define (area s) (* s s scale))
+    (define (make-square n) (list 'square n))))";
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn libraries_have_their_own_names() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(&format!("{} (import (geometry shapes))", SHAPES))
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(area 3)"), "18");
+        assert_eq!(eval(&mut interp, "(square 4)"), "(square 4)");
+        // Only exported names come in
+        assert!(interp.eval_str("(make-square 4)").is_err());
+        // The library's procedures still use the library's definitions
+        interp
+            .eval_str("(environment-define! (interaction-environment) 'scale 100)")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(area 1)"), "2");
+    }
+}
+
+#[test]
+fn import_sets_select_and_rename() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp.eval_str(SHAPES).unwrap();
+        interp
+            .eval_str("(import (prefix (only (geometry shapes) area) g:))")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(g:area 2)"), "8");
+        assert!(interp.eval_str("g:scale").is_err());
+
+        interp
+            .eval_str("(import (rename (except (geometry shapes) area) (square sq)))")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(sq 1)"), "(square 1)");
+        assert_eq!(eval(&mut interp, "scale"), "2");
+        assert!(interp.eval_str("area").is_err());
+
+        assert!(interp
+            .eval_str("(import (only (geometry shapes) perimeter))")
+            .is_err());
+        assert!(interp.eval_str("(import (no such library))").is_err());
+    }
+}
+
+#[test]
+fn built_ins_can_be_imported_under_other_names() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(
+                "(import (scheme base)
+                         (prefix (scheme write) w:)
+                         (rename (scheme base) (+ plus) (< less?)))",
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(plus 1 2 3)"), "6");
+        assert_eq!(eval(&mut interp, "(less? 1 2)"), "true");
+        assert_eq!(
+            eval(
+                &mut interp,
+                "(with-output-to-string (lambda () (w:write \"x\")))"
+            ),
+            "\"x\""
+        );
+        // Syntax can't be called through a variable
+        assert!(interp
+            .eval_str("(import (rename (scheme base) (if when-true)))")
+            .is_err());
+    }
+}
+
+#[test]
+fn only_and_except_cant_hide_built_ins() {
+    let mut interp = Interpreter::new();
+    for program in &[
+        "(import (only (scheme base) car))",
+        "(import (except (scheme write) display))",
+        "(import (only (prefix (scheme base) b:) b:car))",
+    ] {
+        match interp.eval_str(program) {
+            Err(Error::Eval(message)) => {
+                assert!(message.contains("can't hide built-ins"), "{}", message)
+            }
+            other => panic!(
+                "Expected an error for {}, got {:?}",
+                program,
+                other.map(|r| r.print())
+            ),
+        }
+    }
+    // A library of your own with the same name is yours to pick from
+    interp
+        .eval_str("(define-library (scheme mine) (export one) (begin (define one 1)))")
+        .unwrap();
+    interp
+        .eval_str("(import (only (scheme mine) one))")
+        .unwrap();
+    assert_eq!(eval(&mut interp, "one"), "1");
+}
+
+#[test]
+fn each_interpreter_has_its_own_libraries() {
+    let mut defines = Interpreter::new();
+    defines.eval_str(SHAPES).unwrap();
+    let mut other = Interpreter::new();
+    assert!(other.eval_str("(import (geometry shapes))").is_err());
+    defines.eval_str("(import (geometry shapes))").unwrap();
+    assert_eq!(eval(&mut defines, "(area 1)"), "2");
+    // Starting over forgets them too
+    defines.reset();
+    assert!(defines.eval_str("(import (geometry shapes))").is_err());
+}
+
+#[test]
+fn libraries_are_found_in_files() {
+    let dir = env::temp_dir().join(format!("rs_lisp_library_{}", std::process::id()));
+    fs::create_dir_all(dir.join("geometry")).unwrap();
+    fs::write(dir.join("geometry/shapes.sld"), SHAPES).unwrap();
+    fs::write(
+        dir.join("main.scm"),
+        "(import (prefix (geometry shapes) shapes:)) (shapes:area 5)",
+    )
+    .unwrap();
+
+    let mut interp = Interpreter::new();
+    assert_eq!(
+        interp.eval_file(dir.join("main.scm")).unwrap().print(),
+        "50"
+    );
+
+    // And through the search path from anywhere else
+    let mut interp = Interpreter::new();
+    interp.add_search_path(&dir);
+    assert_eq!(
+        eval(&mut interp, "(import (geometry shapes)) (area 1)"),
+        "2"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
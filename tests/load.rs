@@ -0,0 +1,136 @@
+use rs_lisp::{Backend, Error, Interpreter};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// A fresh directory of program files for one test
+fn program_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rs_lisp_load_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    for (file, text) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn loads_are_relative_to_the_loading_file() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let dir = program_dir(
+            &format!("relative_{:?}", backend),
+            &[
+                ("main.scm", "(load \"util/math.scm\") (square (inc 2))"),
+                (
+                    "util/math.scm",
+                    "(load \"inc.scm\") (define (square x) (* x x))",
+                ),
+                ("util/inc.scm", "(define (inc x) (+ x 1))"),
+            ],
+        );
+        let mut interp = Interpreter::with_backend(*backend);
+        assert_eq!(interp.eval_file(dir.join("main.scm")).unwrap().print(), "9");
+        // Definitions from loaded files are globals
+        assert_eq!(interp.eval_str("(inc 1)").unwrap().print(), "2");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn the_search_path_is_used_after_the_files_directory() {
+    let dir = program_dir(
+        "search",
+        &[
+            ("lib/shared.scm", "(define shared 'from-lib)"),
+            ("app/main.scm", "(load \"shared.scm\") shared"),
+        ],
+    );
+    let mut interp = Interpreter::new();
+    assert!(interp.eval_file(dir.join("app/main.scm")).is_err());
+    interp.add_search_path(dir.join("lib"));
+    assert_eq!(
+        interp.eval_file(dir.join("app/main.scm")).unwrap().print(),
+        "from-lib"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn each_interpreter_has_its_own_search_path() {
+    let dir = program_dir(
+        "own_search",
+        &[
+            ("lib/shared.scm", "(define shared 'from-lib)"),
+            ("app/main.scm", "(load \"shared.scm\") shared"),
+        ],
+    );
+    let mut with_path = Interpreter::new();
+    with_path.add_search_path(dir.join("lib"));
+    let mut without = Interpreter::new();
+    assert!(without.eval_file(dir.join("app/main.scm")).is_err());
+    assert_eq!(
+        with_path
+            .eval_file(dir.join("app/main.scm"))
+            .unwrap()
+            .print(),
+        "from-lib"
+    );
+    // Starting over forgets the definitions but not the search path
+    with_path.reset();
+    assert!(with_path.eval_str("shared").is_err());
+    assert_eq!(
+        with_path
+            .eval_file(dir.join("app/main.scm"))
+            .unwrap()
+            .print(),
+        "from-lib"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn include_puts_the_forms_in_place() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let dir = program_dir(
+            &format!("include_{:?}", backend),
+            &[
+                (
+                    "main.scm",
+                    "(define (f x) (begin (include \"body.scm\") (* local x)))",
+                ),
+                ("body.scm", "(define local 10)"),
+            ],
+        );
+        let mut interp = Interpreter::with_backend(*backend);
+        interp.eval_file(dir.join("main.scm")).unwrap();
+        assert_eq!(interp.eval_str("(f 3)").unwrap().print(), "30");
+        // The included definition went into f's scope, not the top level
+        assert!(interp.eval_str("local").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn circular_loads_are_an_error() {
+    let dir = program_dir(
+        "circular",
+        &[
+            ("a.scm", "(load \"b.scm\")"),
+            ("b.scm", "(define b 1) (load \"a.scm\")"),
+        ],
+    );
+    let mut interp = Interpreter::new();
+    match interp.eval_file(dir.join("a.scm")) {
+        Err(Error::Eval(message)) => assert!(message.contains("Circular"), "{}", message),
+        other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+    }
+    // Loading the same file again once it's finished is fine
+    interp.eval_str("(define counter 0)").unwrap();
+    fs::write(dir.join("c.scm"), "(set! counter (+ counter 1))").unwrap();
+    let load_c = format!("(load \"{}\")", dir.join("c.scm").display());
+    interp.eval_str(&load_c).unwrap();
+    interp.eval_str(&load_c).unwrap();
+    assert_eq!(interp.eval_str("counter").unwrap().print(), "2");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,114 @@
+use rs_lisp::{Arity, Backend, Cell, Error, Interpreter, SExpression};
+
+fn number(exp: &SExpression) -> Result<f64, String> {
+    match exp {
+        SExpression::Cell(Cell::Int(n)) => Ok(*n as f64),
+        SExpression::Cell(Cell::Flt(n)) => Ok(*n),
+        other => Err(format!("Expected a number, got {}", other.print())),
+    }
+}
+
+fn with_hypot(backend: Backend) -> Interpreter {
+    let mut interp = Interpreter::with_backend(backend);
+    interp.register_documented_fn(
+        "hypot",
+        2,
+        "The length of the hypotenuse of a right triangle with sides a and b",
+        |args| {
+            let (a, b) = (number(&args[0])?, number(&args[1])?);
+            Ok(SExpression::Cell(Cell::Flt((a * a + b * b).sqrt())))
+        },
+    );
+    interp
+}
+
+#[test]
+fn native_functions_are_called_from_scheme() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = with_hypot(*backend);
+        assert_eq!(interp.eval_str("(hypot 3 4)").unwrap().print(), "5");
+        interp
+            .eval_str("(define (scaled-hypot k) (* k (hypot 3 4)))")
+            .unwrap();
+        assert_eq!(interp.eval_str("(scaled-hypot 2)").unwrap().print(), "10");
+    }
+}
+
+#[test]
+fn native_functions_are_values() {
+    let mut interp = with_hypot(Backend::TreeWalker);
+    interp.eval_str("(define h (make-hash-table))").unwrap();
+    interp
+        .eval_str("(hash-table-set! h 'a 6) (hash-table-update! h 'a (lambda (x) (hypot x 8)))")
+        .unwrap();
+    assert_eq!(
+        interp.eval_str("(hash-table-ref h 'a)").unwrap().print(),
+        "10"
+    );
+    assert_eq!(interp.eval_str("hypot").unwrap().print(), "Native: hypot");
+    assert_eq!(
+        interp.eval_str("(eq? hypot hypot)").unwrap().print(),
+        "true"
+    );
+}
+
+#[test]
+fn arity_is_checked_before_the_call() {
+    let mut interp = with_hypot(Backend::TreeWalker);
+    match interp.eval_str("(hypot 3)") {
+        Err(Error::Eval(message)) => assert!(message.contains("takes 2 arguments, got 1")),
+        other => panic!(
+            "Expected an arity error, got {:?}",
+            other.map(|r| r.print())
+        ),
+    }
+
+    interp.register_fn("count-args", Arity::AtLeast(1), |args| {
+        Ok(SExpression::Cell(Cell::Int(args.len() as i64)))
+    });
+    assert_eq!(interp.eval_str("(count-args 1 2 3)").unwrap().print(), "3");
+    assert!(interp.eval_str("(count-args)").is_err());
+}
+
+#[test]
+fn errors_and_panics_become_scheme_errors() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = with_hypot(*backend);
+        assert!(matches!(
+            interp.eval_str("(hypot \"three\" 4)"),
+            Err(Error::Eval(_))
+        ));
+
+        interp.register_fn("explode", 0, |_| panic!("boom"));
+        match interp.eval_str("(explode)") {
+            Err(Error::Eval(message)) => assert!(message.contains("boom")),
+            other => panic!("Expected a panic error, got {:?}", other.map(|r| r.print())),
+        }
+        // Still usable afterwards
+        assert_eq!(interp.eval_str("(hypot 6 8)").unwrap().print(), "10");
+    }
+}
+
+#[test]
+fn doc_strings() {
+    let mut interp = with_hypot(Backend::TreeWalker);
+    interp.register_fn("plain", 0, |_| Ok(SExpression::Null));
+    assert!(interp.doc("hypot").unwrap().contains("hypotenuse"));
+    assert_eq!(interp.doc("plain").unwrap(), "");
+    assert_eq!(interp.doc("nothing"), None);
+}
+
+#[test]
+fn call_from_rust_reaches_native_functions() {
+    let mut interp = with_hypot(Backend::Vm);
+    let result = interp
+        .call(
+            "hypot",
+            vec![
+                SExpression::Cell(Cell::Int(5)),
+                SExpression::Cell(Cell::Int(12)),
+            ],
+        )
+        .unwrap();
+    assert_eq!(result.print(), "13");
+}
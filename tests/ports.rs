@@ -0,0 +1,243 @@
+use rs_lisp::{Backend, Error, Interpreter};
+use std::env;
+use std::fs;
+
+fn temp_path(name: &str) -> String {
+    let path = env::temp_dir().join(format!("rs_lisp_{}_{}", std::process::id(), name));
+    path.to_string_lossy().into_owned()
+}
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn write_then_read_a_file() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let path = temp_path(&format!("lines_{:?}", backend));
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(&format!(
+                "(define out (open-output-file \"{}\"))
+                 (write-string \"first line\" out)
+                 (newline out)
+                 (write \"quoted\" out)
+                 (display \" héllo\" out)
+                 (close-port out)",
+                path
+            ))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "first line\n\"quoted\" héllo"
+        );
+
+        interp
+            .eval_str(&format!("(define in (open-input-file \"{}\"))", path))
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(input-port? in)"), "true");
+        assert_eq!(eval(&mut interp, "(read-line in)"), "first line");
+        assert_eq!(eval(&mut interp, "(peek-char in)"), "\"");
+        assert_eq!(eval(&mut interp, "(read-char in)"), "\"");
+        assert_eq!(eval(&mut interp, "(read-string 8 in)"), "quoted\" ");
+        assert_eq!(eval(&mut interp, "(read-string 10 in)"), "héllo");
+        assert_eq!(eval(&mut interp, "(eof-object? (read-char in))"), "true");
+        assert_eq!(eval(&mut interp, "(eof-object? (read-line in))"), "true");
+        interp.eval_str("(close-input-port in)").unwrap();
+        assert!(interp.eval_str("(read-line in)").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn output_can_be_redirected_to_a_file() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let path = temp_path(&format!("redirect_{:?}", backend));
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(&format!(
+                "(with-output-to-file \"{}\"
+                   (lambda () (output \"a\" 1) (output-line \" b\") (display 'c)))",
+                path
+            ))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a 1 b\nc");
+
+        // And input read from one, a line at a time
+        let lines = eval(
+            &mut interp,
+            &format!(
+                "(with-input-from-file \"{}\" (lambda () (list (input) (read-line) (eof-object? (input)))))",
+                path
+            ),
+        );
+        assert_eq!(lines, "(a 1 b c true)");
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn redirected_output_is_kept_when_the_thunk_raises() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let path = temp_path(&format!("raise_{:?}", backend));
+        let mut interp = Interpreter::with_backend(*backend);
+        // Holding on to the port means only closing it flushes the file
+        let caught = eval(
+            &mut interp,
+            &format!(
+                "(define port #f)
+                 (guard (e (#t e))
+                   (with-output-to-file \"{}\"
+                     (lambda () (set! port (current-output-port)) (display \"before\") (raise 'oops))))",
+                path
+            ),
+        );
+        assert_eq!(caught, "oops");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "before");
+        assert!(interp.eval_str("(display \"after\" port)").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn call_with_file_closes_the_port() {
+    let path = temp_path("call_with");
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str(&format!(
+            "(call-with-output-file \"{}\" (lambda (port) (write-string \"42\" port)))",
+            path
+        ))
+        .unwrap();
+    assert_eq!(
+        eval(
+            &mut interp,
+            &format!(
+                "(call-with-input-file \"{}\" (lambda (port) (read-line port)))",
+                path
+            )
+        ),
+        "42"
+    );
+    interp
+        .eval_str(&format!(
+            "(define p (call-with-input-file \"{}\" (lambda (port) port)))",
+            path
+        ))
+        .unwrap();
+    match interp.eval_str("(read-line p)") {
+        Err(Error::Eval(message)) => assert!(message.contains("closed"), "{}", message),
+        other => panic!("Expected an error, got {:?}", other.map(|r| r.print())),
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn binary_ports_read_and_write_bytes() {
+    let path = temp_path("bytes");
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str(&format!(
+            "(define out (open-binary-output-file \"{}\"))
+             (write-u8 0 out)
+             (write-u8 255 out)
+             (close-output-port out)",
+            path
+        ))
+        .unwrap();
+    assert_eq!(fs::read(&path).unwrap(), vec![0, 255]);
+
+    interp
+        .eval_str(&format!(
+            "(define in (open-binary-input-file \"{}\"))",
+            path
+        ))
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(binary-port? in)"), "true");
+    assert_eq!(eval(&mut interp, "(peek-u8 in)"), "0");
+    assert_eq!(eval(&mut interp, "(read-u8 in)"), "0");
+    assert_eq!(eval(&mut interp, "(read-u8 in)"), "255");
+    assert_eq!(eval(&mut interp, "(eof-object? (read-u8 in))"), "true");
+    // Textual procedures need textual ports
+    assert!(interp.eval_str("(read-char in)").is_err());
+    assert!(interp
+        .eval_str("(write-u8 256 (current-output-port))")
+        .is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn missing_files_are_errors() {
+    let mut interp = Interpreter::new();
+    let path = temp_path("not_there");
+    assert!(interp
+        .eval_str(&format!("(open-input-file \"{}\")", path))
+        .is_err());
+    assert_eq!(eval(&mut interp, "(eof-object? (eof-object))"), "true");
+    assert_eq!(
+        eval(&mut interp, "(output-port? (current-output-port))"),
+        "true"
+    );
+}
+
+#[test]
+fn output_can_be_captured_in_a_string() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        assert_eq!(
+            eval(
+                &mut interp,
+                "(with-output-to-string (lambda () (output \"x =\" 1) (output-line) (display '(a b))))"
+            ),
+            "x = 1\n(a b)"
+        );
+        assert_eq!(
+            eval(
+                &mut interp,
+                "(call-with-output-string (lambda (port) (write \"q\" port) (json-write (vector 1) port)))"
+            ),
+            "\"q\"[1]"
+        );
+        interp
+            .eval_str("(define out (open-output-string)) (write-string \"ab\" out)")
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(get-output-string out)"), "ab");
+        interp.eval_str("(newline out)").unwrap();
+        assert_eq!(eval(&mut interp, "(get-output-string out)"), "ab\n");
+    }
+}
+
+#[test]
+fn strings_can_be_read_as_ports() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define in (open-input-string \"one\ntwo [1, 2]\"))")
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(read-line in)"), "one");
+    assert_eq!(eval(&mut interp, "(read-string 3 in)"), "two");
+    assert_eq!(eval(&mut interp, "(json-read in)"), "#(1 2)");
+    assert_eq!(eval(&mut interp, "(eof-object? (json-read in))"), "true");
+    // Only output string ports have output to get
+    assert!(interp.eval_str("(get-output-string in)").is_err());
+}
+
+#[test]
+fn each_interpreter_has_its_own_current_ports() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        // The inner interpreter isn't inside with-output-to-string, so its
+        // newline goes to stdout rather than into the outer one's string
+        interp.register_fn("inner-newline", 0, |_| {
+            Interpreter::new()
+                .eval_str("(newline)")
+                .map_err(|_| "inner".to_string())
+        });
+        assert_eq!(
+            eval(
+                &mut interp,
+                "(with-output-to-string (lambda () (inner-newline) (display \"outer\")))"
+            ),
+            "outer"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use rs_lisp::{Backend, Error, Interpreter};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn script(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!(
+        "rs_lisp_program_{}_{}.scm",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn rs_lisp(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rs_lisp"))
+        .args(args)
+        .env("HOME", env::temp_dir())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn forms_before_a_syntax_error_still_run() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(backend);
+        let result = interp.eval_forms("(define x 5)\n(define y (* x 2))\ny)");
+        match result.err() {
+            Some(Error::Parse(reason)) => {
+                assert_eq!(reason, "line 3, column 2: unexpected `)`")
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert_eq!(interp.eval_str("y").unwrap().print(), "10");
+    }
+}
+
+#[test]
+fn a_form_left_open_is_reported_where_it_starts() {
+    let mut interp = Interpreter::new();
+    match interp.eval_forms("(define a 1)\n  (define b\n  2").err() {
+        Some(Error::Parse(reason)) => assert_eq!(
+            reason,
+            "line 2, column 3: the program ended before this form did"
+        ),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn each_form_can_fail_on_its_own() {
+    let mut interp = Interpreter::new();
+    let mut results = Vec::new();
+    interp.eval_forms_each("1 (oops) 2 )\n3", Some("test.scm"), |result| {
+        results.push(match result {
+            Ok(value) => value.print(),
+            Err(error) => error.to_string(),
+        });
+        true
+    });
+    assert_eq!(
+        results,
+        [
+            "1",
+            "test.scm:1:3: Symbol oops not defined.",
+            "2",
+            "Parse error: test.scm:1:12: unexpected `)`",
+            "3"
+        ]
+    );
+}
+
+#[test]
+fn file_errors_say_where_they_are() {
+    let path = script("located", "(define x 1)\n\n   (car x)\n");
+    let mut interp = Interpreter::new();
+    let error = interp.eval_file_forms(&path).err().unwrap().to_string();
+    assert!(
+        error.starts_with(&format!("{}:3:4: ", path.display())),
+        "{}",
+        error
+    );
+    assert_eq!(interp.eval_str("x").unwrap().print(), "1");
+
+    // The same from load
+    let loader = script(
+        "loader",
+        &format!("(load {:?})", path.display().to_string()),
+    );
+    let error = Interpreter::new()
+        .eval_file_forms(&loader)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        error.contains(&format!("{}:3:4: ", path.display())),
+        "{}",
+        error
+    );
+    fs::remove_file(path).unwrap();
+    fs::remove_file(loader).unwrap();
+}
+
+#[test]
+fn keep_going_reports_every_failing_form() {
+    let path = script(
+        "keep_going",
+        "(display \"a\")\n(first 1)\n(display \"b\")\n(nowhere)\n(display \"c\")\n",
+    );
+    let file = path.display().to_string();
+
+    let output = rs_lisp(&[&file]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a");
+    assert_eq!(output.status.code(), Some(1));
+
+    for backend in [&[][..], &["--vm"][..]] {
+        let mut args = backend.to_vec();
+        args.extend(["--keep-going", &file]);
+        let output = rs_lisp(&args);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "abc");
+        let errors = String::from_utf8_lossy(&output.stderr);
+        assert!(errors.contains(&format!("{}:2:1: ", file)), "{}", errors);
+        assert!(errors.contains(&format!("{}:4:1: ", file)), "{}", errors);
+        assert!(errors.ends_with("rs_lisp: 2 forms failed\n"), "{}", errors);
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    // An exit still ends the program
+    let output = rs_lisp(&["--keep-going", "-e", "(oops) (exit 3) (display 1)"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(3));
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::eval_both;
+use rs_lisp::{Error, Interpreter};
+
+#[test]
+fn quote_gives_back_what_it_is_given() {
+    assert_eq!(
+        eval_both("(list (quote a) (quote (1 b \"c\")) (quote ()))"),
+        "(a (1 b c) ())"
+    );
+    // 'x is the same as (quote x)
+    assert_eq!(eval_both("(list 'a '(b (c)) ''x)"), "(a (b (c)) (quote x))");
+}
+
+#[test]
+fn quote_takes_one_expression() {
+    for program in ["(quote)", "(quote a b)"] {
+        let mut interp = Interpreter::new();
+        match interp.eval_str(program) {
+            Err(Error::Eval(message)) => assert_eq!(message, "quote takes exactly one expression."),
+            _ => panic!("expected {} to fail", program),
+        }
+    }
+}
+
+#[test]
+fn boolean_literals() {
+    assert_eq!(
+        eval_both("(list #t #f #true #false)"),
+        "(true false true false)"
+    );
+    assert_eq!(eval_both("(list (if #t 1 2) (if #f 1 2))"), "(1 2)");
+}
@@ -0,0 +1,71 @@
+use rs_lisp::{Backend, Interpreter};
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn reads_one_datum_at_a_time() {
+    for backend in &[Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(*backend);
+        interp
+            .eval_str(
+                r#"(define in (open-input-string "; a data file
+                   (point 1 2) ; trailing comment
+                   \"a (string\" 'quoted #(1 two)
+                   (nested (list \"with ; no comment\") ; comment inside
+                   ) last"))"#,
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(read in)"), "(point 1 2)");
+        assert_eq!(eval(&mut interp, "(read in)"), "a (string");
+        assert_eq!(eval(&mut interp, "(read in)"), "(quote quoted)");
+        assert_eq!(eval(&mut interp, "(read in)"), "#(1 two)");
+        assert_eq!(
+            eval(&mut interp, "(read in)"),
+            "(nested (list with ; no comment))"
+        );
+        assert_eq!(eval(&mut interp, "(read in)"), "last");
+        assert_eq!(eval(&mut interp, "(eof-object? (read in))"), "true");
+    }
+}
+
+#[test]
+fn read_data_is_not_evaluated() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define form (read \"(+ 1 (undefined-thing))\"))")
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(first form)"), "+");
+    assert_eq!(
+        eval(&mut interp, "(first (rest (rest form)))"),
+        "(undefined-thing)"
+    );
+}
+
+#[test]
+fn the_rest_of_the_port_is_left_unread() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define in (open-input-string \"(a b)rest of line\nnext\"))")
+        .unwrap();
+    assert_eq!(eval(&mut interp, "(read in)"), "(a b)");
+    assert_eq!(eval(&mut interp, "(read-line in)"), "rest of line");
+    assert_eq!(eval(&mut interp, "(read in)"), "next");
+}
+
+#[test]
+fn incomplete_data_is_an_error() {
+    let mut interp = Interpreter::new();
+    for text in &["(a (b)", "\\\"open", "')", ")"] {
+        assert!(
+            interp.eval_str(&format!("(read \"{}\")", text)).is_err(),
+            "{}",
+            text
+        );
+    }
+    assert_eq!(
+        eval(&mut interp, "(eof-object? (read \"  ; only a comment\"))"),
+        "true"
+    );
+}
@@ -0,0 +1,236 @@
+use rs_lisp::Interpreter;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Type the input into the REPL and return what it printed. Each session
+// runs in a directory of its own, which is also its home, so the history
+// and config files stay out of the way.
+fn session(name: &str, args: &[&str], input: &str) -> String {
+    session_with_status(name, args, input).0
+}
+
+fn session_with_status(name: &str, args: &[&str], input: &str) -> (String, Option<i32>) {
+    let dir = session_dir(name);
+    let result = run_in(&dir, args, input);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn session_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rs_lisp_repl_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_in(dir: &Path, args: &[&str], input: &str) -> (String, Option<i32>) {
+    // Input from a pipe is a program to run unless -i asks for the REPL
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rs_lisp"))
+        .arg("-i")
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env_remove("XDG_DATA_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code(),
+    )
+}
+
+#[test]
+fn forms_can_span_lines() {
+    for backend in &[&[][..], &["--vm"][..]] {
+        let output = session(
+            &format!("span_{}", backend.len()),
+            backend,
+            "(define (square x)\n  ; a ( in a comment\n  (* x\n     x))\n(square \"(\")\n(square 5)\n",
+        );
+        assert!(output.contains("=>  25"), "{}", output);
+        // The string's paren didn't hold the next line back
+        assert_eq!(output.matches("=>  ").count(), 3, "{}", output);
+    }
+}
+
+#[test]
+fn every_form_on_a_line_is_evaluated() {
+    let output = session(
+        "several",
+        &[],
+        "(define a 2) (define b 3)\n(+ a b) (* a b) 'done\n",
+    );
+    let values: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.strip_prefix("=>  "))
+        .collect();
+    assert_eq!(values[2..], ["5", "6", "done"]);
+}
+
+#[test]
+fn a_pasted_file_runs_as_typed() {
+    let program = "(define total 0)\n\n(define (add! n)\n  (set! total (+ total n)))\n\n(add! 4)\n(add! 6)\ntotal\n";
+    let output = session("pasted", &[], program);
+    assert!(output.contains("=>  10"), "{}", output);
+}
+
+#[test]
+fn globals_and_their_parameters_are_listed() {
+    let mut interp = Interpreter::new();
+    interp
+        .eval_str("(define (area width height) (* width height))")
+        .unwrap();
+    interp.eval_str("(define scale 2)").unwrap();
+    let names = interp.global_names();
+    assert!(names.contains(&"area".to_string()));
+    assert!(names.contains(&"scale".to_string()));
+    assert_eq!(
+        interp.parameters("area"),
+        Some(vec!["width".to_string(), "height".to_string()])
+    );
+    assert_eq!(interp.parameters("scale"), None);
+    assert_eq!(interp.parameters("missing"), None);
+}
+
+#[test]
+fn commands_describe_the_session() {
+    let output = session(
+        "commands",
+        &[],
+        "(define (area width height)\n  (* width height))\n(define size 3)\n,env\n,describe area\n,describe size\n,describe (list 1 2)\n,nonsense\n",
+    );
+    assert!(output.contains("size = 3"), "{}", output);
+    assert!(
+        output.contains("area: procedure (area width height)"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("(define (area width height) (* width height))"),
+        "{}",
+        output
+    );
+    assert!(output.contains("size: number"), "{}", output);
+    assert!(output.contains("(list 1 2): list"), "{}", output);
+    assert!(output.contains("Unknown command ,nonsense"), "{}", output);
+}
+
+#[test]
+fn a_saved_session_loads_back() {
+    let file = env::temp_dir().join(format!("rs_lisp_repl_{}_saved.scm", std::process::id()));
+    let file = file.display().to_string();
+    session(
+        "save",
+        &[],
+        &format!(
+            "(define (twice x) (* 2 x))\n(define n (twice 4))\n,save {}\n",
+            file
+        ),
+    );
+    let output = session(
+        "load",
+        &[],
+        &format!(",load {}\n,clear\nn\n,load {}\n(twice n)\n", file, file),
+    );
+    let _ = fs::remove_file(&file);
+    assert!(output.contains("Cleared the session"), "{}", output);
+    assert!(output.contains("not defined"), "{}", output);
+    assert!(output.contains("=>  16"), "{}", output);
+}
+
+#[test]
+fn tracing_shows_calls() {
+    let output = session(
+        "trace",
+        &["--vm"],
+        "(define (f x) x)\n,trace\n(f 1)\n,trace off\n(f 2)\n,time (f 3)\n",
+    );
+    assert!(output.contains("Tracing is on"), "{}", output);
+    assert!(output.contains("Tracing is off"), "{}", output);
+    assert!(output.contains("Elapsed: "), "{}", output);
+}
+
+#[test]
+fn quitting_gives_the_exit_code() {
+    let (output, code) =
+        session_with_status("quit", &[], "(define a 1)\n,quit 4\n(display \"after\")\n");
+    assert_eq!(code, Some(4));
+    assert!(!output.contains("after"), "{}", output);
+    let (_, code) = session_with_status("exit", &[], "(exit 7)\n");
+    assert_eq!(code, Some(7));
+    let (_, code) = session_with_status("eof", &[], "(+ 1 2)\n");
+    assert_eq!(code, Some(0));
+}
+
+#[test]
+fn history_goes_in_the_data_directory() {
+    let dir = session_dir("history");
+    run_in(&dir, &[], "(+ 1 2)\n");
+    let history = fs::read_to_string(dir.join(".local/share/rs_lisp/history.txt")).unwrap();
+    assert!(history.contains("(+ 1 2)"), "{}", history);
+    assert!(!dir.join("history.txt").exists());
+
+    // The flag puts it somewhere else
+    run_in(&dir, &["--history", "elsewhere.txt"], "(* 2 3)\n");
+    let history = fs::read_to_string(dir.join("elsewhere.txt")).unwrap();
+    assert!(history.contains("(* 2 3)"), "{}", history);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn settings_come_from_the_config_file() {
+    let dir = session_dir("config");
+    let config_dir = dir.join(".config/rs_lisp");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(
+        config_dir.join("config.scm"),
+        "; REPL settings\n((history-file \"~/kept.txt\") (max-history 2) (color #f))",
+    )
+    .unwrap();
+    fs::write(
+        config_dir.join("init.scm"),
+        "(define (greet name) (list (quote hi) name))",
+    )
+    .unwrap();
+    let (output, _) = run_in(&dir, &[], "(greet \"there\")\n1\n2\n3\n");
+    assert!(output.contains("=>  (hi there)"), "{}", output);
+    let history = fs::read_to_string(dir.join("kept.txt")).unwrap();
+    assert!(!history.contains("greet"), "{}", history);
+    assert!(history.contains('3'), "{}", history);
+
+    // A config file named on the command line, and an init file that fails
+    fs::write(
+        dir.join("other.scm"),
+        "((init-file \"broken.scm\") (history-file \"other.txt\"))",
+    )
+    .unwrap();
+    fs::write(dir.join("broken.scm"), "(undefined-function)").unwrap();
+    let (output, _) = run_in(&dir, &["--config", "other.scm"], "(+ 2 2)\n");
+    assert!(output.contains("In init file broken.scm"), "{}", output);
+    assert!(output.contains("=>  4"), "{}", output);
+
+    // The init file next to a config file named on the command line
+    fs::create_dir_all(dir.join("elsewhere")).unwrap();
+    fs::write(dir.join("elsewhere/config.scm"), "((color #f))").unwrap();
+    fs::write(dir.join("elsewhere/init.scm"), "(define where 'elsewhere)").unwrap();
+    let (output, _) = run_in(&dir, &["--config", "elsewhere/config.scm"], "where\n");
+    assert!(output.contains("=>  elsewhere"), "{}", output);
+
+    fs::write(dir.join("bad.scm"), "((max-history \"lots\"))").unwrap();
+    let (_, code) = run_in(&dir, &["--config", "bad.scm"], "");
+    assert_eq!(code, Some(1));
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,57 @@
+use std::env;
+use std::process::Command;
+
+// Each sample program is run on the tree walker and on the VM, and both have
+// to succeed with the same output
+fn run_both(name: &str) -> String {
+    let path = format!("{}/sample_code/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let mut outputs = Vec::new();
+    for backend in [&[][..], &["--vm"][..]] {
+        let mut args = backend.to_vec();
+        args.extend(["--print-results", &path]);
+        let output = Command::new(env!("CARGO_BIN_EXE_rs_lisp"))
+            .args(&args)
+            .env("HOME", env::temp_dir())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{} {:?}: {}",
+            name,
+            backend,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        outputs.push(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    assert_eq!(outputs[0], outputs[1], "the backends disagree on {}", name);
+    outputs.remove(0)
+}
+
+#[test]
+fn fib() {
+    assert_eq!(run_both("fib.scm"), "(fib n)\n75025\n");
+}
+
+#[test]
+fn recurse() {
+    assert_eq!(run_both("recurse.scm"), "(recurse n r)\n15\n");
+}
+
+#[test]
+fn test_while() {
+    assert_eq!(
+        run_both("test_while.scm"),
+        "counter\n(test-while x)\n1543210\n"
+    );
+}
+
+#[test]
+fn output() {
+    assert_eq!(run_both("output.scm"), "(test a b c)\n9595\n");
+}
+
+#[test]
+fn mandelbrot() {
+    let output = run_both("mandelbrot.scm");
+    assert!(output.contains('*'), "{}", output);
+}
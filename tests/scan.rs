@@ -0,0 +1,63 @@
+use rs_lisp::{scan, Lexeme};
+
+// The lexemes of some text, each with the text it covers
+fn lexemes(text: &str) -> Vec<(Lexeme, &str)> {
+    scan(text)
+        .into_iter()
+        .map(|(lexeme, range)| (lexeme, &text[range]))
+        .collect()
+}
+
+#[test]
+fn every_character_is_in_a_lexeme() {
+    assert_eq!(
+        lexemes("(car '#(1 \"a\\\"b\")) ; done"),
+        vec![
+            (Lexeme::Open, "("),
+            (Lexeme::Word, "car"),
+            (Lexeme::Space, " "),
+            (Lexeme::Quote, "'"),
+            (Lexeme::VectorStart, "#("),
+            (Lexeme::Word, "1"),
+            (Lexeme::Space, " "),
+            (Lexeme::Str { finished: true }, "\"a\\\"b\""),
+            (Lexeme::Close, ")"),
+            (Lexeme::Close, ")"),
+            (Lexeme::Space, " "),
+            (Lexeme::Comment, "; done"),
+        ]
+    );
+}
+
+#[test]
+fn unfinished_strings_and_comments_run_to_the_end() {
+    assert_eq!(
+        lexemes("\"open ( \\\""),
+        vec![(Lexeme::Str { finished: false }, "\"open ( \\\"")]
+    );
+    assert_eq!(
+        lexemes("; a ( in a comment\nx"),
+        vec![
+            (Lexeme::Comment, "; a ( in a comment"),
+            (Lexeme::Space, "\n"),
+            (Lexeme::Word, "x"),
+        ]
+    );
+}
+
+#[test]
+fn ranges_are_byte_offsets() {
+    let text = "(λ \"é\" $)";
+    assert_eq!(
+        lexemes(text),
+        vec![
+            (Lexeme::Open, "("),
+            (Lexeme::Word, "λ"),
+            (Lexeme::Space, " "),
+            (Lexeme::Str { finished: true }, "\"é\""),
+            (Lexeme::Space, " "),
+            (Lexeme::Unknown, "$"),
+            (Lexeme::Close, ")"),
+        ]
+    );
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::eval_both;
+use rs_lisp::{Backend, Error, Interpreter};
+
+// Scoping is lexical: a function sees the variables where it was defined, not
+// the ones of whoever called it. (Before lexical addressing lookups went
+// through the callers, and this gave 5.)
+#[test]
+fn functions_dont_see_their_callers_variables() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(backend);
+        match interp.eval_str("(define (f y) (h)) (define (h) y) (f 5)") {
+            Err(Error::Eval(message)) => assert_eq!(message, "Symbol y not defined."),
+            _ => panic!("{:?}: expected y to be undefined in h", backend),
+        }
+    }
+    assert_eq!(
+        eval_both("(define y 1) (define (h) y) (define (f y) (h)) (f 5)"),
+        "1"
+    );
+}
+
+#[test]
+fn lambdas_close_over_where_they_were_made() {
+    assert_eq!(
+        eval_both(
+            "(define (make-adder n) (lambda (x) (+ x n))) \
+             (define add2 (make-adder 2)) \
+             (define n 100) \
+             (list (add2 1) ((make-adder 10) 1))"
+        ),
+        "(3 11)"
+    );
+    assert_eq!(eval_both("((lambda (x y) (* x y)) 6 7)"), "42");
+}
+
+// Looking a variable up by its slot gives the same as looking it up by name,
+// which eval of a quoted symbol does
+#[test]
+fn slot_lookups_match_name_lookups() {
+    assert_eq!(
+        eval_both(
+            "(define (outer a) \
+               (begin \
+                 (define b (* a 2)) \
+                 ((lambda (c) \
+                    (list (list a b c) \
+                          (list (eval 'a (current-environment)) \
+                                (eval 'b (current-environment)) \
+                                (eval 'c (current-environment))))) \
+                  3))) \
+             (outer 1)"
+        ),
+        "((1 2 3) (1 2 3))"
+    );
+    // A parameter hides a global of the same name either way
+    assert_eq!(
+        eval_both(
+            "(define x 'global) \
+             (define (f x) (list x (eval 'x (current-environment)))) \
+             (list (f 'param) x)"
+        ),
+        "((param param) global)"
+    );
+}
+
+#[test]
+fn globals_can_be_defined_after_the_functions_using_them() {
+    assert_eq!(
+        eval_both(
+            "(define (g) later) \
+             (define later 7) \
+             (define before (g)) \
+             (define later 8) \
+             (list before (g) (eval 'later (interaction-environment)))"
+        ),
+        "(7 8 8)"
+    );
+    // Including ones set! from inside a function
+    assert_eq!(
+        eval_both(
+            "(define (bump) (set! total (+ total 1))) \
+             (define total 0) \
+             (bump) (bump) \
+             total"
+        ),
+        "2"
+    );
+}
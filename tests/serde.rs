@@ -0,0 +1,136 @@
+use rs_lisp::{from_str, to_sexpression, to_string, Error, Interpreter, SExpression};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Mode {
+    Fullscreen,
+    Windowed(u32, u32),
+    Scaled(f64),
+    Tiled { rows: u8, columns: u8 },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Window {
+    title: String,
+    size: (u32, u32),
+    mode: Mode,
+    border: Option<u8>,
+    tags: Vec<String>,
+    visible: bool,
+}
+
+fn window(mode: Mode) -> Window {
+    Window {
+        title: "main \"view\"".to_string(),
+        size: (640, 480),
+        mode,
+        border: None,
+        tags: vec!["a".to_string(), "b".to_string()],
+        visible: true,
+    }
+}
+
+#[test]
+fn structs_are_association_lists() {
+    let text = to_string(&window(Mode::Fullscreen)).unwrap();
+    assert_eq!(
+        text,
+        "((title \"main \\\"view\\\"\") (size (640 480)) (mode Fullscreen) (border #nil) (tags (\"a\" \"b\")) (visible #t))"
+    );
+}
+
+#[test]
+fn enums_are_tagged_lists() {
+    assert_eq!(
+        to_string(&Mode::Windowed(800, 600)).unwrap(),
+        "(Windowed 800 600)"
+    );
+    assert_eq!(to_string(&Mode::Scaled(1.5)).unwrap(), "(Scaled 1.5)");
+    assert_eq!(
+        to_string(&Mode::Tiled {
+            rows: 2,
+            columns: 3
+        })
+        .unwrap(),
+        "(Tiled (rows 2) (columns 3))"
+    );
+}
+
+#[test]
+fn values_read_back_the_same() {
+    for mode in [
+        Mode::Fullscreen,
+        Mode::Windowed(800, 600),
+        Mode::Scaled(0.25),
+        Mode::Tiled {
+            rows: 2,
+            columns: 3,
+        },
+    ] {
+        let original = window(mode);
+        let text = to_string(&original).unwrap();
+        let back: Window = from_str(&text).unwrap();
+        assert_eq!(back, original);
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert("list".to_string(), vec![1i64, -2]);
+    map.insert("snake_case".to_string(), vec![]);
+    let back: BTreeMap<String, Vec<i64>> = from_str(&to_string(&map).unwrap()).unwrap();
+    assert_eq!(back, map);
+}
+
+#[test]
+fn handwritten_text_is_read_too() {
+    let text = "; a config file
+        ((title \"notes\")
+         (visible #f)
+         (mode (Windowed 1024 768))
+         (tags ())
+         (border 2)
+         (size (10 20)))";
+    let window: Window = from_str(text).unwrap();
+    assert_eq!(window.mode, Mode::Windowed(1024, 768));
+    assert_eq!(window.border, Some(2));
+    assert!(!window.visible);
+}
+
+#[test]
+fn bad_data_is_an_error() {
+    assert!(matches!(
+        from_str::<Mode>("(Sideways 1)"),
+        Err(Error::Data(_))
+    ));
+    assert!(matches!(from_str::<u8>("300"), Err(Error::Data(_))));
+    assert!(matches!(
+        from_str::<Window>("((title \"x\"))"),
+        Err(Error::Data(_))
+    ));
+    assert!(matches!(
+        from_str::<Mode>("(Windowed 1"),
+        Err(Error::Parse(_))
+    ));
+    assert!(matches!(from_str::<u8>("1 2"), Err(Error::Parse(_))));
+}
+
+#[test]
+fn sexpressions_serialize_as_data() {
+    let mut interp = Interpreter::new();
+    let exp = interp
+        .eval_str("(list 'point \"label\" 3 #t (list 'car '+))")
+        .unwrap();
+    let text = to_string(&exp).unwrap();
+    assert_eq!(text, "(point \"label\" 3 #t (car +))");
+
+    // Symbols stay symbols on the way back, and the text is valid code
+    let back: SExpression = from_str(&text).unwrap();
+    assert_eq!(back.write(), text);
+    let quoted = interp.eval_str(&format!("(first '{})", text)).unwrap();
+    assert_eq!(quoted.print(), "point");
+
+    let procedure = interp.eval_str("car").unwrap();
+    let lambda = interp.eval_str("(lambda (x) x)").unwrap();
+    assert!(to_sexpression(&procedure).is_ok());
+    assert!(matches!(to_string(&lambda), Err(Error::Data(_))));
+}
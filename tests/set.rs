@@ -0,0 +1,80 @@
+mod common;
+
+use common::both_backends;
+use rs_lisp::Interpreter;
+
+fn eval(interp: &mut Interpreter, program: &str) -> String {
+    interp.eval_str(program).unwrap().print()
+}
+
+#[test]
+fn functions_can_set_globals() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str("(define hits 0) (define (bump) (set! hits (+ hits 1)))")
+            .unwrap();
+        interp.eval_str("(bump) (bump) (bump)").unwrap();
+        assert_eq!(eval(&mut interp, "hits"), "3");
+    }
+}
+
+#[test]
+fn closures_keep_their_own_counters() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str(
+                "(define (make-counter)
+                   (begin
+                     (define n 0)
+                     (lambda () (begin (set! n (+ n 1)) n))))
+                 (define a (make-counter))
+                 (define b (make-counter))",
+            )
+            .unwrap();
+        interp.eval_str("(a) (a)").unwrap();
+        assert_eq!(eval(&mut interp, "(a)"), "3");
+        assert_eq!(eval(&mut interp, "(b)"), "1");
+    }
+}
+
+#[test]
+fn accumulators_update_outer_variables_two_levels_out() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str(
+                "(define (make-accumulator total)
+                   (lambda (amount)
+                     (begin
+                       (define (add!) (set! total (+ total amount)))
+                       (add!)
+                       total)))
+                 (define acc (make-accumulator 100))",
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(acc 10)"), "110");
+        assert_eq!(eval(&mut interp, "(acc 5)"), "115");
+    }
+}
+
+#[test]
+fn loops_update_variables_of_the_enclosing_function() {
+    for mut interp in both_backends() {
+        interp
+            .eval_str(
+                "(define (sum-to k)
+                   (begin
+                     (define total 0)
+                     (define i 0)
+                     (while (< i k)
+                       (begin (set! i (+ i 1)) (set! total (+ total i))))
+                     total))",
+            )
+            .unwrap();
+        assert_eq!(eval(&mut interp, "(sum-to 10)"), "55");
+        // Setting something that was never defined is still an error
+        assert!(interp.eval_str("(set! never-defined 1)").is_err());
+        assert!(interp
+            .eval_str("(define (f) (set! also-undefined 1)) (f)")
+            .is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use rs_lisp::{parse, Backend, Cell, Interpreter, SExpression};
+
+fn symbol_number(text: &str) -> i32 {
+    match parse(text).unwrap().as_slice() {
+        [SExpression::Cell(Cell::Symbol(number, _))] => *number,
+        other => panic!("expected one symbol, got {:?}", other.len()),
+    }
+}
+
+#[test]
+fn a_name_is_interned_once() {
+    assert_eq!(symbol_number("apple"), symbol_number("apple"));
+    assert_ne!(symbol_number("apple"), symbol_number("apples"));
+}
+
+#[test]
+fn symbols_read_at_run_time_are_the_same_symbols() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::with_backend(backend);
+        let result = interp
+            .eval_str(
+                "(define hello 5) \
+                 (define t (make-hash-table eq?)) \
+                 (hash-table-set! t (read \"key\") 1) \
+                 (list (eq? 'abc (read \"abc\")) \
+                       (eq? 'abc 'abd) \
+                       (memq (read \"c\") '(a b c)) \
+                       (hash-table-ref t 'key) \
+                       (eval (read \"hello\") (interaction-environment)))",
+            )
+            .unwrap();
+        assert_eq!(result.print(), "(true false (c) 1 5)");
+    }
+}
+
+#[test]
+fn symbols_pass_between_interpreters() {
+    let mut first = Interpreter::new();
+    let mut second = Interpreter::with_backend(Backend::Vm);
+    let symbol = first.eval_str("'shared").unwrap();
+    second.set_global("from-first", symbol);
+    assert_eq!(
+        second.eval_str("(eq? from-first 'shared)").unwrap().print(),
+        "true"
+    );
+}